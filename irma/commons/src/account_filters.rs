@@ -1,8 +1,6 @@
 use anchor_lang::prelude::*;
-use std::collections::HashMap;
 use crate::anyhow;
 use crate::dlmm::accounts::*;
-use crate::conversions::*;
 
 /// Check if a position account matches the given wallet and pair
 /// For on-chain usage where we have direct access to account data
@@ -77,7 +75,7 @@ pub fn get_matching_positions(
     
     for account in position_accounts.iter() {
         if account.data.borrow().is_empty() {
-            return Err(anyhow!("Invalid position {} found in input list", account.key()))?;
+            Err(anyhow!("Invalid position {} found in input list", account.key()))?;
         }
         let discriminator = &account.data.borrow()[0..8];
         msg!("Discriminator: {:?}", discriminator);
@@ -53,11 +53,7 @@ pub fn get_bytemuck_account<T: bytemuck::Pod>(
     acct_infos: &[AccountInfo],
     pubkey: &Pubkey
 ) -> Option<T> {
-    let account_info = if let Some(acc) = acct_infos.iter().find(|acc| acc.key == pubkey) {
-        acc
-    } else {
-        return None;
-    };
+    let account_info = acct_infos.iter().find(|acc| acc.key == pubkey)?;
     
     let borrowed_data = account_info.data.borrow();
     if borrowed_data.len() < 8 + std::mem::size_of::<T>() {
@@ -73,7 +69,7 @@ pub fn get_bytemuck_account<T: bytemuck::Pod>(
 
 pub fn get_multiple_bytemuck_accounts<T: bytemuck::Pod>(
     acct_infos: &[AccountInfo],
-    pubkeys: &Vec<Pubkey>
+    pubkeys: &[Pubkey]
 ) -> Result<HashMap<Pubkey, Option<T>>> {
     let mut data = HashMap::new();
     for pubkey in pubkeys.iter() {
@@ -102,12 +98,13 @@ pub fn get_bytemuck_account_ref<'a, T: bytemuck::Pod>(
     // SAFETY: We need to use unsafe to get a stable reference to the account data
     // This bypasses RefCell's runtime borrowing and gives us direct access
     unsafe {
-        let data_ptr = account_info.data.as_ptr();
+        // data is a RefCell around the &mut [u8]; the bytes are behind that slice, not the cell
+        let data_ptr: *const u8 = (*account_info.data.as_ptr()).as_ptr();
         let data_slice = std::slice::from_raw_parts(data_ptr, data_len);
         let account_data_slice = &data_slice[8..8 + std::mem::size_of::<T>()];
         
         // Ensure alignment and create reference
-        if account_data_slice.as_ptr() as usize % std::mem::align_of::<T>() == 0 {
+        if account_data_slice.as_ptr().align_offset(std::mem::align_of::<T>()) == 0 {
             Some(&*(account_data_slice.as_ptr() as *const T))
         } else {
             // If not properly aligned, we can't safely create a reference
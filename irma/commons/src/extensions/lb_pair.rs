@@ -216,7 +216,7 @@ impl LbPairExtension for LbPair {
         .ok_or("Nonexistent bin").unwrap();
 
         require!(
-            next_active_bin_id >= MIN_BIN_ID && next_active_bin_id <= MAX_BIN_ID,
+            (MIN_BIN_ID..=MAX_BIN_ID).contains(&next_active_bin_id),
             CustomError::ActiveBinOutOfBounds
         );
 
@@ -285,30 +285,30 @@ impl LbPairExtension for LbPair {
             msg!("offset bitmap is ");
             if offset_bit_map.eq(&U1024::ZERO) {
                 msg!("    zero");
-                return Ok((min_bitmap_id.checked_sub(1).ok_or("overflow").unwrap(), false));
+                Ok((min_bitmap_id.checked_sub(1).ok_or("overflow").unwrap(), false))
             } else {
                 let next_bit = offset_bit_map.leading_zeros();
                 msg!("    non-zero, next_bit = {}", next_bit);
-                return Ok((
+                Ok((
                     start_array_index
                         .checked_sub(next_bit as i32)
                         .ok_or("overflow").unwrap(),
                     true,
-                ));
+                ))
             }
         } else {
             let offset_bit_map = bin_array_bitmap.shr(array_offset);
             if offset_bit_map.eq(&U1024::ZERO) {
-                return Ok((max_bitmap_id.checked_add(1).ok_or("overflow").unwrap(), false));
+                Ok((max_bitmap_id.checked_add(1).ok_or("overflow").unwrap(), false))
             } else {
                 let next_bit = offset_bit_map.trailing_zeros();
-                return Ok((
+                Ok((
                     start_array_index
                         .checked_add(next_bit as i32)
                         .ok_or("overflow").unwrap(),
                     true,
-                ));
-            };
+                ))
+            }
         }
     }
 }
@@ -224,8 +224,7 @@ pub fn quote_exact_in<'a>(
     const MAX_ITERATIONS: u64 = 70 * 512;
 
     while amount_left > 0 {
-        let mut active_bin_array_pubkey = Pubkey::default();
-        match get_bin_array_pubkeys_for_swap(
+        let active_bin_array_pubkey = match get_bin_array_pubkeys_for_swap(
             lb_pair_pubkey,
             &lb_pair,
             bitmap_extension,
@@ -233,14 +232,12 @@ pub fn quote_exact_in<'a>(
             1,
         )?
         .pop() {
-            Some(pubkey) => {
-                active_bin_array_pubkey = pubkey;
-            }
+            Some(pubkey) => pubkey,
             None => {
                 msg!("Failed to get bin array pubkey for swap");
                 break;
             }
-        }
+        };
 
         let mut active_bin_array = bin_arrays
             .get(&active_bin_array_pubkey)
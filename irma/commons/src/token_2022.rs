@@ -102,7 +102,7 @@ pub fn get_extra_account_metas_for_transfer_hook<'a>(
     if let Some(transfer_hook_program_id) = transfer_hook::get_program_id(&mint_state) {
         let mut transfer_ix =
             anchor_spl::token_2022::spl_token_2022::instruction::transfer_checked(
-                &mint_account.owner,
+                mint_account.owner,
                 &Pubkey::default(),
                 &mint,
                 &Pubkey::default(),
@@ -121,7 +121,7 @@ pub fn get_extra_account_metas_for_transfer_hook<'a>(
             remaining_accounts[1].clone(),
             remaining_accounts[2].clone(),
             0,
-            &[mint_account.clone()],
+            std::slice::from_ref(&mint_account),
         );
         // .map_err(|e: anyhow::Error| anyhow!(e))?;
 
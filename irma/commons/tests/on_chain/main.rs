@@ -27,7 +27,6 @@ pub use commons::quote::*;
 /// - `test_swap` - Tests for swap calculation logic
 /// - `test_swap_token2022` - Tests for Token 2022 specific logic
 /// - `utils` - Common utilities for on-chain testing
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -14,6 +14,12 @@ pub struct OnChainTestConfig {
     pub current_time: i64,
 }
 
+impl Default for OnChainTestConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OnChainTestConfig {
     /// Create a new on-chain test configuration for pure program logic testing
     pub fn new() -> Self {
@@ -367,7 +373,7 @@ mod tests {
         let balance = test_pair.get_token_balance(&test_pair.user_token_x)?;
         assert_eq!(balance, 1_000_000_000);
 
-        let user_token_x = test_pair.user_token_x.clone();
+        let user_token_x = test_pair.user_token_x;
         
         // Test setting balance
         test_pair.set_token_balance(&user_token_x, 500_000_000)?;
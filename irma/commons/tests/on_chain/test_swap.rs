@@ -276,13 +276,13 @@ fn create_mock_bin_arrays(lb_pair_key: Pubkey) -> HashMap<Pubkey, commons::dlmm:
     let mut bins = [Bin::default(); 70];
     
     // Add liquidity to all bins to ensure we have enough liquidity
-    for i in 0..70 {
-        bins[i] = Bin {
+    for bin in bins.iter_mut() {
+        *bin = Bin {
             amount_x: 1000000000,
             amount_y: 1000000000000,
             amount_x_in: 0,
             amount_y_in: 0,
-            price: 1000000,
+            price: 0, // derived from the bin id by get_or_store_bin_price
             liquidity_supply: 1000000000,
             reward_per_token_stored: [0; 2],
             fee_amount_x_per_token_stored: 0,
@@ -264,7 +264,7 @@ fn create_mock_lb_pair_token2022(
         }
     }
     
-    let lb_pair = LbPair {
+    LbPair {
         parameters: StaticParameters {
             base_factor: 5000,
             filter_period: 30,
@@ -322,9 +322,7 @@ fn create_mock_lb_pair_token2022(
         token_mint_x_program_flag: 0u8,
         token_mint_y_program_flag: 0u8,
         _reserved: [0u8; 22],
-    };
-
-    lb_pair
+    }
 }
 
 /// Helper function to create mock bin arrays for Token 2022
@@ -344,13 +342,13 @@ fn create_mock_bin_arrays_token2022(lb_pair_key: Pubkey) -> HashMap<Pubkey, BinA
         let mut bins = [Bin::default(); 70];
         
         // Add liquidity to this bin array with Token 2022 considerations
-        for i in 0..69 {
-            bins[i] = Bin {
+        for bin in bins.iter_mut().take(69) {
+            *bin = Bin {
                 amount_x: 2000000000, // Higher amounts to account for potential fees
                 amount_y: 2000000000000,
                 amount_x_in: 2100000000, // Simulate some transfer fee impact
                 amount_y_in: 2100000000000,
-                price: (1000000 + (index * 100000) + (i as i64 * 1000)) as u128,
+                price: 0, // derived from the bin id by get_or_store_bin_price
                 liquidity_supply: 2000000000,
                 reward_per_token_stored: [0; 2],
                 fee_amount_x_per_token_stored: 0,
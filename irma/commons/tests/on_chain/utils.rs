@@ -277,8 +277,8 @@ mod tests {
         );
         
         assert_eq!(*account_info.key, key);
-        assert_eq!(account_info.is_signer, true);
-        assert_eq!(account_info.is_writable, true);
+        assert!(account_info.is_signer);
+        assert!(account_info.is_writable);
         assert_eq!(*account_info.owner, owner);
         assert_eq!(account_info.data_len(), 100);
     }
@@ -0,0 +1,165 @@
+// programs/irma/src/aggregation.rs
+//
+// Multi-source aggregation of reserve USD rates.
+// Each reserve has a PriceAggregator PDA collecting USD rates from registered price sources: price
+// authorities relaying signed attestations (attestation.rs) and registered oracle feeds (oracle.rs).
+// Each source holds at most one submission; submissions older than the window are dropped. Once a
+// quorum of sources has submitted, rates deviating from the median by more than max_deviation_bps are
+// rejected, and if a quorum remains, the median of the rest is committed to the StateMap. No single
+// source can move a mint price on its own.
+
+use anchor_lang::prelude::*;
+use commons::dlmm::types::Rounding;
+
+use crate::attestation::MAX_PRICE_SOURCES;
+use crate::config::ProtocolConfig;
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice};
+use crate::history::{self, PriceHistory};
+use crate::pricing::{StateMap, BASIS_POINTS_MAX};
+use crate::{ConfigurePriceAggregator, InitPriceAggregator};
+
+/// Seed of the per-reserve PriceAggregator PDA, followed by the reserve mint.
+pub const PRICE_AGGREGATOR_SEED: &[u8] = b"price_aggregator";
+pub const DEFAULT_QUORUM: u8 = 3;
+/// Default lifetime of a submission, in seconds.
+pub const DEFAULT_AGGREGATION_WINDOW: i64 = 300;
+/// Default largest accepted deviation from the median (0.5%).
+pub const DEFAULT_MAX_DEVIATION_BPS: u16 = 50;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct PriceSubmission {
+    pub source: Pubkey, // price authority or feed account
+    pub usd_rate: FixedPrice,
+    pub timestamp: i64,
+}
+
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct PriceAggregator {
+    pub mint: Pubkey, // reserve mint
+    pub submissions: Vec<PriceSubmission>,
+    pub quorum: u8, // sources needed to commit a rate
+    pub window: i64, // seconds a submission stays valid
+    pub max_deviation_bps: u16, // submissions further than this from the median are rejected
+    pub bump: u8, // Bump seed for PDA
+}
+
+impl PriceAggregator {
+    pub const LEN: usize = 32 + 4 + MAX_PRICE_SOURCES * (32 + 16 + 8) + 1 + 8 + 2 + 1;
+
+    pub fn new(mint: Pubkey, bump: u8) -> Self {
+        PriceAggregator {
+            mint,
+            submissions: Vec::with_capacity(MAX_PRICE_SOURCES),
+            quorum: DEFAULT_QUORUM,
+            window: DEFAULT_AGGREGATION_WINDOW,
+            max_deviation_bps: DEFAULT_MAX_DEVIATION_BPS,
+            bump,
+        }
+    }
+
+    pub fn configure(&mut self, quorum: u8, window: i64, max_deviation_bps: u16) -> Result<()> {
+        require!(
+            quorum > 0 && quorum as usize <= MAX_PRICE_SOURCES,
+            CustomError::InvalidAggregatorConfig
+        );
+        require!(window > 0, CustomError::InvalidAggregatorConfig);
+        require!(max_deviation_bps as u128 <= BASIS_POINTS_MAX, CustomError::InvalidAggregatorConfig);
+        self.quorum = quorum;
+        self.window = window;
+        self.max_deviation_bps = max_deviation_bps;
+        self.submissions.clear();
+        Ok(())
+    }
+
+    /// Record a submission, replacing the source's earlier one, and aggregate.
+    /// Returns the rate to commit once a quorum agrees; the submissions are then cleared.
+    pub fn submit(&mut self, submission: PriceSubmission, now: i64) -> Result<Option<FixedPrice>> {
+        require!(submission.usd_rate > 0, CustomError::InvalidAmount);
+        let oldest = now.saturating_sub(self.window);
+        require!(submission.timestamp >= oldest, CustomError::StaleOraclePrice);
+        self.submissions.retain(|s| s.source != submission.source && s.timestamp >= oldest);
+        require!(self.submissions.len() < MAX_PRICE_SOURCES, CustomError::TooManyPriceAuthorities);
+        self.submissions.push(submission);
+
+        let aggregate = self.aggregate()?;
+        if aggregate.is_some() {
+            self.submissions.clear();
+        }
+        Ok(aggregate)
+    }
+
+    /// Median of the submissions within max_deviation_bps of the median of all submissions,
+    /// or None while fewer than quorum submissions are in agreement.
+    pub fn aggregate(&self) -> Result<Option<FixedPrice>> {
+        let quorum = self.quorum as usize;
+        if self.submissions.len() < quorum {
+            return Ok(None);
+        }
+        let rates: Vec<FixedPrice> = self.submissions.iter().map(|s| s.usd_rate).collect();
+        let center = median(&rates)?;
+        let max_deviation = fixed_point::mul_div(
+            center, self.max_deviation_bps as u128, BASIS_POINTS_MAX, Rounding::Down)?;
+        let accepted: Vec<FixedPrice> = rates.into_iter()
+            .filter(|rate| rate.abs_diff(center) <= max_deviation)
+            .collect();
+        if accepted.len() < quorum {
+            msg!("{} of {} submissions within {} bps of the median, need {}",
+                accepted.len(), self.submissions.len(), self.max_deviation_bps, quorum);
+            return Ok(None);
+        }
+        Ok(Some(median(&accepted)?))
+    }
+}
+
+/// Median of rates; with an even count, the mean of the two middle rates rounded up.
+pub fn median(rates: &[FixedPrice]) -> Result<FixedPrice> {
+    require!(!rates.is_empty(), CustomError::InvalidAmount);
+    let mut sorted = rates.to_vec();
+    sorted.sort_unstable();
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        return Ok(sorted[middle]);
+    }
+    let (low, high) = (sorted[middle - 1], sorted[middle]);
+    Ok(low + (high - low).div_ceil(2))
+}
+
+/// Feed a submission for quote_token into aggregator and commit the aggregate, if any, to state_map,
+/// recording it in the reserve's price history.
+#[allow(clippy::too_many_arguments)]
+pub fn submit_rate(
+    aggregator: &mut PriceAggregator, state_map: &mut StateMap, price_history: &mut PriceHistory,
+    config: &ProtocolConfig, index: FixedPrice, quote_token: &str, submission: PriceSubmission, now: i64,
+) -> Result<()> {
+    let stablecoin = state_map.get_stablecoin(quote_token)?;
+    require_keys_eq!(stablecoin.mint_address, aggregator.mint, CustomError::InvalidQuoteToken);
+    if let Some(rate) = aggregator.submit(submission, now)? {
+        state_map.set_usd_rate(config, index, quote_token, rate, now)?;
+        history::record(price_history, state_map, now)?;
+        msg!("USD rate for {} committed at {}", quote_token, rate);
+    }
+    Ok(())
+}
+
+/// Create the price aggregator of a listed reserve with the default quorum, window and deviation.
+pub fn init_price_aggregator(ctx: Context<InitPriceAggregator>) -> Result<()> {
+    let mint = ctx.accounts.reserve_mint.key();
+    require!(
+        ctx.accounts.state.reserves.iter().any(|r| r.mint_address == mint),
+        CustomError::ReserveNotFound
+    );
+    *ctx.accounts.price_aggregator = PriceAggregator::new(mint, ctx.bumps.price_aggregator);
+    Ok(())
+}
+
+/// Change the quorum, window and deviation of a price aggregator; pending submissions are discarded.
+pub fn configure_price_aggregator(
+    ctx: Context<ConfigurePriceAggregator>, quorum: u8, window: i64, max_deviation_bps: u16
+) -> Result<()> {
+    ctx.accounts.price_aggregator.configure(quorum, window, max_deviation_bps)?;
+    msg!("Price aggregator for {} needs {} sources within {} bps, window {}s",
+        ctx.accounts.price_aggregator.mint, quorum, max_deviation_bps, window);
+    Ok(())
+}
@@ -0,0 +1,464 @@
+// programs/irma/src/allocation.rs
+//
+// Redemption allocation.
+// A redemption always pays out of the reserve the user redeems from (the subject), but the redeemed IRMA
+// may come off the circulation of other reserves: taking it from a reserve whose mint price is far above
+// its redemption price raises that redemption price and narrows the spread. A RedemptionAllocator decides
+// the split. The admin picks one through the AllocationStrategy stored in the StateMap, and
+// StateMap::distribute checks every allocation against the same invariants before applying it:
+// - the reductions add up to exactly the redeemed IRMA,
+// - no reserve loses more circulation than it has,
+// - only the subject and reserves open for redemption are reduced.
+// The subject's backing goes down by its redemption payout whatever the allocation.
+// Spreads are measured from the mint price, unless the redemption comes with the reserves' DLMM pools
+// (see market_prices): a reserve whose IRMA trades below the mint price on its pool has less demand to
+// mint with it, so its spread is measured from the market price instead. The market only steers which
+// circulation is reduced, never the redeemer's payout.
+
+use anchor_lang::prelude::*;
+use commons::dlmm::accounts::LbPair;
+use commons::dlmm::types::Rounding;
+use commons::fetch_lb_pair_state;
+use commons::math::u64x64_math::{self, SCALE_OFFSET};
+use commons::{BASIS_POINT_MAX, MAX_BIN_ID, MIN_BIN_ID};
+
+use crate::config::ProtocolConfig;
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice, PRICE_ONE};
+use crate::pricing::{StableState, IRMA};
+use crate::ManageState;
+
+/// Most halvings of the level range WaterFill makes. Spreads are below MAX_MINT_PRICE (< 2^78 in Q64.64),
+/// so the level found is within 2^14 ulps (under 1e-15) of the exact one.
+pub const WATER_FILL_STEPS: u32 = 64;
+
+/// Splits the IRMA of a redemption into circulation reductions per reserve.
+pub trait RedemptionAllocator {
+    /// Circulation reduction (IRMA base units) of each reserve, in the order of reserves, for irma_amount
+    /// redeemed from reserves[subject]. reserves are as they were before the redemption; market_prices
+    /// holds their market prices where known (see market_prices), and may be empty.
+    fn allocate(
+        &self, reserves: &[StableState], subject: usize, irma_amount: u64, config: &ProtocolConfig,
+        market_prices: &[Option<FixedPrice>],
+    ) -> Result<Vec<u128>>;
+}
+
+/// The admin-selectable allocators.
+/// MaxSpread: the original heuristic; see MaxSpread.
+/// SelfOnly: the subject's circulation takes the whole reduction, so no redemption price moves.
+/// ProportionalToSpread: reserves with a positive spread share the reduction in proportion to it.
+/// WaterFill: reserves with an above-average spread are brought down to a common spread; see WaterFill.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AllocationStrategy {
+    #[default]
+    MaxSpread,
+    SelfOnly,
+    ProportionalToSpread,
+    WaterFill,
+}
+
+impl AllocationStrategy {
+    pub fn allocator(&self) -> &'static dyn RedemptionAllocator {
+        match self {
+            AllocationStrategy::MaxSpread => &MaxSpread,
+            AllocationStrategy::SelfOnly => &SelfOnly,
+            AllocationStrategy::ProportionalToSpread => &ProportionalToSpread,
+            AllocationStrategy::WaterFill => &WaterFill,
+        }
+    }
+}
+
+/// Market price of reserve i, if market_prices has one.
+fn market_price(market_prices: &[Option<FixedPrice>], i: usize) -> Option<FixedPrice> {
+    market_prices.get(i).copied().flatten()
+}
+
+/// Price a reserve's spread is measured from: the mint price, or the market price if that is lower.
+fn target_price(reserve: &StableState, market_price: Option<FixedPrice>) -> FixedPrice {
+    match market_price {
+        Some(price) if price < reserve.mint_price => price,
+        _ => reserve.mint_price,
+    }
+}
+
+/// Spread (target price - redemption price, signed Q64.64) of a priced reserve open for redemption; 0 for any other.
+fn spread(reserve: &StableState, market_price: Option<FixedPrice>) -> Result<i128> {
+    if reserve.mint_price == 0 || reserve.backing_decimals == 0 || !reserve.status.can_redeem() {
+        return Ok(0);
+    }
+    fixed_point::signed_diff(target_price(reserve, market_price), reserve.redemption_price()?)
+}
+
+/// Price (Q64.64, token Y base units per token X base unit) at bin_id for a DLMM pool with bin_step.
+/// Unlike price_math::get_price_from_id this returns an error instead of panicking when the price
+/// does not fit in Q64.64.
+pub fn bin_price(bin_id: i32, bin_step: u16) -> Result<FixedPrice> {
+    let bps: u128 = (u128::from(bin_step) << SCALE_OFFSET) / BASIS_POINT_MAX as u128;
+    let base: u128 = PRICE_ONE.checked_add(bps).ok_or(CustomError::MathError)?;
+    u64x64_math::pow(base, bin_id).ok_or(error!(CustomError::MathError))
+}
+
+/// Id of the highest bin of lb_pair, within its bin range, whose price (see bin_price) is at most price,
+/// or the lowest bin of the range if price is below all of them.
+pub fn price_bin(lb_pair: &LbPair, price: FixedPrice) -> Result<i32> {
+    let mut low: i32 = lb_pair.parameters.min_bin_id.max(MIN_BIN_ID);
+    let mut high: i32 = lb_pair.parameters.max_bin_id.min(MAX_BIN_ID);
+    require!(low <= high, CustomError::InvalidLbPairState);
+    let mut bin: i32 = low;
+    while low <= high {
+        let mid: i32 = low + (high - low) / 2;
+        match bin_price(mid, lb_pair.bin_step) {
+            Ok(mid_price) if mid_price <= price => {
+                bin = mid;
+                low = mid + 1;
+            }
+            // a price too large for Q64.64 is above any price
+            _ => high = mid - 1,
+        }
+    }
+    Ok(bin)
+}
+
+/// Price of IRMA in whole reserve tokens (Q64.64, like the mint price) at the active bin of lb_pair,
+/// the DLMM pool between IRMA and the reserve stablecoin. The active bin must lie within the pair's
+/// bin range.
+pub fn pair_price(reserve: &StableState, lb_pair: &LbPair) -> Result<FixedPrice> {
+    let active_id: i32 = lb_pair.active_id;
+    require!((MIN_BIN_ID..=MAX_BIN_ID).contains(&active_id), CustomError::InvalidLbPairState);
+    require!((lb_pair.parameters.min_bin_id..=lb_pair.parameters.max_bin_id).contains(&active_id),
+        CustomError::InvalidLbPairState);
+    // DLMM prices are token Y base units per token X base unit
+    let price: FixedPrice = bin_price(active_id, lb_pair.bin_step)?;
+    let price: FixedPrice = if lb_pair.token_y_mint == reserve.mint_address {
+        price
+    } else if lb_pair.token_x_mint == reserve.mint_address {
+        fixed_point::div_price(PRICE_ONE, price, Rounding::Down)?
+    } else {
+        return Err(error!(CustomError::InvalidLbPairState));
+    };
+    fixed_point::rescale(price, reserve.backing_decimals as u32, IRMA.backing_decimals as u32, Rounding::Down)
+}
+
+/// The LbPair of each reserve's pool (StableState::pool_id) among remaining_accounts, in the order of
+/// reserves, or None where it is not passed.
+pub fn pool_states<'a>(
+    reserves: &[StableState], remaining_accounts: &'a [AccountInfo<'a>]
+) -> Result<Vec<Option<&'a LbPair>>> {
+    reserves.iter().map(|reserve| {
+        if reserve.pool_id == Pubkey::default() || !remaining_accounts.iter().any(|a| *a.key == reserve.pool_id) {
+            return Ok(None);
+        }
+        Ok(Some(fetch_lb_pair_state(remaining_accounts, &reserve.pool_id)?))
+    }).collect()
+}
+
+/// Market prices of reserves, in their order, at the active bins of pools (see pool_states).
+/// pools must hold the LbPair of every redeemable reserve with a pool, or of none: with only some of
+/// them, the allocator would weigh the rest as if IRMA traded at their mint price.
+pub fn market_prices(reserves: &[StableState], pools: &[Option<&LbPair>]) -> Result<Vec<Option<FixedPrice>>> {
+    require!(pools.len() == reserves.len(), CustomError::IncompleteLbPairs);
+    let configured = reserves.iter().zip(pools)
+        .filter(|(reserve, _)| reserve.pool_id != Pubkey::default() && reserve.status.can_redeem());
+    let passed: usize = configured.clone().filter(|(_, lb_pair)| lb_pair.is_some()).count();
+    require!(passed == 0 || passed == configured.count(), CustomError::IncompleteLbPairs);
+    reserves.iter().zip(pools).map(|(reserve, lb_pair)| match lb_pair {
+        Some(lb_pair) => Ok(Some(pair_price(reserve, lb_pair)?)),
+        None => Ok(None),
+    }).collect()
+}
+
+/// Reductions with all of irma_amount on the subject.
+fn self_only(reserves: &[StableState], subject: usize, irma_amount: u64) -> Vec<u128> {
+    let mut reductions = vec![0u128; reserves.len()];
+    reductions[subject] = irma_amount as u128;
+    reductions
+}
+
+/// Check the invariants every allocation must meet; see the module comment.
+pub fn check_allocation(reserves: &[StableState], subject: usize, irma_amount: u64, reductions: &[u128]) -> Result<()> {
+    require!(reductions.len() == reserves.len(), CustomError::InvalidAllocation);
+    let mut total: u128 = 0;
+    for (i, (reserve, reduction)) in reserves.iter().zip(reductions).enumerate() {
+        if *reduction == 0 {
+            continue;
+        }
+        require!(i == subject || reserve.status.can_redeem(), CustomError::InvalidAllocation);
+        require!(*reduction <= reserve.irma_in_circulation, CustomError::InsufficientCirculation);
+        total = total.checked_add(*reduction).ok_or(CustomError::MathError)?;
+    }
+    require!(total == irma_amount as u128, CustomError::InvalidAllocation);
+    Ok(())
+}
+
+/// Takes the reduction from the subject only.
+pub struct SelfOnly;
+
+impl RedemptionAllocator for SelfOnly {
+    fn allocate(
+        &self, reserves: &[StableState], subject: usize, irma_amount: u64, _config: &ProtocolConfig,
+        _market_prices: &[Option<FixedPrice>],
+    ) -> Result<Vec<u128>> {
+        Ok(self_only(reserves, subject, irma_amount))
+    }
+}
+
+/// The original heuristic of distribute. It picks the reserve whose spread is widest, by more than
+/// config.min_price_diff above the average, as the other target. If the average spread is negligible
+/// or negative, the subject takes the whole reduction. Otherwise the reduction goes to the subject if
+/// taking it from the other target would not narrow the other target's spread, to the other target if
+/// that leaves a narrower spread than the subject would have, and else is split linearly between them.
+pub struct MaxSpread;
+
+impl RedemptionAllocator for MaxSpread {
+    fn allocate(
+        &self, reserves: &[StableState], subject: usize, irma_amount: u64, config: &ProtocolConfig,
+        market_prices: &[Option<FixedPrice>],
+    ) -> Result<Vec<u128>> {
+        // a single reserve takes the whole reduction
+        if reserves.len() == 1 {
+            return Ok(self_only(reserves, subject, irma_amount));
+        }
+
+        // Spreads (target price - redemption price) are signed Q64.64 values.
+        let mut count: u8 = 0;
+        let mut total_diff: i128 = 0;
+        let mut price_differences: Vec<i128> = Vec::with_capacity(reserves.len());
+        for (i, reserve) in reserves.iter().enumerate() {
+            let x: i128 = spread(reserve, market_price(market_prices, i))?;
+            if reserve.mint_price != 0 && reserve.backing_decimals != 0 && reserve.status.can_redeem() {
+                count += 1;
+                total_diff = total_diff.checked_add(x).ok_or(CustomError::MathError)?;
+            }
+            price_differences.push(x);
+        }
+        require!(count > 0, CustomError::InvalidBacking);
+        let average_diff: i128 = total_diff / count as i128;
+        let min_diff: i128 = i128::try_from(config.min_price_diff).map_err(|_| error!(CustomError::MathError))?;
+
+        // if all spreads are close to the average or inflation-adjusted prices are below the redemption
+        // prices, the subject takes the reduction. If the mint price is higher than the redemption price,
+        // this keeps price differences the same (it's minting that adjusts redemption price). If the mint
+        // price is lower (deflation), the payout was capped at the mint price, so the redemption price rises
+        // and the backing is preserved.
+        if (average_diff.abs() < min_diff) || (average_diff < 0) {
+            return Ok(self_only(reserves, subject, irma_amount));
+        }
+
+        // the other target is the reserve with the greatest spread, if it stands out from the average
+        let mut max_price_diff: i128 = average_diff;
+        let mut other: usize = subject;
+        for (i, price_diff) in price_differences.iter().enumerate() {
+            if (*price_diff - max_price_diff).abs() > min_diff && *price_diff > max_price_diff {
+                max_price_diff = *price_diff;
+                other = i;
+            }
+        }
+        if other == subject {
+            return Ok(self_only(reserves, subject, irma_amount));
+        }
+
+        let stablecoin = &reserves[subject];
+        let other_stablecoin = &reserves[other];
+        let other_circulation: u128 = other_stablecoin.irma_in_circulation;
+        let other_price: FixedPrice = target_price(other_stablecoin, market_price(market_prices, other));
+        let other_price_diff: i128 = price_differences[other];
+
+        // spread of the subject if the redeemed IRMA is valued at the mint price
+        let mut post_stablecoin = stablecoin.clone();
+        post_stablecoin.backing_reserves = stablecoin.backing_reserves.saturating_sub(
+            fixed_point::mul_price(irma_amount as u128, stablecoin.raw_mint_price(Rounding::Down)?, Rounding::Down)?);
+        let post_price_diff: i128 = fixed_point::signed_diff(
+            target_price(stablecoin, market_price(market_prices, subject)), post_stablecoin.redemption_price()?)?;
+
+        // spread of the other reserve if the redeemed IRMA is taken from its circulation
+        let mut post_other_stablecoin = other_stablecoin.clone();
+        post_other_stablecoin.irma_in_circulation = other_circulation.saturating_sub(irma_amount as u128);
+        let post_other_red_price: FixedPrice = if post_other_stablecoin.irma_in_circulation == 0 {
+            0
+        } else {
+            post_other_stablecoin.redemption_price()?
+        };
+        let post_other_price_diff: i128 = fixed_point::signed_diff(other_price, post_other_red_price)?;
+
+        let mut reductions = vec![0u128; reserves.len()];
+        if other_price_diff < post_other_price_diff {
+            // taking the IRMA from the other reserve would not improve its redemption price
+            reductions[subject] = irma_amount as u128;
+        } else if post_other_price_diff < post_price_diff {
+            // taking the IRMA from the other reserve leaves it with a narrower spread than the subject
+            require!(irma_amount as u128 <= other_circulation, CustomError::InsufficientCirculation);
+            reductions[other] = irma_amount as u128;
+        } else {
+            // split linearly between the two:
+            // adjustment = irma_amount * (other_price_diff - post_price_diff) / (other_price_diff + post_price_diff)
+            let numerator: i128 = other_price_diff.checked_sub(post_price_diff).ok_or(CustomError::MathError)?;
+            let denominator: i128 = other_price_diff.checked_add(post_price_diff).ok_or(CustomError::MathError)?;
+            // the ratio must be strictly positive
+            require!(numerator != 0 && denominator != 0, CustomError::InvalidAmount);
+            require!((numerator > 0) == (denominator > 0), CustomError::InvalidAmount);
+            let adjustment_amount: u128 = fixed_point::mul_div(
+                irma_amount as u128,
+                numerator.unsigned_abs(),
+                denominator.unsigned_abs(),
+                Rounding::Up,
+            )?;
+            require!(adjustment_amount <= irma_amount as u128, CustomError::InvalidAmount);
+            // the subject takes the rest, so the reductions add up to irma_amount
+            reductions[other] = adjustment_amount;
+            reductions[subject] = irma_amount as u128 - adjustment_amount;
+        }
+        Ok(reductions)
+    }
+}
+
+/// Shares the reduction among reserves with a positive spread in proportion to their spread, rounding
+/// down; each share is capped at the reserve's circulation and the subject takes whatever is left.
+pub struct ProportionalToSpread;
+
+impl RedemptionAllocator for ProportionalToSpread {
+    fn allocate(
+        &self, reserves: &[StableState], subject: usize, irma_amount: u64, _config: &ProtocolConfig,
+        market_prices: &[Option<FixedPrice>],
+    ) -> Result<Vec<u128>> {
+        let spreads: Vec<u128> = reserves.iter().enumerate()
+            .map(|(i, r)| Ok(spread(r, market_price(market_prices, i))?.max(0) as u128))
+            .collect::<Result<_>>()?;
+        let total: u128 = spreads.iter()
+            .try_fold(0u128, |total, s| total.checked_add(*s))
+            .ok_or(CustomError::MathError)?;
+        if total == 0 {
+            return Ok(self_only(reserves, subject, irma_amount));
+        }
+
+        let mut reductions = vec![0u128; reserves.len()];
+        for (i, (reserve, spread)) in reserves.iter().zip(spreads).enumerate() {
+            if i != subject {
+                reductions[i] = fixed_point::mul_div(irma_amount as u128, spread, total, Rounding::Down)?
+                    .min(reserve.irma_in_circulation);
+            }
+        }
+        let others: u128 = reductions.iter().sum();
+        reductions[subject] = (irma_amount as u128).checked_sub(others).ok_or(CustomError::MathError)?;
+        Ok(reductions)
+    }
+}
+
+/// Spreads the reduction over every reserve whose spread is above the average, so that all of them end
+/// at the same spread (the level) instead of one reserve moving per redemption. The subject counts with
+/// its backing after the payout. The level is found by bisection between the average spread and the
+/// widest one: each reserve above the level gives up the circulation, rounded down, that brings its
+/// redemption price up to mint price - level. If even the average level does not use the whole
+/// redemption, that is the level. The subject takes whatever is left: the rest of the redemption, or
+/// else the rounding of the level search, at most a few base units per reserve.
+/// As with MaxSpread, the subject takes everything if the average spread is negligible or negative.
+pub struct WaterFill;
+
+/// A reserve WaterFill can reduce: target price, backing in IRMA decimals (rounded up) and circulation.
+struct Vessel {
+    index: usize,
+    price: FixedPrice,
+    backing: u128,
+    circulation: u128,
+}
+
+impl Vessel {
+    /// Circulation reduction (rounded down) that brings the spread down to level; 0 if it is not above it.
+    fn reduction_at(&self, level: FixedPrice) -> Result<u128> {
+        if level >= self.price {
+            return Ok(0);
+        }
+        let circulation = fixed_point::div_price(self.backing, self.price - level, Rounding::Up)?;
+        Ok(self.circulation.saturating_sub(circulation))
+    }
+}
+
+fn total_reduction(vessels: &[Vessel], level: FixedPrice) -> Result<u128> {
+    vessels.iter().try_fold(0u128, |total, vessel| {
+        total.checked_add(vessel.reduction_at(level)?).ok_or(error!(CustomError::MathError))
+    })
+}
+
+impl RedemptionAllocator for WaterFill {
+    fn allocate(
+        &self, reserves: &[StableState], subject: usize, irma_amount: u64, config: &ProtocolConfig,
+        market_prices: &[Option<FixedPrice>],
+    ) -> Result<Vec<u128>> {
+        if reserves.len() == 1 || irma_amount == 0 {
+            return Ok(self_only(reserves, subject, irma_amount));
+        }
+
+        // the reserves as they are once the payout has left the subject
+        let payout: u128 = reserves[subject].redemption_payout(irma_amount as u128)?;
+        let mut after: Vec<StableState> = reserves.to_vec();
+        after[subject].backing_reserves = after[subject].backing_reserves
+            .checked_sub(payout)
+            .ok_or(CustomError::InsufficientReserve)?;
+
+        let mut count: i128 = 0;
+        let mut total_diff: i128 = 0;
+        let mut spreads: Vec<i128> = Vec::with_capacity(after.len());
+        for (i, reserve) in after.iter().enumerate() {
+            let x: i128 = spread(reserve, market_price(market_prices, i))?;
+            if reserve.mint_price != 0 && reserve.backing_decimals != 0 && reserve.status.can_redeem() {
+                count += 1;
+                total_diff = total_diff.checked_add(x).ok_or(CustomError::MathError)?;
+            }
+            spreads.push(x);
+        }
+        require!(count > 0, CustomError::InvalidBacking);
+        let average_diff: i128 = total_diff / count;
+        let min_diff: i128 = i128::try_from(config.min_price_diff).map_err(|_| error!(CustomError::MathError))?;
+        if average_diff < min_diff || average_diff <= 0 {
+            return Ok(self_only(reserves, subject, irma_amount));
+        }
+
+        let mut vessels: Vec<Vessel> = Vec::new();
+        let mut widest: i128 = average_diff;
+        for (i, (reserve, spread)) in after.iter().zip(spreads).enumerate() {
+            if spread > average_diff {
+                vessels.push(Vessel {
+                    index: i,
+                    price: target_price(reserve, market_price(market_prices, i)),
+                    backing: fixed_point::rescale(
+                        reserve.backing_reserves, reserve.backing_decimals as u32, IRMA.backing_decimals as u32, Rounding::Up)?,
+                    circulation: reserve.irma_in_circulation,
+                });
+                widest = widest.max(spread);
+            }
+        }
+
+        // total_reduction falls as the level rises; find the lowest level whose total fits the redemption
+        let mut low: FixedPrice = average_diff as u128;
+        let mut high: FixedPrice = widest as u128;
+        if total_reduction(&vessels, low)? <= irma_amount as u128 {
+            high = low;
+        }
+        for _ in 0..WATER_FILL_STEPS {
+            if high - low <= 1 {
+                break;
+            }
+            let mid: FixedPrice = low + (high - low) / 2;
+            if total_reduction(&vessels, mid)? <= irma_amount as u128 {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+
+        let mut reductions = vec![0u128; reserves.len()];
+        for vessel in vessels.iter() {
+            reductions[vessel.index] = vessel.reduction_at(high)?;
+        }
+        let filled: u128 = reductions.iter().sum();
+        let rest: u128 = (irma_amount as u128).checked_sub(filled).ok_or(CustomError::MathError)?;
+        reductions[subject] = reductions[subject].checked_add(rest).ok_or(CustomError::MathError)?;
+        Ok(reductions)
+    }
+}
+
+/// Select how redeemed IRMA is taken off the reserves' circulation.
+pub fn set_allocation_strategy(ctx: Context<ManageState>, strategy: AllocationStrategy) -> Result<()> {
+    ctx.accounts.state.allocation_strategy = strategy;
+    msg!("Redemption allocation strategy set to {:?}", strategy);
+    Ok(())
+}
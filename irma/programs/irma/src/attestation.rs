@@ -0,0 +1,235 @@
+// programs/irma/src/attestation.rs
+//
+// Signed price attestations.
+// A registered price authority signs a PriceAttestation off-chain with its ed25519 key; anyone can
+// then relay it in a transaction whose preceding instruction is the native Ed25519 program verifying
+// that signature. submit_signed_price finds that instruction through the instructions sysvar, checks
+// that it verified this exact attestation from a registered authority, checks the authority's nonce
+// and the attestation's timestamp, and submits the USD rate to the reserve's price aggregator
+// (aggregation.rs). The price-setting key never has to sign or pay for a transaction.
+// The registry also lists, separately from the signing authorities, the oracle feed accounts whose
+// readings the aggregator accepts, and the emergency authority that co-signs USD rate changes beyond
+// a reserve's bounds.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+#[allow(deprecated)]
+use anchor_lang::solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+
+use crate::aggregation::{self, PriceSubmission};
+use crate::errors::CustomError;
+use crate::fixed_point::FixedPrice;
+use crate::oracle::MAX_PRICE_AGE;
+use crate::{InitPriceAuthorities, ManagePriceAuthorities, SubmitSignedPrice};
+
+/// Native program that verifies ed25519 signatures.
+pub const ED25519_PROGRAM_ID: Pubkey = pubkey!("Ed25519SigVerify111111111111111111111111111");
+/// Sysvar holding the instructions of the current transaction.
+pub const INSTRUCTIONS_SYSVAR_ID: Pubkey = pubkey!("Sysvar1nstructions1111111111111111111111111");
+
+/// Seed of the PriceAuthorities PDA.
+pub const PRICE_AUTHORITIES_SEED: &[u8] = b"price_authorities";
+pub const MAX_PRICE_AUTHORITIES: usize = 8;
+pub const MAX_PRICE_FEEDS: usize = 8;
+/// Most sources a reserve's price aggregator can hear from: every authority and every feed.
+pub const MAX_PRICE_SOURCES: usize = MAX_PRICE_AUTHORITIES + MAX_PRICE_FEEDS;
+/// How far in the future an attestation timestamp may be, to allow for clock drift.
+pub const MAX_CLOCK_DRIFT: i64 = 30;
+
+/// Layout of the Ed25519 program's instruction data.
+const ED25519_HEADER_LEN: usize = 2; // num_signatures: u8, padding: u8
+const ED25519_OFFSETS_LEN: usize = 14; // seven u16 offsets per signature
+const ED25519_PUBKEY_LEN: usize = 32;
+const ED25519_SIGNATURE_LEN: usize = 64;
+
+/// The message a price authority signs: the borsh serialization of this struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct PriceAttestation {
+    pub program_id: Pubkey, // this program, so attestations cannot be replayed elsewhere
+    pub quote_token: String,
+    pub usd_rate: FixedPrice, // quote tokens per USD, Q64.64
+    pub nonce: u64, // strictly increasing per authority
+    pub timestamp: i64, // unix time of signing
+}
+
+impl PriceAttestation {
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut message: Vec<u8> = Vec::new();
+        self.serialize(&mut message)?;
+        Ok(message)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct PriceAuthority {
+    pub key: Pubkey,
+    pub last_nonce: u64,
+}
+
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct PriceAuthorities {
+    pub authorities: Vec<PriceAuthority>,
+    pub feeds: Vec<Pubkey>, // oracle feed accounts update_usd_rate_from_oracle accepts
+    pub emergency_authority: Pubkey, // co-signer of out-of-bounds rate changes, default if none
+    pub bump: u8,
+}
+
+impl PriceAuthorities {
+    pub const LEN: usize = 4 + MAX_PRICE_AUTHORITIES * (32 + 8) + 4 + MAX_PRICE_FEEDS * 32 + 32 + 1;
+
+    pub fn add(&mut self, key: Pubkey) -> Result<()> {
+        require!(!self.contains(&key), CustomError::PriceAuthorityExists);
+        require!(self.authorities.len() < MAX_PRICE_AUTHORITIES, CustomError::TooManyPriceAuthorities);
+        self.authorities.push(PriceAuthority { key, last_nonce: 0 });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: Pubkey) -> Result<()> {
+        let position = self.authorities.iter().position(|a| a.key == key)
+            .ok_or(CustomError::UnknownPriceAuthority)?;
+        self.authorities.remove(position);
+        Ok(())
+    }
+
+    pub fn contains(&self, key: &Pubkey) -> bool {
+        self.authorities.iter().any(|a| a.key == *key)
+    }
+
+    pub fn add_feed(&mut self, feed: Pubkey) -> Result<()> {
+        require!(!self.has_feed(&feed), CustomError::PriceFeedExists);
+        require!(self.feeds.len() < MAX_PRICE_FEEDS, CustomError::TooManyPriceFeeds);
+        self.feeds.push(feed);
+        Ok(())
+    }
+
+    pub fn remove_feed(&mut self, feed: Pubkey) -> Result<()> {
+        let position = self.feeds.iter().position(|f| *f == feed).ok_or(CustomError::UnknownPriceFeed)?;
+        self.feeds.remove(position);
+        Ok(())
+    }
+
+    pub fn has_feed(&self, feed: &Pubkey) -> bool {
+        self.feeds.contains(feed)
+    }
+
+    /// Record nonce for authority; it must be greater than the authority's last nonce.
+    pub fn use_nonce(&mut self, authority: &Pubkey, nonce: u64) -> Result<()> {
+        let entry = self.authorities.iter_mut().find(|a| a.key == *authority)
+            .ok_or(CustomError::UnknownPriceAuthority)?;
+        require!(nonce > entry.last_nonce, CustomError::StaleNonce);
+        entry.last_nonce = nonce;
+        Ok(())
+    }
+}
+
+/// Check that timestamp is no older than MAX_PRICE_AGE and not ahead of now by more than MAX_CLOCK_DRIFT.
+pub fn check_timestamp(timestamp: i64, now: i64) -> Result<()> {
+    require!(now.saturating_sub(timestamp) <= MAX_PRICE_AGE, CustomError::StaleOraclePrice);
+    require!(timestamp.saturating_sub(now) <= MAX_CLOCK_DRIFT, CustomError::StaleOraclePrice);
+    Ok(())
+}
+
+/// Check that ix is an Ed25519 program instruction verifying one signature by authority over message,
+/// with the key, signature and message all inside ix itself. The runtime has already checked the
+/// signature by the time this program runs, so only what was verified needs checking here.
+pub fn verify_ed25519_instruction(ix: &Instruction, authority: &Pubkey, message: &[u8]) -> Result<()> {
+    require_keys_eq!(ix.program_id, ED25519_PROGRAM_ID, CustomError::InvalidSignature);
+    let data = &ix.data;
+    require!(
+        data.len() >= ED25519_HEADER_LEN + ED25519_OFFSETS_LEN && data[0] == 1,
+        CustomError::InvalidSignature
+    );
+    let offset = |i: usize| -> usize {
+        let at = ED25519_HEADER_LEN + 2 * i;
+        u16::from_le_bytes([data[at], data[at + 1]]) as usize
+    };
+    let (signature_offset, signature_ix) = (offset(0), offset(1));
+    let (pubkey_offset, pubkey_ix) = (offset(2), offset(3));
+    let (message_offset, message_size, message_ix) = (offset(4), offset(5), offset(6));
+    let this_ix = u16::MAX as usize;
+    require!(
+        signature_ix == this_ix && pubkey_ix == this_ix && message_ix == this_ix,
+        CustomError::InvalidSignature
+    );
+    require!(
+        signature_offset + ED25519_SIGNATURE_LEN <= data.len()
+            && pubkey_offset + ED25519_PUBKEY_LEN <= data.len()
+            && message_offset + message_size <= data.len(),
+        CustomError::InvalidSignature
+    );
+    require!(
+        data[pubkey_offset..pubkey_offset + ED25519_PUBKEY_LEN] == authority.to_bytes(),
+        CustomError::InvalidSignature
+    );
+    require!(data[message_offset..message_offset + message_size] == *message, CustomError::InvalidSignature);
+    Ok(())
+}
+
+/// Create the empty price authority registry.
+pub fn init_price_authorities(ctx: Context<InitPriceAuthorities>) -> Result<()> {
+    let registry = &mut ctx.accounts.price_authorities;
+    registry.authorities = Vec::with_capacity(MAX_PRICE_AUTHORITIES);
+    registry.feeds = Vec::with_capacity(MAX_PRICE_FEEDS);
+    registry.emergency_authority = Pubkey::default();
+    registry.bump = ctx.bumps.price_authorities;
+    Ok(())
+}
+
+pub fn add_price_authority(ctx: Context<ManagePriceAuthorities>, authority: Pubkey) -> Result<()> {
+    ctx.accounts.price_authorities.add(authority)?;
+    msg!("Added price authority {}", authority);
+    Ok(())
+}
+
+pub fn remove_price_authority(ctx: Context<ManagePriceAuthorities>, authority: Pubkey) -> Result<()> {
+    ctx.accounts.price_authorities.remove(authority)?;
+    msg!("Removed price authority {}", authority);
+    Ok(())
+}
+
+pub fn add_price_feed(ctx: Context<ManagePriceAuthorities>, feed: Pubkey) -> Result<()> {
+    ctx.accounts.price_authorities.add_feed(feed)?;
+    msg!("Added price feed {}", feed);
+    Ok(())
+}
+
+pub fn remove_price_feed(ctx: Context<ManagePriceAuthorities>, feed: Pubkey) -> Result<()> {
+    ctx.accounts.price_authorities.remove_feed(feed)?;
+    msg!("Removed price feed {}", feed);
+    Ok(())
+}
+
+/// Set the key that co-signs emergency USD rate changes; the default key disables them.
+pub fn set_emergency_authority(ctx: Context<ManagePriceAuthorities>, emergency_authority: Pubkey) -> Result<()> {
+    ctx.accounts.price_authorities.emergency_authority = emergency_authority;
+    msg!("Emergency authority set to {}", emergency_authority);
+    Ok(())
+}
+
+/// Submit a price attestation signed by authority to the reserve's price aggregator. The instruction immediately before this one
+/// must be the Ed25519 program verifying the authority's signature over the attestation.
+pub fn submit_signed_price(
+    ctx: Context<SubmitSignedPrice>, authority: Pubkey, attestation: PriceAttestation
+) -> Result<()> {
+    require_keys_eq!(attestation.program_id, crate::ID, CustomError::InvalidSignature);
+    let instructions = ctx.accounts.instructions.to_account_info();
+    let current = load_current_index_checked(&instructions)?;
+    require!(current > 0, CustomError::InvalidSignature);
+    let ed25519_ix = load_instruction_at_checked(current as usize - 1, &instructions)?;
+    verify_ed25519_instruction(&ed25519_ix, &authority, &attestation.message()?)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    check_timestamp(attestation.timestamp, now)?;
+    ctx.accounts.price_authorities.use_nonce(&authority, attestation.nonce)?;
+    msg!(
+        "USD rate {} for {} signed by {} (nonce {}), relayed by {}",
+        attestation.usd_rate, attestation.quote_token, authority, attestation.nonce, ctx.accounts.relayer.key()
+    );
+
+    let submission = PriceSubmission { source: authority, usd_rate: attestation.usd_rate, timestamp: attestation.timestamp };
+    let index = ctx.accounts.inflation_index.index;
+    aggregation::submit_rate(
+        &mut ctx.accounts.price_aggregator, &mut ctx.accounts.state, &mut ctx.accounts.price_history,
+        &ctx.accounts.protocol_config, index, &attestation.quote_token, submission, now)
+}
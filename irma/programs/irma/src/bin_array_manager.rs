@@ -21,7 +21,7 @@ impl<'a> BinArrayManager<'a> {
         match self
             .bin_arrays
             .iter()
-            .find(|ba| (*ba).index == bin_array_idx as i64)
+            .find(|ba| ba.index == bin_array_idx as i64)
         {
             Some(bin_array) => Ok(bin_array.get_bin(bin_id)?),
             None => Err(Error::from(CustomError::BinArrayNotFound)),
@@ -29,8 +29,8 @@ impl<'a> BinArrayManager<'a> {
     }
 
     pub fn get_lower_upper_bin_id(&self) -> Result<(i32, i32)> {
-        let lower_bin_array_idx = (*self.bin_arrays[0]).index as i32;
-        let upper_bin_array_idx = (*self.bin_arrays[self.bin_arrays.len() - 1]).index as i32;
+        let lower_bin_array_idx = self.bin_arrays[0].index as i32;
+        let upper_bin_array_idx = self.bin_arrays[self.bin_arrays.len() - 1].index as i32;
 
         let lower_bin_id = lower_bin_array_idx
             .checked_mul(MAX_BIN_PER_ARRAY as i32)
@@ -67,7 +67,7 @@ impl<'a> BinArrayManager<'a> {
         for bin_id in position.lower_bin_id..=position.upper_bin_id {
             let bin = self.get_bin(bin_id)?;
             let (fee_x_pending, fee_y_pending) =
-                BinArrayManager::get_fee_pending_for_a_bin(position, bin_id, &bin)?;
+                BinArrayManager::get_fee_pending_for_a_bin(position, bin_id, bin)?;
             total_fee_x = fee_x_pending
                 .checked_add(total_fee_x).unwrap();
             total_fee_y = fee_y_pending
@@ -0,0 +1,152 @@
+// programs/irma/src/config.rs
+//
+// Protocol parameters.
+// The ProtocolConfig PDA holds the limits and amounts that used to be compile-time constants: the
+// minimum mint, the maximum redemption per transaction (defaults for reserves without their own), the mint price at which minting stops, the
+// spread difference below which distribute ignores other reserves, the slippage allowed on DLMM swaps
+// and the sizes of the minting and redemption positions. The admin changes them through the setters
+// below, each of which validates its range; pricing.rs and meteora_integration.rs read them from the account.
+
+use anchor_lang::prelude::*;
+
+use crate::errors::CustomError;
+use crate::fixed_point::{FixedPrice, PRICE_ONE};
+use crate::pricing::MAX_MINT_PRICE;
+use crate::{InitProtocolConfig, ManageProtocolConfig};
+
+/// Seed of the ProtocolConfig PDA.
+pub const PROTOCOL_CONFIG_SEED: &[u8] = b"protocol_config";
+
+// Minimum mintable amount, in whole reserve tokens, scaled by each reserve's decimals. Reserves can
+// set their own minimum (see StableState::min_mint_amount), e.g. where 18 decimals make 100 tokens
+// more than fits in a u64 amount. Any request to mint below this amount will error out.
+// There is no maximum mint amount. Large mint requests are good for the system.
+// Users are advised to start small to test the system, then increase their mint amounts gradually.
+pub const DEFAULT_MIN_MINT_AMOUNT: u64 = 100;
+
+// Maximum redeemable amount per transaction, in whole IRMA; the payout is capped at as many whole
+// reserve tokens. Multiple transactions totalling more than this are allowed: users break up large
+// redemptions into multiple transactions, to prevent sudden large price movements.
+pub const DEFAULT_MAX_REDEEM_AMOUNT: u64 = 100_000;
+
+// Spread differences (Q64.64) below 0.001 are ignored when distribute picks a reserve to adjust.
+pub const DEFAULT_MIN_PRICE_DIFF: FixedPrice = PRICE_ONE / 1000;
+/// Largest accepted min_price_diff (0.1).
+pub const MAX_MIN_PRICE_DIFF: FixedPrice = PRICE_ONE / 10;
+
+// Slippage allowed on swaps against the DLMM pools, in basis points (3%), and its upper limit (10%).
+pub const DEFAULT_SLIPPAGE_BPS: u16 = 300;
+pub const MAX_SLIPPAGE_BPS: u16 = 1_000;
+
+// Amounts deposited when a position is shifted to a new bin.
+pub const DEFAULT_MINTING_POSITION_AMOUNT: u64 = 1_100_000_000; // IRMA base units
+pub const DEFAULT_REDEMPTION_POSITION_AMOUNT: u64 = 100_000_000; // reserve base units
+
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct ProtocolConfig {
+    pub min_mint_amount: u64, // whole reserve tokens
+    pub max_redeem_amount: u64, // whole IRMA per transaction
+    pub max_mint_price: FixedPrice, // minting stops for reserves priced at or above this
+    pub min_price_diff: FixedPrice, // spread differences distribute ignores
+    pub slippage_bps: u16, // slippage allowed on DLMM swaps
+    pub minting_position_amount: u64, // IRMA base units per minting position
+    pub redemption_position_amount: u64, // reserve base units per redemption position
+    pub bump: u8, // Bump seed for PDA
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        ProtocolConfig {
+            min_mint_amount: DEFAULT_MIN_MINT_AMOUNT,
+            max_redeem_amount: DEFAULT_MAX_REDEEM_AMOUNT,
+            max_mint_price: MAX_MINT_PRICE,
+            min_price_diff: DEFAULT_MIN_PRICE_DIFF,
+            slippage_bps: DEFAULT_SLIPPAGE_BPS,
+            minting_position_amount: DEFAULT_MINTING_POSITION_AMOUNT,
+            redemption_position_amount: DEFAULT_REDEMPTION_POSITION_AMOUNT,
+            bump: 0,
+        }
+    }
+}
+
+impl ProtocolConfig {
+    pub const LEN: usize = 8 + 8 + 16 + 16 + 2 + 8 + 8 + 1;
+
+    pub fn set_mint_redeem_limits(&mut self, min_mint_amount: u64, max_redeem_amount: u64) -> Result<()> {
+        require!(min_mint_amount > 0 && max_redeem_amount > 0, CustomError::InvalidProtocolConfig);
+        self.min_mint_amount = min_mint_amount;
+        self.max_redeem_amount = max_redeem_amount;
+        Ok(())
+    }
+
+    /// max_mint_price must be above 1.0 and at most MAX_MINT_PRICE, the ceiling prices are kept under.
+    pub fn set_max_mint_price(&mut self, max_mint_price: FixedPrice) -> Result<()> {
+        require!(
+            max_mint_price > PRICE_ONE && max_mint_price <= MAX_MINT_PRICE,
+            CustomError::InvalidProtocolConfig
+        );
+        self.max_mint_price = max_mint_price;
+        Ok(())
+    }
+
+    pub fn set_min_price_diff(&mut self, min_price_diff: FixedPrice) -> Result<()> {
+        require!(min_price_diff <= MAX_MIN_PRICE_DIFF, CustomError::InvalidProtocolConfig);
+        self.min_price_diff = min_price_diff;
+        Ok(())
+    }
+
+    pub fn set_slippage(&mut self, slippage_bps: u16) -> Result<()> {
+        require!(slippage_bps <= MAX_SLIPPAGE_BPS, CustomError::InvalidProtocolConfig);
+        self.slippage_bps = slippage_bps;
+        Ok(())
+    }
+
+    pub fn set_position_amounts(&mut self, minting_position_amount: u64, redemption_position_amount: u64) -> Result<()> {
+        require!(
+            minting_position_amount > 0 && redemption_position_amount > 0,
+            CustomError::InvalidProtocolConfig
+        );
+        self.minting_position_amount = minting_position_amount;
+        self.redemption_position_amount = redemption_position_amount;
+        Ok(())
+    }
+}
+
+/// Create the protocol config with the default parameters.
+pub fn init_protocol_config(ctx: Context<InitProtocolConfig>) -> Result<()> {
+    *ctx.accounts.protocol_config = ProtocolConfig { bump: ctx.bumps.protocol_config, ..ProtocolConfig::default() };
+    Ok(())
+}
+
+pub fn set_mint_redeem_limits(ctx: Context<ManageProtocolConfig>, min_mint_amount: u64, max_redeem_amount: u64) -> Result<()> {
+    ctx.accounts.protocol_config.set_mint_redeem_limits(min_mint_amount, max_redeem_amount)?;
+    msg!("Minimum mint set to {}, maximum redemption to {} IRMA", min_mint_amount, max_redeem_amount);
+    Ok(())
+}
+
+pub fn set_max_mint_price(ctx: Context<ManageProtocolConfig>, max_mint_price: FixedPrice) -> Result<()> {
+    ctx.accounts.protocol_config.set_max_mint_price(max_mint_price)?;
+    msg!("Maximum mint price set to {}", max_mint_price);
+    Ok(())
+}
+
+pub fn set_min_price_diff(ctx: Context<ManageProtocolConfig>, min_price_diff: FixedPrice) -> Result<()> {
+    ctx.accounts.protocol_config.set_min_price_diff(min_price_diff)?;
+    msg!("Minimum price difference set to {}", min_price_diff);
+    Ok(())
+}
+
+pub fn set_slippage(ctx: Context<ManageProtocolConfig>, slippage_bps: u16) -> Result<()> {
+    ctx.accounts.protocol_config.set_slippage(slippage_bps)?;
+    msg!("Swap slippage set to {} bps", slippage_bps);
+    Ok(())
+}
+
+pub fn set_position_amounts(
+    ctx: Context<ManageProtocolConfig>, minting_position_amount: u64, redemption_position_amount: u64
+) -> Result<()> {
+    ctx.accounts.protocol_config.set_position_amounts(minting_position_amount, redemption_position_amount)?;
+    msg!("Position amounts set to {} (minting) and {} (redemption)", minting_position_amount, redemption_position_amount);
+    Ok(())
+}
@@ -0,0 +1,578 @@
+// programs/irma/src/custody.rs
+//
+// Token custody for IRMA.
+// Each reserve stablecoin is held in a program-owned vault (a token account PDA per reserve mint),
+// and all vaults share a single PDA authority. IRMA itself is minted by a PDA mint authority,
+// so the only way to create IRMA is through the instructions in this module.
+// StableState bookkeeping is updated in the same instruction as the token movements.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Burn, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::allocation;
+use crate::errors::CustomError;
+use crate::history;
+use crate::pricing::{self, BasketDeposit, FeePolicy};
+use crate::queue::{self, RedemptionQueue};
+use crate::{CreateReserveVault, MintBasket, MintIrma, RedeemBasket, RedeemIrma, SwapReserves, WithdrawFees};
+
+/// Seed prefix of a reserve vault: [VAULT_SEED, reserve_mint]
+pub const VAULT_SEED: &[u8] = b"vault";
+/// Seed prefix of a reserve's treasury vault, which holds fees: [TREASURY_SEED, reserve_mint]
+pub const TREASURY_SEED: &[u8] = b"treasury";
+/// Seed of the PDA that owns every reserve vault and treasury vault.
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault_authority";
+/// Seed of the PDA that is the mint authority of IRMA.
+pub const MINT_AUTHORITY_SEED: &[u8] = b"mint_authority";
+/// Accounts of each reserve in a basket redemption, passed as remaining accounts in this order:
+/// reserve mint, reserve vault, treasury vault, user's token account, price history, token program.
+pub const BASKET_ACCOUNTS_PER_RESERVE: usize = 6;
+/// Accounts of each deposit in a basket mint, passed as remaining accounts in this order:
+/// reserve mint, user's token account, reserve vault, price history, token program, redemption queue, IRMA escrow.
+pub const BASKET_MINT_ACCOUNTS_PER_RESERVE: usize = 7;
+
+/// Transfer amount out of a vault (or IRMA escrow) owned by the vault authority PDA.
+pub(crate) fn pay_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    vault_authority: AccountInfo<'info>,
+    vault_authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[VAULT_AUTHORITY_SEED, &[vault_authority_bump]]];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to,
+                authority: vault_authority,
+            },
+            signer_seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Burn amount of IRMA from a reserve's IRMA escrow, owned by the vault authority PDA.
+pub(crate) fn burn_from_escrow<'info>(
+    token_program: AccountInfo<'info>,
+    irma_mint: AccountInfo<'info>,
+    escrow: AccountInfo<'info>,
+    vault_authority: AccountInfo<'info>,
+    vault_authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[VAULT_AUTHORITY_SEED, &[vault_authority_bump]]];
+    token_interface::burn(
+        CpiContext::new_with_signer(
+            token_program,
+            Burn {
+                mint: irma_mint,
+                from: escrow,
+                authority: vault_authority,
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+/// Check that info is this program's PDA [seed, mint], as vaults and price histories passed in
+/// remaining accounts must be.
+fn require_reserve_pda(info: &AccountInfo, seed: &[u8], mint: &Pubkey) -> Result<()> {
+    let (address, _) = Pubkey::find_program_address(&[seed, mint.as_ref()], &crate::ID);
+    require_keys_eq!(info.key(), address, CustomError::InvalidBasketAccounts);
+    Ok(())
+}
+
+/// Create the vault and the treasury vault for a reserve stablecoin that is already in the StateMap.
+/// Both are token accounts owned by the vault authority PDA.
+pub fn create_reserve_vault(ctx: Context<CreateReserveVault>) -> Result<()> {
+    let reserve_mint = &ctx.accounts.reserve_mint;
+    let symbol = ctx.accounts.state.get_stablecoin_symbol(reserve_mint.key())
+        .ok_or(error!(CustomError::ReserveNotFound))?;
+    let stablecoin = ctx.accounts.state.get_stablecoin(&symbol)?;
+    require!(stablecoin.backing_decimals == reserve_mint.decimals as u64, CustomError::InvalidBacking);
+    msg!("Created vault {} and treasury {} for {}",
+        ctx.accounts.reserve_vault.key(), ctx.accounts.treasury_vault.key(), symbol);
+    Ok(())
+}
+
+/// Mint IRMA for amount (in base units) of the reserve stablecoin.
+/// The stablecoin is moved from the user into the reserve vault, and IRMA is minted to the
+/// user's IRMA account at the current mint price.
+/// Only the amount that actually arrives in the vault is credited, so Token-2022 transfer fees
+/// are borne by the user rather than by the backing.
+/// The new backing then fills the reserve's queued redemptions, whose escrowed IRMA is burned.
+/// The remaining accounts may hold reserves' LbPair accounts; RedemptionShiftNeeded is emitted for those
+/// whose redemption price the fills moved into another bin (see pricing::crossed_redemption_bins).
+pub fn mint<'info>(ctx: Context<'_, '_, 'info, 'info, MintIrma<'info>>, amount: u64) -> Result<()> {
+    let pools = allocation::pool_states(&ctx.accounts.state.reserves, ctx.remaining_accounts)?;
+    let accounts = ctx.accounts;
+    let symbol = accounts.state.get_stablecoin_symbol(accounts.reserve_mint.key())
+        .ok_or(error!(CustomError::ReserveNotFound))?;
+    let stablecoin = accounts.state.get_stablecoin(&symbol)?;
+    require!(stablecoin.backing_decimals == accounts.reserve_mint.decimals as u64, CustomError::InvalidBacking);
+
+    // move the stablecoin into the vault
+    let vault_before: u64 = accounts.reserve_vault.amount;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            accounts.reserve_token_program.to_account_info(),
+            TransferChecked {
+                from: accounts.user_reserve_account.to_account_info(),
+                mint: accounts.reserve_mint.to_account_info(),
+                to: accounts.reserve_vault.to_account_info(),
+                authority: accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+        accounts.reserve_mint.decimals,
+    )?;
+    accounts.reserve_vault.reload()?;
+    let received: u64 = accounts.reserve_vault.amount
+        .checked_sub(vault_before)
+        .ok_or(CustomError::MathError)?;
+
+    // bookkeeping; the IRMA to mint is whatever mint_irma added to circulation
+    let circulation_before: u128 = stablecoin.irma_in_circulation;
+    let now = Clock::get()?.unix_timestamp;
+    pricing::mint_irma(&mut accounts.state, &accounts.protocol_config, &symbol, received, now)?;
+    let irma_minted: u64 = accounts.state.get_stablecoin(&symbol)?.irma_in_circulation
+        .checked_sub(circulation_before)
+        .and_then(|minted| u64::try_from(minted).ok())
+        .ok_or(CustomError::MathError)?;
+    require!(irma_minted > 0, CustomError::InvalidAmount);
+    let prices_before = pricing::redemption_prices(&accounts.state.reserves)?;
+    let irma_filled: u64 = queue::fill(
+        &mut accounts.redemption_queue, &mut accounts.state, &accounts.protocol_config, &symbol,
+        accounts.reserve_vault.amount, now)?;
+    history::record(&mut accounts.price_history, &accounts.state, now)?;
+    let crossed = pricing::crossed_redemption_bins(&prices_before, &accounts.state.reserves, &pools)?;
+    pricing::request_redemption_shifts(&accounts.state.reserves, &crossed);
+
+    // mint IRMA to the user
+    let signer_seeds: &[&[&[u8]]] = &[&[MINT_AUTHORITY_SEED, &[ctx.bumps.mint_authority]]];
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            accounts.irma_token_program.to_account_info(),
+            MintTo {
+                mint: accounts.irma_mint.to_account_info(),
+                to: accounts.user_irma_account.to_account_info(),
+                authority: accounts.mint_authority.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        irma_minted,
+    )?;
+
+    // burn the escrowed IRMA of the filled redemptions
+    if irma_filled > 0 {
+        burn_from_escrow(
+            accounts.irma_token_program.to_account_info(),
+            accounts.irma_mint.to_account_info(),
+            accounts.irma_escrow.to_account_info(),
+            accounts.vault_authority.to_account_info(),
+            ctx.bumps.vault_authority,
+            irma_filled,
+        )?;
+    }
+
+    msg!("Minted {} IRMA for {} {}", irma_minted, received, symbol);
+    Ok(())
+}
+
+/// Redeem irma_amount IRMA (in base units) for the reserve stablecoin of reserve_mint.
+/// The IRMA is burned from the user's account and the payout, at the current redemption price
+/// less the redemption fee, is transferred out of the reserve vault. The payout is checked against
+/// the actual vault balance, not just the backing_reserves counter.
+/// The remaining accounts may hold the LbPair accounts of all reserves with a pool, or none; their
+/// prices then steer which reserves' circulation the redemption reduces (see allocation::market_prices),
+/// and RedemptionShiftNeeded is emitted for reserves whose redemption price moved into another bin.
+pub fn redeem<'info>(ctx: Context<'_, '_, 'info, 'info, RedeemIrma<'info>>, irma_amount: u64) -> Result<()> {
+    let pools = allocation::pool_states(&ctx.accounts.state.reserves, ctx.remaining_accounts)?;
+    let market_prices = allocation::market_prices(&ctx.accounts.state.reserves, &pools)?;
+    let accounts = ctx.accounts;
+    let symbol = accounts.state.get_stablecoin_symbol(accounts.reserve_mint.key())
+        .ok_or(error!(CustomError::ReserveNotFound))?;
+    let stablecoin = accounts.state.get_stablecoin(&symbol)?;
+    require!(stablecoin.backing_decimals == accounts.reserve_mint.decimals as u64, CustomError::InvalidBacking);
+
+    // bookkeeping; the payout is whatever distribute took out of this reserve
+    let backing_before: u128 = stablecoin.backing_reserves;
+    let prices_before = pricing::redemption_prices(&accounts.state.reserves)?;
+    let now: i64 = Clock::get()?.unix_timestamp;
+    pricing::redeem_irma_at_market(&mut accounts.state, &accounts.protocol_config, &symbol, irma_amount, now, &market_prices)?;
+    let payout: u64 = backing_before
+        .checked_sub(accounts.state.get_stablecoin(&symbol)?.backing_reserves)
+        .and_then(|payout| u64::try_from(payout).ok())
+        .ok_or(CustomError::MathError)?;
+    require!(payout > 0, CustomError::InvalidAmount);
+
+    // limits apply to what is really in the vault
+    let max_payout: u128 = stablecoin.max_redeem_payout(&accounts.protocol_config)?;
+    if payout as u128 > max_payout {
+        msg!("Maximum payout from {} is {} tokens ({} base units), got {} base units",
+            symbol, stablecoin.max_redeem_tokens(&accounts.protocol_config), max_payout, payout);
+        return Err(error!(CustomError::RedemptionAboveMaximum));
+    }
+    require!(payout <= accounts.reserve_vault.amount, CustomError::InsufficientReserve);
+
+    let fee: u64 = accounts.state.charge_redemption_fee(&symbol, payout as u128)? as u64;
+    let amount_out: u64 = payout - fee;
+    history::record(&mut accounts.price_history, &accounts.state, now)?;
+    // the DLMM positions are the Core owner's; keepers shift them
+    let crossed = pricing::crossed_redemption_bins(&prices_before, &accounts.state.reserves, &pools)?;
+    pricing::request_redemption_shifts(&accounts.state.reserves, &crossed);
+
+    // burn the user's IRMA
+    token_interface::burn(
+        CpiContext::new(
+            accounts.irma_token_program.to_account_info(),
+            Burn {
+                mint: accounts.irma_mint.to_account_info(),
+                from: accounts.user_irma_account.to_account_info(),
+                authority: accounts.user.to_account_info(),
+            },
+        ),
+        irma_amount,
+    )?;
+
+    // pay out of the vault; under the treasury policy the fee moves to the treasury vault
+    pay_from_vault(
+        &accounts.reserve_token_program,
+        &accounts.reserve_vault,
+        &accounts.reserve_mint,
+        accounts.user_reserve_account.to_account_info(),
+        accounts.vault_authority.to_account_info(),
+        ctx.bumps.vault_authority,
+        amount_out,
+    )?;
+    if accounts.state.fee_policy == FeePolicy::Treasury && fee > 0 {
+        pay_from_vault(
+            &accounts.reserve_token_program,
+            &accounts.reserve_vault,
+            &accounts.reserve_mint,
+            accounts.treasury_vault.to_account_info(),
+            accounts.vault_authority.to_account_info(),
+            ctx.bumps.vault_authority,
+            fee,
+        )?;
+    }
+
+    msg!("Redeemed {} IRMA for {} {} (fee {})", irma_amount, amount_out, symbol, fee);
+    Ok(())
+}
+
+/// Swap amount (base units) of from_symbol for to_symbol, moving tokens between the two vaults.
+/// The accounting is a mint followed by a redemption (see pricing::swap_reserves); no IRMA is minted
+/// to the user. Fails if the to_symbol reserve cannot cover the payout or if it is below min_out.
+/// The remaining accounts may hold reserves' LbPair accounts; RedemptionShiftNeeded is emitted for those
+/// whose redemption price the swap moved into another bin (see pricing::crossed_redemption_bins).
+/// Like a mint, the input then fills from_symbol's queued redemptions, whose escrowed IRMA is burned.
+pub fn swap_reserves<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SwapReserves<'info>>,
+    from_symbol: &str,
+    to_symbol: &str,
+    amount: u64,
+    min_out: u64,
+) -> Result<()> {
+    let pools = allocation::pool_states(&ctx.accounts.state.reserves, ctx.remaining_accounts)?;
+    let accounts = ctx.accounts;
+    let from_stablecoin = accounts.state.get_stablecoin(from_symbol)?;
+    let to_stablecoin = accounts.state.get_stablecoin(to_symbol)?;
+    require_keys_eq!(from_stablecoin.mint_address, accounts.from_mint.key(), CustomError::InvalidBackingAddress);
+    require_keys_eq!(to_stablecoin.mint_address, accounts.to_mint.key(), CustomError::InvalidBackingAddress);
+
+    // move the input into its vault
+    let vault_before: u64 = accounts.from_vault.amount;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            accounts.from_token_program.to_account_info(),
+            TransferChecked {
+                from: accounts.user_from_account.to_account_info(),
+                mint: accounts.from_mint.to_account_info(),
+                to: accounts.from_vault.to_account_info(),
+                authority: accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+        accounts.from_mint.decimals,
+    )?;
+    accounts.from_vault.reload()?;
+    let received: u64 = accounts.from_vault.amount
+        .checked_sub(vault_before)
+        .ok_or(CustomError::MathError)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let prices_before = pricing::redemption_prices(&accounts.state.reserves)?;
+    let (amount_out, fee) = pricing::swap_reserves(
+        &mut accounts.state, &accounts.protocol_config, from_symbol, to_symbol, received, now)?;
+    require!(amount_out >= min_out, CustomError::SlippageExceeded);
+    require!(
+        amount_out.checked_add(fee).ok_or(CustomError::MathError)? <= accounts.to_vault.amount,
+        CustomError::InsufficientReserve
+    );
+    // the input is new backing for from_symbol, which may fill its queued redemptions
+    let irma_filled: u64 = queue::fill(
+        &mut accounts.from_redemption_queue, &mut accounts.state, &accounts.protocol_config, from_symbol,
+        accounts.from_vault.amount, now)?;
+    history::record(&mut accounts.from_price_history, &accounts.state, now)?;
+    history::record(&mut accounts.to_price_history, &accounts.state, now)?;
+    let crossed = pricing::crossed_redemption_bins(&prices_before, &accounts.state.reserves, &pools)?;
+    pricing::request_redemption_shifts(&accounts.state.reserves, &crossed);
+
+    // pay out of the other vault; under the treasury policy the fee moves to the treasury vault
+    pay_from_vault(
+        &accounts.to_token_program,
+        &accounts.to_vault,
+        &accounts.to_mint,
+        accounts.user_to_account.to_account_info(),
+        accounts.vault_authority.to_account_info(),
+        ctx.bumps.vault_authority,
+        amount_out,
+    )?;
+    if accounts.state.fee_policy == FeePolicy::Treasury && fee > 0 {
+        pay_from_vault(
+            &accounts.to_token_program,
+            &accounts.to_vault,
+            &accounts.to_mint,
+            accounts.to_treasury_vault.to_account_info(),
+            accounts.vault_authority.to_account_info(),
+            ctx.bumps.vault_authority,
+            fee,
+        )?;
+    }
+    if irma_filled > 0 {
+        burn_from_escrow(
+            accounts.irma_token_program.to_account_info(),
+            accounts.irma_mint.to_account_info(),
+            accounts.from_irma_escrow.to_account_info(),
+            accounts.vault_authority.to_account_info(),
+            ctx.bumps.vault_authority,
+            irma_filled,
+        )?;
+    }
+
+    msg!("Swapped {} {} for {} {} (fee {})", received, from_symbol, amount_out, to_symbol, fee);
+    Ok(())
+}
+
+/// Mint IRMA against several reserve stablecoins in one step (see pricing::mint_basket).
+/// Each deposit is moved from the user into its reserve vault and only what arrives is credited;
+/// the IRMA of all deposits is then minted to the user at once. The remaining accounts hold
+/// BASKET_MINT_ACCOUNTS_PER_RESERVE accounts for each deposit, in the order of deposits.
+/// Each deposit then fills its reserve's queued redemptions, whose escrowed IRMA is burned.
+pub fn mint_basket<'info>(
+    ctx: Context<'_, '_, 'info, 'info, MintBasket<'info>>, deposits: Vec<BasketDeposit>
+) -> Result<()> {
+    let accounts = ctx.accounts;
+    require!(
+        ctx.remaining_accounts.len() == deposits.len() * BASKET_MINT_ACCOUNTS_PER_RESERVE,
+        CustomError::InvalidBasketAccounts
+    );
+
+    // move each deposit into its vault
+    let mut received: Vec<BasketDeposit> = Vec::with_capacity(deposits.len());
+    let mut histories: Vec<AccountInfo<'info>> = Vec::with_capacity(deposits.len());
+    let mut queues: Vec<(Account<'info, RedemptionQueue>, &AccountInfo<'info>, u64)> = Vec::with_capacity(deposits.len());
+    for (deposit, group) in deposits.iter().zip(ctx.remaining_accounts.chunks(BASKET_MINT_ACCOUNTS_PER_RESERVE)) {
+        let [mint_info, user_info, vault_info, history_info, program_info, queue_info, escrow_info] = group else {
+            return Err(error!(CustomError::InvalidBasketAccounts));
+        };
+        let mint_key = mint_info.key();
+        let stablecoin = accounts.state.get_stablecoin(&deposit.symbol)?;
+        require_keys_eq!(mint_key, stablecoin.mint_address, CustomError::InvalidBasketAccounts);
+        require_reserve_pda(vault_info, VAULT_SEED, &mint_key)?;
+        require_reserve_pda(history_info, history::PRICE_HISTORY_SEED, &mint_key)?;
+        require_reserve_pda(queue_info, queue::REDEMPTION_QUEUE_SEED, &mint_key)?;
+        require_reserve_pda(escrow_info, queue::ESCROW_SEED, &mint_key)?;
+        let token_program: Interface<'info, TokenInterface> = Interface::try_from(program_info)?;
+        require_keys_eq!(*mint_info.owner, token_program.key(), CustomError::InvalidBasketAccounts);
+        let reserve_mint: InterfaceAccount<'info, Mint> = InterfaceAccount::try_from(mint_info)?;
+        require!(stablecoin.backing_decimals == reserve_mint.decimals as u64, CustomError::InvalidBacking);
+        let redemption_queue: Account<'info, RedemptionQueue> = Account::try_from(queue_info)?;
+        let mut vault: InterfaceAccount<'info, TokenAccount> = InterfaceAccount::try_from(vault_info)?;
+
+        let vault_before: u64 = vault.amount;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: user_info.clone(),
+                    mint: mint_info.clone(),
+                    to: vault_info.clone(),
+                    authority: accounts.user.to_account_info(),
+                },
+            ),
+            deposit.amount,
+            reserve_mint.decimals,
+        )?;
+        vault.reload()?;
+        let amount: u64 = vault.amount
+            .checked_sub(vault_before)
+            .ok_or(CustomError::MathError)?;
+        received.push(BasketDeposit { symbol: deposit.symbol.clone(), amount });
+        histories.push(history_info.clone());
+        queues.push((redemption_queue, escrow_info, vault.amount));
+    }
+
+    // bookkeeping, then a single mint of the total
+    let now = Clock::get()?.unix_timestamp;
+    let minted: Vec<u64> = pricing::mint_basket(&mut accounts.state, &accounts.protocol_config, &received, now)?;
+    let irma_minted: u64 = minted.iter()
+        .try_fold(0u64, |total, irma| total.checked_add(*irma))
+        .ok_or(CustomError::MathError)?;
+    require!(irma_minted > 0, CustomError::InvalidAmount);
+    let mut fills: Vec<(&AccountInfo<'info>, u64)> = Vec::with_capacity(queues.len());
+    for (deposit, (mut redemption_queue, escrow_info, vault_amount)) in received.iter().zip(queues) {
+        let irma_filled: u64 = queue::fill(
+            &mut redemption_queue, &mut accounts.state, &accounts.protocol_config, &deposit.symbol, vault_amount, now)?;
+        redemption_queue.exit(&crate::ID)?;
+        fills.push((escrow_info, irma_filled));
+    }
+    history::record_all(&histories, &accounts.state, now)?;
+
+    let signer_seeds: &[&[&[u8]]] = &[&[MINT_AUTHORITY_SEED, &[ctx.bumps.mint_authority]]];
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            accounts.irma_token_program.to_account_info(),
+            MintTo {
+                mint: accounts.irma_mint.to_account_info(),
+                to: accounts.user_irma_account.to_account_info(),
+                authority: accounts.mint_authority.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        irma_minted,
+    )?;
+
+    // burn the escrowed IRMA of the filled redemptions
+    for (escrow_info, irma_filled) in fills {
+        if irma_filled > 0 {
+            burn_from_escrow(
+                accounts.irma_token_program.to_account_info(),
+                accounts.irma_mint.to_account_info(),
+                escrow_info.clone(),
+                accounts.vault_authority.to_account_info(),
+                ctx.bumps.vault_authority,
+                irma_filled,
+            )?;
+        }
+    }
+
+    msg!("Minted {} IRMA for a basket of {} reserves", irma_minted, received.len());
+    Ok(())
+}
+
+/// Redeem irma_amount IRMA (base units) for a share of every reserve open for redemption, in proportion to the USD value
+/// of its backing (see pricing::redeem_basket). The IRMA is burned from the user's account and each share,
+/// less the redemption fee, is transferred out of its reserve vault. The remaining accounts hold
+/// BASKET_ACCOUNTS_PER_RESERVE accounts for each of those reserves, in state order, optionally followed by
+/// reserves' LbPair accounts; RedemptionShiftNeeded is emitted for those whose redemption price the
+/// redemption moved into another bin (see pricing::crossed_redemption_bins).
+pub fn redeem_basket<'info>(ctx: Context<'_, '_, 'info, 'info, RedeemBasket<'info>>, irma_amount: u64) -> Result<()> {
+    let accounts = ctx.accounts;
+    let now = Clock::get()?.unix_timestamp;
+    let prices_before = pricing::redemption_prices(&accounts.state.reserves)?;
+    let payouts = pricing::redeem_basket(&mut accounts.state, &accounts.protocol_config, irma_amount, now)?;
+    let basket_len: usize = payouts.len() * BASKET_ACCOUNTS_PER_RESERVE;
+    require!(ctx.remaining_accounts.len() >= basket_len, CustomError::InvalidBasketAccounts);
+    let (basket_accounts, lb_pair_accounts) = ctx.remaining_accounts.split_at(basket_len);
+    let pools = allocation::pool_states(&accounts.state.reserves, lb_pair_accounts)?;
+    let crossed = pricing::crossed_redemption_bins(&prices_before, &accounts.state.reserves, &pools)?;
+    pricing::request_redemption_shifts(&accounts.state.reserves, &crossed);
+
+    // burn the user's IRMA
+    token_interface::burn(
+        CpiContext::new(
+            accounts.irma_token_program.to_account_info(),
+            Burn {
+                mint: accounts.irma_mint.to_account_info(),
+                from: accounts.user_irma_account.to_account_info(),
+                authority: accounts.user.to_account_info(),
+            },
+        ),
+        irma_amount,
+    )?;
+
+    // pay each share out of its vault; under the treasury policy the fee moves to the treasury vault
+    let treasury_policy = accounts.state.fee_policy == FeePolicy::Treasury;
+    for ((symbol, amount_out, fee), group) in payouts.iter().zip(basket_accounts.chunks(BASKET_ACCOUNTS_PER_RESERVE)) {
+        let [mint_info, vault_info, treasury_info, user_info, history_info, program_info] = group else {
+            return Err(error!(CustomError::InvalidBasketAccounts));
+        };
+        let mint_key = mint_info.key();
+        require_keys_eq!(mint_key, accounts.state.get_stablecoin(symbol)?.mint_address, CustomError::InvalidBasketAccounts);
+        require_reserve_pda(vault_info, VAULT_SEED, &mint_key)?;
+        require_reserve_pda(treasury_info, TREASURY_SEED, &mint_key)?;
+        require_reserve_pda(history_info, history::PRICE_HISTORY_SEED, &mint_key)?;
+        let token_program: Interface<'info, TokenInterface> = Interface::try_from(program_info)?;
+        require_keys_eq!(*mint_info.owner, token_program.key(), CustomError::InvalidBasketAccounts);
+        let reserve_mint: InterfaceAccount<'info, Mint> = InterfaceAccount::try_from(mint_info)?;
+        let vault: InterfaceAccount<'info, TokenAccount> = InterfaceAccount::try_from(vault_info)?;
+        let user_account: InterfaceAccount<'info, TokenAccount> = InterfaceAccount::try_from(user_info)?;
+        require_keys_eq!(user_account.mint, mint_key, CustomError::InvalidBasketAccounts);
+        require!(
+            amount_out.checked_add(*fee).ok_or(CustomError::MathError)? <= vault.amount,
+            CustomError::InsufficientReserve
+        );
+        history::record_all(std::slice::from_ref(history_info), &accounts.state, now)?;
+
+        if *amount_out > 0 {
+            pay_from_vault(
+                &token_program,
+                &vault,
+                &reserve_mint,
+                user_info.clone(),
+                accounts.vault_authority.to_account_info(),
+                ctx.bumps.vault_authority,
+                *amount_out,
+            )?;
+        }
+        if treasury_policy && *fee > 0 {
+            pay_from_vault(
+                &token_program,
+                &vault,
+                &reserve_mint,
+                treasury_info.clone(),
+                accounts.vault_authority.to_account_info(),
+                ctx.bumps.vault_authority,
+                *fee,
+            )?;
+        }
+        msg!("Paid {} {} (fee {})", amount_out, symbol, fee);
+    }
+
+    msg!("Redeemed {} IRMA across {} reserves", irma_amount, payouts.len());
+    Ok(())
+}
+
+/// Withdraw amount (base units) of collected fees from a reserve's treasury vault (admin only).
+pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+    let accounts = ctx.accounts;
+    let symbol = accounts.state.get_stablecoin_symbol(accounts.reserve_mint.key())
+        .ok_or(error!(CustomError::ReserveNotFound))?;
+    let stablecoin = accounts.state.get_mut_stablecoin(&symbol)?;
+    stablecoin.treasury_fees = stablecoin.treasury_fees
+        .checked_sub(amount as u128)
+        .ok_or(CustomError::InsufficientReserve)?;
+
+    pay_from_vault(
+        &accounts.token_program,
+        &accounts.treasury_vault,
+        &accounts.reserve_mint,
+        accounts.destination.to_account_info(),
+        accounts.vault_authority.to_account_info(),
+        ctx.bumps.vault_authority,
+        amount,
+    )?;
+
+    msg!("Withdrew {} {} of fees", amount, symbol);
+    Ok(())
+}
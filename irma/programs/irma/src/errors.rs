@@ -76,4 +76,80 @@ pub enum CustomError {
     InvalidMarketMakingModeForIRMA,
     #[msg("Price not found in LB pair")]
     PriceNotFoundInLBPair,
+    #[msg("Signer is not the IRMA admin.")]
+    Unauthorized,
+    #[msg("State account is already in the current layout.")]
+    StateAlreadyMigrated,
+    #[msg("Output amount is below the requested minimum.")]
+    SlippageExceeded,
+    #[msg("Inflation was already applied in the current period.")]
+    InflationPeriodNotElapsed,
+    #[msg("Inflation rate is out of range.")]
+    InvalidInflationRate,
+    #[msg("Oracle account has the wrong owner or layout.")]
+    InvalidOracle,
+    #[msg("Oracle price is too old.")]
+    StaleOraclePrice,
+    #[msg("Oracle confidence interval is too wide.")]
+    OracleConfidenceTooWide,
+    #[msg("Oracle feed account missing from remaining accounts.")]
+    MissingOracleAccount,
+    #[msg("Signature was not verified by a matching Ed25519 instruction.")]
+    InvalidSignature,
+    #[msg("Signer is not a registered price authority.")]
+    UnknownPriceAuthority,
+    #[msg("Price authority is already registered.")]
+    PriceAuthorityExists,
+    #[msg("Too many price authorities.")]
+    TooManyPriceAuthorities,
+    #[msg("Nonce must be greater than the price authority's last nonce.")]
+    StaleNonce,
+    #[msg("Price aggregator quorum, window or deviation is out of range.")]
+    InvalidAggregatorConfig,
+    #[msg("Price change exceeds the reserve's per-update or daily bound.")]
+    PriceChangeOutOfBounds,
+    #[msg("Mint price is stale; minting is paused until the USD rate is updated.")]
+    StaleMintPrice,
+    #[msg("Price history does not cover the requested window.")]
+    InsufficientPriceHistory,
+    #[msg("Account is not a writable price history of this program.")]
+    InvalidPriceHistory,
+    #[msg("Protocol parameter is out of range.")]
+    InvalidProtocolConfig,
+    #[msg("Mint price has reached the maximum; minting against this reserve is disabled.")]
+    MintPriceTooHigh,
+    #[msg("Mint amount is below the reserve's minimum mint; see the log for the limit in base units.")]
+    MintBelowMinimum,
+    #[msg("Redemption is above the reserve's maximum per transaction; see the log for the limit in base units.")]
+    RedemptionAboveMaximum,
+    #[msg("Redemption cap for the current window reached; see the log for when the window resets.")]
+    RedemptionCapReached,
+    #[msg("Redemption queue is full.")]
+    RedemptionQueueFull,
+    #[msg("Queued redemption not found.")]
+    RedemptionNotFound,
+    #[msg("Queued redemption is already filled; claim it instead.")]
+    RedemptionAlreadyFilled,
+    #[msg("Queued redemption is not filled yet.")]
+    RedemptionNotFilled,
+    #[msg("Remaining accounts do not match the reserves of the basket.")]
+    InvalidBasketAccounts,
+    #[msg("Redemption allocation does not add up to the redeemed IRMA or reduces a reserve closed for redemption.")]
+    InvalidAllocation,
+    #[msg("Reserve status does not allow this change.")]
+    InvalidStatusTransition,
+    #[msg("Reserve is not open for minting.")]
+    ReserveNotMintable,
+    #[msg("Reserve is not open for redemption.")]
+    ReserveNotRedeemable,
+    #[msg("LbPair accounts must be passed for every reserve with a pool, or for none.")]
+    IncompleteLbPairs,
+    #[msg("Inflation period is out of range.")]
+    InvalidInflationPeriod,
+    #[msg("Oracle feed is not a registered price feed.")]
+    UnknownPriceFeed,
+    #[msg("Price feed is already registered.")]
+    PriceFeedExists,
+    #[msg("Too many price feeds.")]
+    TooManyPriceFeeds,
 }
@@ -0,0 +1,76 @@
+// programs/irma/src/fixed_point.rs
+//
+// Deterministic fixed-point arithmetic for IRMA pricing.
+// All prices are unsigned Q64.64 numbers, the same representation Meteora DLMM uses
+// for bin prices (see commons::ONE). Every operation that can lose precision takes an
+// explicit rounding mode, so the direction of every rounding step is visible at the call site.
+
+use anchor_lang::prelude::*;
+use commons::dlmm::types::Rounding;
+use commons::{mul_div as u256_mul_div, ONE};
+
+use crate::errors::CustomError;
+
+/// Q64.64 fixed-point price: the upper 64 bits are the integer part,
+/// the lower 64 bits are the fraction.
+pub type FixedPrice = u128;
+
+/// 1.0 in Q64.64.
+pub const PRICE_ONE: FixedPrice = ONE;
+
+/// Largest power of ten that fits in a u128.
+const MAX_POW10: u32 = 38;
+
+/// 10^exp as u128.
+pub fn pow10(exp: u32) -> Result<u128> {
+    require!(exp <= MAX_POW10, CustomError::MathError);
+    Ok(10u128.pow(exp))
+}
+
+/// (x * y) / denominator, computed in 256 bits.
+pub fn mul_div(x: u128, y: u128, denominator: u128, rounding: Rounding) -> Result<u128> {
+    u256_mul_div(x, y, denominator, rounding).ok_or(error!(CustomError::MathError))
+}
+
+/// Price equal to numerator / denominator.
+pub fn from_ratio(numerator: u128, denominator: u128, rounding: Rounding) -> Result<FixedPrice> {
+    mul_div(numerator, PRICE_ONE, denominator, rounding)
+}
+
+/// Price from a decimal mantissa, e.g. from_decimal(123, 2) == 1.23.
+pub fn from_decimal(mantissa: u128, decimals: u32, rounding: Rounding) -> Result<FixedPrice> {
+    from_ratio(mantissa, pow10(decimals)?, rounding)
+}
+
+/// Integer amount multiplied by a price.
+pub fn mul_price(amount: u128, price: FixedPrice, rounding: Rounding) -> Result<u128> {
+    mul_div(amount, price, PRICE_ONE, rounding)
+}
+
+/// Integer amount divided by a price.
+pub fn div_price(amount: u128, price: FixedPrice, rounding: Rounding) -> Result<u128> {
+    require!(price > 0, CustomError::InvalidAmount);
+    mul_div(amount, PRICE_ONE, price, rounding)
+}
+
+/// Rescale a value expressed with from_decimals to to_decimals: value * 10^to / 10^from.
+/// Works for both plain token amounts and Q64.64 prices.
+pub fn rescale(value: u128, from_decimals: u32, to_decimals: u32, rounding: Rounding) -> Result<u128> {
+    if from_decimals == to_decimals {
+        return Ok(value);
+    }
+    if to_decimals > from_decimals {
+        return value
+            .checked_mul(pow10(to_decimals - from_decimals)?)
+            .ok_or(error!(CustomError::MathError));
+    }
+    mul_div(value, 1, pow10(from_decimals - to_decimals)?, rounding)
+}
+
+/// Signed difference between two prices (a - b).
+/// Q64.64 prices below MAX_MINT_PRICE are far below i128::MAX, so this cannot overflow in practice.
+pub fn signed_diff(a: FixedPrice, b: FixedPrice) -> Result<i128> {
+    let a = i128::try_from(a).map_err(|_| error!(CustomError::MathError))?;
+    let b = i128::try_from(b).map_err(|_| error!(CustomError::MathError))?;
+    a.checked_sub(b).ok_or(error!(CustomError::MathError))
+}
@@ -0,0 +1,187 @@
+// programs/irma/src/history.rs
+//
+// Per-reserve price history.
+// Each reserve has a PriceHistory PDA holding the last PRICE_HISTORY_LEN observations of its mint price,
+// redemption price, backing and circulation in a ring buffer. An observation is recorded whenever the
+// reserve changes: mints, redemptions and swaps through the vaults, committed USD rates and repricing
+// by the inflation index. Observations in the same second replace each other, so the buffer holds one
+// observation per second at most.
+// Time-weighted averages treat each observation as holding until the next one (or until now), and are
+// only served when the history covers the whole window, so a single recent trade or price update cannot
+// move a TWAP by more than its share of the window.
+
+use anchor_lang::prelude::*;
+use commons::dlmm::types::Rounding;
+
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice};
+use crate::pricing::{StableState, StateMap};
+use crate::{GetTwap, InitPriceHistory, RecordPrice};
+
+/// Seed of the per-reserve PriceHistory PDA, followed by the reserve mint.
+pub const PRICE_HISTORY_SEED: &[u8] = b"price_history";
+pub const PRICE_HISTORY_LEN: usize = 64;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PriceObservation {
+    pub timestamp: i64,
+    pub mint_price: FixedPrice,
+    pub redemption_price: FixedPrice,
+    pub backing_reserves: u128, // reserve base units
+    pub irma_in_circulation: u128, // IRMA base units
+}
+
+impl PriceObservation {
+    pub fn from_stablecoin(stablecoin: &StableState, now: i64) -> Result<Self> {
+        Ok(PriceObservation {
+            timestamp: now,
+            mint_price: stablecoin.mint_price,
+            redemption_price: stablecoin.redemption_price()?,
+            backing_reserves: stablecoin.backing_reserves,
+            irma_in_circulation: stablecoin.irma_in_circulation,
+        })
+    }
+}
+
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct PriceHistory {
+    pub mint: Pubkey, // reserve mint
+    pub observations: Vec<PriceObservation>, // ring buffer, at most PRICE_HISTORY_LEN
+    pub next: u16, // slot the next observation goes to once the buffer is full
+    pub bump: u8, // Bump seed for PDA
+}
+
+impl PriceHistory {
+    pub const LEN: usize = 32 + 4 + PRICE_HISTORY_LEN * (8 + 16 * 4) + 2 + 1;
+
+    pub fn new(mint: Pubkey, bump: u8) -> Self {
+        PriceHistory {
+            mint,
+            observations: Vec::with_capacity(PRICE_HISTORY_LEN),
+            next: 0,
+            bump,
+        }
+    }
+
+    /// Slot of the most recent observation, if any.
+    fn latest_slot(&self) -> Option<usize> {
+        match self.observations.len() {
+            0 => None,
+            len if len < PRICE_HISTORY_LEN => Some(len - 1),
+            _ => Some((self.next as usize + PRICE_HISTORY_LEN - 1) % PRICE_HISTORY_LEN),
+        }
+    }
+
+    /// Most recent observation, if any.
+    pub fn latest(&self) -> Option<&PriceObservation> {
+        self.latest_slot().map(|slot| &self.observations[slot])
+    }
+
+    /// Observations from oldest to newest.
+    pub fn chronological(&self) -> impl Iterator<Item = &PriceObservation> {
+        let split = if self.observations.len() < PRICE_HISTORY_LEN { 0 } else { self.next as usize };
+        self.observations[split..].iter().chain(self.observations[..split].iter())
+    }
+
+    /// Append an observation of stablecoin at now, overwriting the oldest once the buffer is full.
+    /// An observation in the same second as the latest one replaces it.
+    pub fn record(&mut self, stablecoin: &StableState, now: i64) -> Result<()> {
+        require_keys_eq!(stablecoin.mint_address, self.mint, CustomError::InvalidQuoteToken);
+        let observation = PriceObservation::from_stablecoin(stablecoin, now)?;
+        if let Some(slot) = self.latest_slot() {
+            let latest = self.observations[slot].timestamp;
+            require!(now >= latest, CustomError::InvalidAmount);
+            if now == latest {
+                self.observations[slot] = observation;
+                return Ok(());
+            }
+        }
+        if self.observations.len() < PRICE_HISTORY_LEN {
+            self.observations.push(observation);
+        } else {
+            self.observations[self.next as usize] = observation;
+            self.next = ((self.next as usize + 1) % PRICE_HISTORY_LEN) as u16;
+        }
+        Ok(())
+    }
+
+    /// Time-weighted average of value over the window seconds before now, rounded down.
+    /// Fails unless the oldest observation is at or before the start of the window.
+    pub fn twap<F>(&self, window: i64, now: i64, value: F) -> Result<u128>
+    where
+        F: Fn(&PriceObservation) -> u128,
+    {
+        require!(window > 0, CustomError::InvalidAmount);
+        let start = now.checked_sub(window).ok_or(CustomError::MathError)?;
+        let oldest = self.chronological().next().ok_or(CustomError::InsufficientPriceHistory)?;
+        require!(oldest.timestamp <= start, CustomError::InsufficientPriceHistory);
+        require!(self.latest().is_some_and(|latest| latest.timestamp <= now), CustomError::InvalidAmount);
+
+        let observations: Vec<&PriceObservation> = self.chronological().collect();
+        let mut weighted: u128 = 0;
+        for (i, observation) in observations.iter().enumerate() {
+            let until = observations.get(i + 1).map_or(now, |next| next.timestamp);
+            let from = observation.timestamp.max(start);
+            if until <= from {
+                continue;
+            }
+            let seconds = (until - from) as u128;
+            weighted = value(observation).checked_mul(seconds)
+                .and_then(|v| weighted.checked_add(v))
+                .ok_or(CustomError::MathError)?;
+        }
+        fixed_point::mul_div(weighted, 1, window as u128, Rounding::Down)
+    }
+
+    /// Time-weighted (mint price, redemption price) over the window seconds before now.
+    pub fn price_twap(&self, window: i64, now: i64) -> Result<(FixedPrice, FixedPrice)> {
+        Ok((
+            self.twap(window, now, |o| o.mint_price)?,
+            self.twap(window, now, |o| o.redemption_price)?,
+        ))
+    }
+}
+
+/// Record the current state of history's reserve in state_map.
+pub fn record(history: &mut PriceHistory, state_map: &StateMap, now: i64) -> Result<()> {
+    let stablecoin = state_map.reserves.iter().find(|r| r.mint_address == history.mint)
+        .ok_or(CustomError::ReserveNotFound)?;
+    history.record(stablecoin, now)
+}
+
+/// Record the current state of each reserve whose writable PriceHistory account is in histories.
+/// Used by instructions that may reprice several reserves and take the histories as remaining accounts.
+pub fn record_all(histories: &[AccountInfo], state_map: &StateMap, now: i64) -> Result<()> {
+    for info in histories {
+        require_keys_eq!(*info.owner, crate::ID, CustomError::InvalidPriceHistory);
+        require!(info.is_writable, CustomError::InvalidPriceHistory);
+        let mut history = PriceHistory::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        record(&mut history, state_map, now)?;
+        history.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    }
+    Ok(())
+}
+
+/// Create the price history of a listed reserve, starting with an observation of its current state.
+pub fn init_price_history(ctx: Context<InitPriceHistory>) -> Result<()> {
+    let mint = ctx.accounts.reserve_mint.key();
+    let mut history = PriceHistory::new(mint, ctx.bumps.price_history);
+    record(&mut history, &ctx.accounts.state, Clock::get()?.unix_timestamp)?;
+    *ctx.accounts.price_history = history;
+    Ok(())
+}
+
+/// Record the current state of a reserve.
+pub fn record_price(ctx: Context<RecordPrice>) -> Result<()> {
+    record(&mut ctx.accounts.price_history, &ctx.accounts.state, Clock::get()?.unix_timestamp)
+}
+
+/// Time-weighted (mint price, redemption price) of a reserve over the last window seconds.
+pub fn get_twap(ctx: Context<GetTwap>, window: i64) -> Result<(FixedPrice, FixedPrice)> {
+    let now = Clock::get()?.unix_timestamp;
+    let (mint_twap, redemption_twap) = ctx.accounts.price_history.price_twap(window, now)?;
+    msg!("TWAP over {}s for {}: mint {}, redemption {}",
+        window, ctx.accounts.price_history.mint, mint_twap, redemption_twap);
+    Ok((mint_twap, redemption_twap))
+}
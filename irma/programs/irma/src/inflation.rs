@@ -0,0 +1,166 @@
+// programs/irma/src/inflation.rs
+//
+// Global USD inflation index.
+// The index is the USD price of one IRMA (Q64.64). It starts at 1.0 and compounds once per period
+// from an annualized inflation rate: index *= 1 + rate * period / year, which for a daily period
+// is the Issuance_Price *= 1 + USD_Inflation / 36500 rule of Implementation.md.
+// Every reserve's mint price is the index times the reserve's USD exchange rate (see pricing.rs),
+// so all mint prices move together.
+// Inflation up to the tolerable rate leaves the index unchanged; above it, the full rate is applied.
+// Deflation (a negative rate) lowers the index, below 1.0 if need be, unless the deflation policy holds it.
+
+use anchor_lang::prelude::*;
+use commons::dlmm::types::Rounding;
+
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice, PRICE_ONE};
+use crate::history;
+use crate::pricing::BASIS_POINTS_MAX;
+use crate::{InitInflationIndex, UpdatePrices};
+
+/// Seed of the InflationIndex PDA.
+pub const INFLATION_INDEX_SEED: &[u8] = b"inflation_index";
+/// Default minimum time between two applications of inflation (one day).
+pub const DEFAULT_INFLATION_PERIOD: i64 = 86_400;
+pub const SECONDS_PER_YEAR: i64 = 365 * 86_400;
+/// Longest period inflation may be applied over (one year).
+pub const MAX_INFLATION_PERIOD: i64 = SECONDS_PER_YEAR;
+/// Sanity limit on the annualized rate (1000%).
+pub const MAX_INFLATION_RATE_BPS: i32 = 100_000;
+/// Sanity limit on the annualized deflation rate (100%).
+pub const MAX_DEFLATION_RATE_BPS: i32 = 10_000;
+/// Default tolerable annual inflation (1%).
+pub const DEFAULT_TOLERABLE_INFLATION_BPS: i32 = 100;
+
+/// What a negative inflation rate does to the index.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DeflationPolicy {
+    /// Lower the index by the deflation rate, below 1.0 if need be.
+    #[default]
+    Track,
+    /// Keep the index unchanged during deflation.
+    Hold,
+}
+
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct InflationIndex {
+    pub index: FixedPrice, // USD per IRMA (Q64.64), 1.0 at launch
+    pub last_rate_bps: i32, // annualized rate of the last application, in basis points
+    pub last_applied: i64, // unix timestamp of the last application
+    pub period: i64, // minimum seconds between applications
+    pub tolerable_rate_bps: i32, // annualized inflation that does not move the index
+    pub deflation_policy: DeflationPolicy, // what a negative rate does to the index
+    pub bump: u8, // Bump seed for PDA
+}
+
+impl InflationIndex {
+    pub const LEN: usize = 16 + 4 + 8 + 8 + 4 + 1 + 1;
+
+    pub fn new(bump: u8) -> Self {
+        InflationIndex {
+            index: PRICE_ONE,
+            last_rate_bps: 0,
+            last_applied: 0,
+            period: DEFAULT_INFLATION_PERIOD,
+            tolerable_rate_bps: DEFAULT_TOLERABLE_INFLATION_BPS,
+            deflation_policy: DeflationPolicy::Track,
+            bump,
+        }
+    }
+
+    /// Rate actually compounded for an observed annual rate_bps: zero within the tolerable band,
+    /// the full rate above it, and the (negative) rate during deflation unless the policy holds.
+    pub fn effective_rate_bps(&self, rate_bps: i32) -> i32 {
+        let inflating = rate_bps > self.tolerable_rate_bps;
+        let deflating = rate_bps < 0 && self.deflation_policy == DeflationPolicy::Track;
+        if inflating || deflating { rate_bps } else { 0 }
+    }
+
+    /// Compound one period of the annualized rate_bps into the index.
+    /// Fails if less than one period has passed since the last application.
+    /// A period whose rate is within the tolerable band still counts as applied.
+    pub fn compound(&mut self, rate_bps: i32, now: i64) -> Result<FixedPrice> {
+        let next_allowed = self.last_applied.checked_add(self.period).ok_or(CustomError::MathError)?;
+        require!(now >= next_allowed, CustomError::InflationPeriodNotElapsed);
+        require!(
+            (-MAX_DEFLATION_RATE_BPS..=MAX_INFLATION_RATE_BPS).contains(&rate_bps),
+            CustomError::InvalidInflationRate
+        );
+
+        // change = index * |rate| * period / year, rounded down either way so that
+        // deflation never cuts the index by more than the rate
+        let rate_bps_applied = self.effective_rate_bps(rate_bps);
+        let change: u128 = fixed_point::mul_div(
+            self.index,
+            rate_bps_applied.unsigned_abs() as u128 * self.period as u128,
+            BASIS_POINTS_MAX * SECONDS_PER_YEAR as u128,
+            Rounding::Down,
+        )?;
+        self.index = if rate_bps_applied >= 0 {
+            self.index.checked_add(change).ok_or(CustomError::MathError)?
+        } else {
+            self.index.checked_sub(change).ok_or(CustomError::MathError)?
+        };
+        require!(self.index > 0, CustomError::InvalidInflationRate);
+        self.last_rate_bps = rate_bps;
+        self.last_applied = now;
+        Ok(self.index)
+    }
+
+    /// Set the tolerable inflation rate and the deflation policy.
+    pub fn set_params(&mut self, tolerable_rate_bps: i32, deflation_policy: DeflationPolicy) -> Result<()> {
+        require!(
+            (0..=MAX_INFLATION_RATE_BPS).contains(&tolerable_rate_bps),
+            CustomError::InvalidInflationRate
+        );
+        self.tolerable_rate_bps = tolerable_rate_bps;
+        self.deflation_policy = deflation_policy;
+        Ok(())
+    }
+
+    /// Set the minimum time between two applications, which is also the time each application compounds.
+    pub fn set_period(&mut self, period: i64) -> Result<()> {
+        require!(period > 0 && period <= MAX_INFLATION_PERIOD, CustomError::InvalidInflationPeriod);
+        self.period = period;
+        Ok(())
+    }
+}
+
+/// Create the inflation index account with an index of 1.0.
+pub fn init_inflation_index(ctx: Context<InitInflationIndex>) -> Result<()> {
+    *ctx.accounts.inflation_index = InflationIndex::new(ctx.bumps.inflation_index);
+    msg!("Inflation index initialized at 1.0");
+    Ok(())
+}
+
+/// Apply one period of inflation at the annualized rate_bps and reprice every reserve,
+/// recording the reserves whose price histories are in the remaining accounts.
+pub fn apply_inflation(ctx: Context<UpdatePrices>, rate_bps: i32) -> Result<()> {
+    inflate(ctx.accounts, rate_bps, ctx.remaining_accounts)
+}
+
+pub(crate) fn inflate(accounts: &mut UpdatePrices, rate_bps: i32, histories: &[AccountInfo]) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let index = accounts.inflation_index.compound(rate_bps, now)?;
+    accounts.state.reprice(&accounts.protocol_config, index)?;
+    history::record_all(histories, &accounts.state, now)?;
+    msg!("Applied {} bps inflation, index now {}", rate_bps, index);
+    Ok(())
+}
+
+/// Set the tolerable inflation rate (bps per year) and the deflation policy.
+pub fn set_inflation_params(
+    ctx: Context<UpdatePrices>, tolerable_rate_bps: i32, deflation_policy: DeflationPolicy
+) -> Result<()> {
+    ctx.accounts.inflation_index.set_params(tolerable_rate_bps, deflation_policy)?;
+    msg!("Tolerable inflation set to {} bps, deflation policy {:?}", tolerable_rate_bps, deflation_policy);
+    Ok(())
+}
+
+/// Set the inflation period in seconds.
+pub fn set_inflation_period(ctx: Context<UpdatePrices>, period: i64) -> Result<()> {
+    ctx.accounts.inflation_index.set_period(period)?;
+    msg!("Inflation period set to {}s", period);
+    Ok(())
+}
@@ -2,14 +2,25 @@
 #![allow(unexpected_cfgs)]
 
 use anchor_lang::prelude::*;
-use std::mem::size_of;
 use std::str::FromStr;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 // use anchor_spl::token::ID as TOKEN_PROGRAM_ID;
 // use anchor_spl::token_2022::ID as TOKEN_2022_PROGRAM_ID;
 
 // Module declarations
 pub mod errors;
+pub mod fixed_point;
 pub mod pricing;
+pub mod allocation;
+pub mod custody;
+pub mod migration;
+pub mod config;
+pub mod inflation;
+pub mod oracle;
+pub mod attestation;
+pub mod aggregation;
+pub mod history;
+pub mod queue;
 pub mod position_manager;
 pub mod meteora_integration;
 pub mod pair_config;
@@ -17,7 +28,7 @@ pub mod bin_array_manager;
 pub mod utils;
 
 // Import the state structs from your modules, as they are used in the account definitions.
-pub use pricing::{StateMap, StableState};
+pub use pricing::{StateMap, StableState, FeePolicy, BasketDeposit, ReserveStatus};
 use errors::CustomError;
 
 // declare_program!(dlmm);
@@ -35,23 +46,27 @@ use commons::fetch_lb_pair_state;
 // Re-export types for IDL generation
 pub use position_manager::{AllPosition, SinglePosition, MintInfo, MintWithProgramId, TokenEntry};
 pub use meteora_integration::Core;
+pub use inflation::{InflationIndex, DeflationPolicy};
+pub use oracle::{FeedKind, MockPriceFeed};
+pub use attestation::{PriceAttestation, PriceAuthorities};
+pub use aggregation::PriceAggregator;
+pub use history::{PriceHistory, PriceObservation};
+pub use config::ProtocolConfig;
+pub use allocation::AllocationStrategy;
+pub use queue::{RedemptionQueue, QueuedRedemption};
 pub use pair_config::*;
 
 pub const IRMA_ID: Pubkey = crate::ID;
 
-#[derive(Clone, Debug, PartialEq, AnchorDeserialize, AnchorSerialize)]
+#[derive(Clone, Debug, Default, PartialEq, AnchorDeserialize, AnchorSerialize)]
 pub enum MarketMakingMode {
     ModeRight,
     ModeLeft,
     ModeBoth,
+    #[default]
     ModeView,
 }
 
-impl Default for MarketMakingMode {
-    fn default() -> Self {
-        MarketMakingMode::ModeView
-    }
-}
 
 impl FromStr for MarketMakingMode {
     type Err = anchor_lang::error::Error;
@@ -76,7 +91,7 @@ pub struct Init<'info> {
     // Note: We need to qualify MAX_BACKING_COUNT with its module
     #[account(
         init,
-        space=32 + 8 + size_of::<StableState>()*pricing::MAX_BACKING_COUNT,
+        space=8 + StateMap::LEN,
         payer=irma_admin, seeds=[b"state_v5".as_ref()],
         bump
     )]
@@ -110,10 +125,835 @@ pub struct Maint<'info> {
     pub irma_admin: Signer<'info>,
     #[account(mut, seeds=[b"core_v5".as_ref()], bump)]
     pub core: Account<'info, Core>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
     pub system_program: Program<'info, System>,
     // pub bumps: MaintBumps,
 }
 
+/// Context for changing reserve and redemption settings in the state (admin only).
+#[derive(Accounts)]
+pub struct ManageState<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+}
+
+/// Context for rewriting the state_v5 account from an older layout.
+/// The state account is unchecked because it does not deserialize as StateMap until migrated.
+#[derive(Accounts)]
+pub struct MigrateState<'info> {
+    /// CHECK: PDA verified by seeds; owner, discriminator and layout are checked in migration::migrate_state
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for creating the inflation index account (admin only).
+#[derive(Accounts)]
+pub struct InitInflationIndex<'info> {
+    #[account(
+        init,
+        space = 8 + InflationIndex::LEN,
+        payer = irma_admin,
+        seeds = [inflation::INFLATION_INDEX_SEED],
+        bump
+    )]
+    pub inflation_index: Account<'info, InflationIndex>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for instructions that change mint prices (admin only).
+/// The PriceHistory accounts of the repriced reserves are passed as remaining accounts.
+#[derive(Accounts)]
+pub struct UpdatePrices<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(mut, seeds = [inflation::INFLATION_INDEX_SEED], bump = inflation_index.bump)]
+    pub inflation_index: Account<'info, InflationIndex>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+}
+
+/// Context for creating the protocol config (admin only).
+#[derive(Accounts)]
+pub struct InitProtocolConfig<'info> {
+    #[account(
+        init,
+        space = 8 + ProtocolConfig::LEN,
+        payer = irma_admin,
+        seeds = [config::PROTOCOL_CONFIG_SEED],
+        bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for changing protocol parameters (admin only).
+#[derive(Accounts)]
+pub struct ManageProtocolConfig<'info> {
+    #[account(mut, seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+}
+
+/// Context for creating the price authority registry (admin only).
+#[derive(Accounts)]
+pub struct InitPriceAuthorities<'info> {
+    #[account(
+        init,
+        space = 8 + PriceAuthorities::LEN,
+        payer = irma_admin,
+        seeds = [attestation::PRICE_AUTHORITIES_SEED],
+        bump
+    )]
+    pub price_authorities: Account<'info, PriceAuthorities>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for adding or removing price authorities and price feeds (admin only).
+#[derive(Accounts)]
+pub struct ManagePriceAuthorities<'info> {
+    #[account(mut, seeds = [attestation::PRICE_AUTHORITIES_SEED], bump = price_authorities.bump)]
+    pub price_authorities: Account<'info, PriceAuthorities>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+}
+
+/// Context for relaying a signed price attestation; the relayer can be anyone.
+#[derive(Accounts)]
+pub struct SubmitSignedPrice<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [inflation::INFLATION_INDEX_SEED], bump = inflation_index.bump)]
+    pub inflation_index: Account<'info, InflationIndex>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut, seeds = [attestation::PRICE_AUTHORITIES_SEED], bump = price_authorities.bump)]
+    pub price_authorities: Account<'info, PriceAuthorities>,
+    #[account(
+        mut,
+        seeds = [aggregation::PRICE_AGGREGATOR_SEED, price_aggregator.mint.as_ref()],
+        bump = price_aggregator.bump
+    )]
+    pub price_aggregator: Account<'info, PriceAggregator>,
+    #[account(
+        mut,
+        seeds = [history::PRICE_HISTORY_SEED, price_aggregator.mint.as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    pub relayer: Signer<'info>,
+    /// CHECK: the instructions sysvar, checked by address
+    #[account(address = attestation::INSTRUCTIONS_SYSVAR_ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+/// Context for submitting a registered oracle feed's reading; the keeper can be anyone.
+/// The feed account is the first remaining account.
+#[derive(Accounts)]
+pub struct SubmitOraclePrice<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [inflation::INFLATION_INDEX_SEED], bump = inflation_index.bump)]
+    pub inflation_index: Account<'info, InflationIndex>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(seeds = [attestation::PRICE_AUTHORITIES_SEED], bump = price_authorities.bump)]
+    pub price_authorities: Account<'info, PriceAuthorities>,
+    #[account(
+        mut,
+        seeds = [aggregation::PRICE_AGGREGATOR_SEED, price_aggregator.mint.as_ref()],
+        bump = price_aggregator.bump
+    )]
+    pub price_aggregator: Account<'info, PriceAggregator>,
+    #[account(
+        mut,
+        seeds = [history::PRICE_HISTORY_SEED, price_aggregator.mint.as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    pub keeper: Signer<'info>,
+}
+
+/// Context for creating a reserve's price aggregator (admin only).
+#[derive(Accounts)]
+pub struct InitPriceAggregator<'info> {
+    #[account(seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        space = 8 + PriceAggregator::LEN,
+        payer = irma_admin,
+        seeds = [aggregation::PRICE_AGGREGATOR_SEED, reserve_mint.key().as_ref()],
+        bump
+    )]
+    pub price_aggregator: Account<'info, PriceAggregator>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for configuring a reserve's price aggregator (admin only).
+#[derive(Accounts)]
+pub struct ConfigurePriceAggregator<'info> {
+    #[account(
+        mut,
+        seeds = [aggregation::PRICE_AGGREGATOR_SEED, price_aggregator.mint.as_ref()],
+        bump = price_aggregator.bump
+    )]
+    pub price_aggregator: Account<'info, PriceAggregator>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+}
+
+/// Context for creating a reserve's price history (admin only).
+#[derive(Accounts)]
+pub struct InitPriceHistory<'info> {
+    #[account(seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        space = 8 + PriceHistory::LEN,
+        payer = irma_admin,
+        seeds = [history::PRICE_HISTORY_SEED, reserve_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for recording a reserve's current prices in its history; anyone may do this.
+#[derive(Accounts)]
+pub struct RecordPrice<'info> {
+    #[account(seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(mut, seeds = [history::PRICE_HISTORY_SEED, price_history.mint.as_ref()], bump = price_history.bump)]
+    pub price_history: Account<'info, PriceHistory>,
+    pub keeper: Signer<'info>,
+}
+
+/// Context for reading a reserve's time-weighted prices.
+#[derive(Accounts)]
+pub struct GetTwap<'info> {
+    #[account(seeds = [history::PRICE_HISTORY_SEED, price_history.mint.as_ref()], bump = price_history.bump)]
+    pub price_history: Account<'info, PriceHistory>,
+}
+
+/// Context for changing a USD rate beyond its bounds: the admin and the emergency authority both sign.
+#[derive(Accounts)]
+pub struct EmergencyUpdatePrices<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [inflation::INFLATION_INDEX_SEED], bump = inflation_index.bump)]
+    pub inflation_index: Account<'info, InflationIndex>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(seeds = [attestation::PRICE_AUTHORITIES_SEED], bump = price_authorities.bump)]
+    pub price_authorities: Account<'info, PriceAuthorities>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        constraint = emergency_authority.key() == price_authorities.emergency_authority @ CustomError::Unauthorized,
+        constraint = emergency_authority.key() != Pubkey::default() @ CustomError::Unauthorized
+    )]
+    pub emergency_authority: Signer<'info>,
+    #[account(mut, seeds = [history::PRICE_HISTORY_SEED, price_history.mint.as_ref()], bump = price_history.bump)]
+    pub price_history: Account<'info, PriceHistory>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+}
+
+/// Context for creating the vault that holds a reserve stablecoin (admin only).
+#[derive(Accounts)]
+pub struct CreateReserveVault<'info> {
+    #[account(seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    #[account(mint::token_program = token_program)]
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = irma_admin,
+        seeds = [custody::VAULT_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = token_program
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = irma_admin,
+        seeds = [custody::TREASURY_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = token_program
+    )]
+    pub treasury_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for withdrawing collected fees from a reserve's treasury vault (admin only).
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    #[account(mint::token_program = token_program)]
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [custody::TREASURY_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = token_program
+    )]
+    pub treasury_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = reserve_mint,
+        token::token_program = token_program
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for a user minting IRMA against a reserve stablecoin.
+/// The reserve is identified by reserve_mint; it may be an SPL Token or a Token-2022 mint.
+#[derive(Accounts)]
+pub struct MintIrma<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub user: Signer<'info>,
+    #[account(mint::token_program = reserve_token_program)]
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = reserve_mint,
+        token::authority = user,
+        token::token_program = reserve_token_program
+    )]
+    pub user_reserve_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::VAULT_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = reserve_token_program
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = pricing::IRMA.mint_address,
+        mint::authority = mint_authority,
+        mint::token_program = irma_token_program
+    )]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA mint authority of IRMA; it holds no data
+    #[account(seeds=[custody::MINT_AUTHORITY_SEED], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = irma_mint,
+        token::authority = user,
+        token::token_program = irma_token_program
+    )]
+    pub user_irma_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [history::PRICE_HISTORY_SEED, reserve_mint.key().as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    #[account(
+        mut,
+        seeds = [queue::REDEMPTION_QUEUE_SEED, reserve_mint.key().as_ref()],
+        bump = redemption_queue.bump
+    )]
+    pub redemption_queue: Account<'info, RedemptionQueue>,
+    #[account(
+        mut,
+        seeds = [queue::ESCROW_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = irma_mint,
+        token::authority = vault_authority,
+        token::token_program = irma_token_program
+    )]
+    pub irma_escrow: InterfaceAccount<'info, TokenAccount>,
+    pub reserve_token_program: Interface<'info, TokenInterface>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for a user redeeming IRMA for a reserve stablecoin held in its vault.
+#[derive(Accounts)]
+pub struct RedeemIrma<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub user: Signer<'info>,
+    #[account(mint::token_program = reserve_token_program)]
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = reserve_mint,
+        token::token_program = reserve_token_program
+    )]
+    pub user_reserve_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::VAULT_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = reserve_token_program
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::TREASURY_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = reserve_token_program
+    )]
+    pub treasury_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = pricing::IRMA.mint_address,
+        mint::token_program = irma_token_program
+    )]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = irma_mint,
+        token::authority = user,
+        token::token_program = irma_token_program
+    )]
+    pub user_irma_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [history::PRICE_HISTORY_SEED, reserve_mint.key().as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    pub reserve_token_program: Interface<'info, TokenInterface>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for a user minting IRMA from several reserve stablecoins at once.
+/// The accounts of each deposit follow as remaining accounts, see custody::BASKET_MINT_ACCOUNTS_PER_RESERVE.
+#[derive(Accounts)]
+pub struct MintBasket<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        address = pricing::IRMA.mint_address,
+        mint::authority = mint_authority,
+        mint::token_program = irma_token_program
+    )]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA mint authority of IRMA; it holds no data
+    #[account(seeds=[custody::MINT_AUTHORITY_SEED], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+    /// CHECK: PDA that owns all reserve vaults and IRMA escrows; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = irma_mint,
+        token::authority = user,
+        token::token_program = irma_token_program
+    )]
+    pub user_irma_account: InterfaceAccount<'info, TokenAccount>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for a user redeeming IRMA for a share of every reserve open for redemption.
+/// The accounts of each reserve follow as remaining accounts, see custody::BASKET_ACCOUNTS_PER_RESERVE.
+#[derive(Accounts)]
+pub struct RedeemBasket<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub user: Signer<'info>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = pricing::IRMA.mint_address,
+        mint::token_program = irma_token_program
+    )]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = irma_mint,
+        token::authority = user,
+        token::token_program = irma_token_program
+    )]
+    pub user_irma_account: InterfaceAccount<'info, TokenAccount>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for swapping one reserve stablecoin for another through their vaults.
+#[derive(Accounts)]
+pub struct SwapReserves<'info> {
+    #[account(mut, seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub user: Signer<'info>,
+    #[account(mint::token_program = from_token_program)]
+    pub from_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = to_token_program)]
+    pub to_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = from_mint,
+        token::authority = user,
+        token::token_program = from_token_program
+    )]
+    pub user_from_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = to_mint,
+        token::token_program = to_token_program
+    )]
+    pub user_to_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::VAULT_SEED, from_mint.key().as_ref()],
+        bump,
+        token::mint = from_mint,
+        token::authority = vault_authority,
+        token::token_program = from_token_program
+    )]
+    pub from_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::VAULT_SEED, to_mint.key().as_ref()],
+        bump,
+        token::mint = to_mint,
+        token::authority = vault_authority,
+        token::token_program = to_token_program
+    )]
+    pub to_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::TREASURY_SEED, to_mint.key().as_ref()],
+        bump,
+        token::mint = to_mint,
+        token::authority = vault_authority,
+        token::token_program = to_token_program
+    )]
+    pub to_treasury_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [history::PRICE_HISTORY_SEED, from_mint.key().as_ref()],
+        bump = from_price_history.bump
+    )]
+    pub from_price_history: Account<'info, PriceHistory>,
+    #[account(
+        mut,
+        seeds = [history::PRICE_HISTORY_SEED, to_mint.key().as_ref()],
+        bump = to_price_history.bump
+    )]
+    pub to_price_history: Account<'info, PriceHistory>,
+    #[account(
+        mut,
+        seeds = [queue::REDEMPTION_QUEUE_SEED, from_mint.key().as_ref()],
+        bump = from_redemption_queue.bump
+    )]
+    pub from_redemption_queue: Account<'info, RedemptionQueue>,
+    #[account(
+        mut,
+        seeds = [queue::ESCROW_SEED, from_mint.key().as_ref()],
+        bump,
+        token::mint = irma_mint,
+        token::authority = vault_authority,
+        token::token_program = irma_token_program
+    )]
+    pub from_irma_escrow: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pricing::IRMA.mint_address, mint::token_program = irma_token_program)]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    pub from_token_program: Interface<'info, TokenInterface>,
+    pub to_token_program: Interface<'info, TokenInterface>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for creating a reserve's redemption queue and its IRMA escrow (admin only).
+#[derive(Accounts)]
+pub struct InitRedemptionQueue<'info> {
+    #[account(seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        space = 8 + RedemptionQueue::LEN,
+        payer = irma_admin,
+        seeds = [queue::REDEMPTION_QUEUE_SEED, reserve_mint.key().as_ref()],
+        bump
+    )]
+    pub redemption_queue: Account<'info, RedemptionQueue>,
+    #[account(address = pricing::IRMA.mint_address, mint::token_program = irma_token_program)]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = irma_admin,
+        seeds = [queue::ESCROW_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = irma_mint,
+        token::authority = vault_authority,
+        token::token_program = irma_token_program
+    )]
+    pub irma_escrow: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults and escrows; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub irma_admin: Signer<'info>,
+    #[account(
+        seeds=[b"core_v5".as_ref()],
+        bump,
+        constraint = core.owner == irma_admin.key() @ CustomError::Unauthorized
+    )]
+    pub core: Account<'info, Core>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Context for a user escrowing IRMA in a reserve's redemption queue.
+#[derive(Accounts)]
+pub struct QueueRedemption<'info> {
+    #[account(seeds=[b"state_v5".as_ref()], bump)]
+    pub state: Account<'info, StateMap>,
+    #[account(seeds = [config::PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub user: Signer<'info>,
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [queue::REDEMPTION_QUEUE_SEED, reserve_mint.key().as_ref()],
+        bump = redemption_queue.bump
+    )]
+    pub redemption_queue: Account<'info, RedemptionQueue>,
+    #[account(address = pricing::IRMA.mint_address, mint::token_program = irma_token_program)]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = irma_mint,
+        token::authority = user,
+        token::token_program = irma_token_program
+    )]
+    pub user_irma_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [queue::ESCROW_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = irma_mint,
+        token::token_program = irma_token_program
+    )]
+    pub irma_escrow: InterfaceAccount<'info, TokenAccount>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for a user cancelling an unfilled queued redemption.
+#[derive(Accounts)]
+pub struct CancelRedemption<'info> {
+    pub user: Signer<'info>,
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [queue::REDEMPTION_QUEUE_SEED, reserve_mint.key().as_ref()],
+        bump = redemption_queue.bump
+    )]
+    pub redemption_queue: Account<'info, RedemptionQueue>,
+    #[account(address = pricing::IRMA.mint_address, mint::token_program = irma_token_program)]
+    pub irma_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = irma_mint,
+        token::token_program = irma_token_program
+    )]
+    pub user_irma_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [queue::ESCROW_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = irma_mint,
+        token::authority = vault_authority,
+        token::token_program = irma_token_program
+    )]
+    pub irma_escrow: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults and escrows; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub irma_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Context for a user claiming the payout of a filled queued redemption.
+#[derive(Accounts)]
+pub struct ClaimRedemption<'info> {
+    pub user: Signer<'info>,
+    #[account(mint::token_program = reserve_token_program)]
+    pub reserve_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [queue::REDEMPTION_QUEUE_SEED, reserve_mint.key().as_ref()],
+        bump = redemption_queue.bump
+    )]
+    pub redemption_queue: Account<'info, RedemptionQueue>,
+    #[account(
+        mut,
+        token::mint = reserve_mint,
+        token::token_program = reserve_token_program
+    )]
+    pub user_reserve_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::VAULT_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = reserve_token_program
+    )]
+    pub reserve_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [custody::TREASURY_SEED, reserve_mint.key().as_ref()],
+        bump,
+        token::mint = reserve_mint,
+        token::authority = vault_authority,
+        token::token_program = reserve_token_program
+    )]
+    pub treasury_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA that owns all reserve vaults; it holds no data
+    #[account(seeds=[custody::VAULT_AUTHORITY_SEED], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub reserve_token_program: Interface<'info, TokenInterface>,
+}
+
 /// Context to force Core and related types into IDL
 #[derive(Accounts)]
 pub struct GetCoreData<'info> {
@@ -150,7 +990,7 @@ pub mod irma {
     ) -> Result<()> {
         let owner_pk = Pubkey::from_str(&owner).unwrap();
 
-        assert_eq!(config_keys.len() == 0, true);
+        assert!(config_keys.is_empty());
 
         let config_pks: Vec<Pubkey> = config_keys.iter()
             .map(|key| Pubkey::from_str(key).unwrap())
@@ -164,7 +1004,7 @@ pub mod irma {
         msg!("IRMA protocol initialized with owner: {}", owner_pk);
         msg!("Config keys count: {}", config_pks.len());
 
-        assert_eq!(config_pks.len() == 0, true);
+        assert!(config_pks.is_empty());
 
         let core = Core::create_core(owner_pk, config_pks)?;
         ctx.accounts.core.set_inner(core);
@@ -181,6 +1021,7 @@ pub mod irma {
         pricing::add_reserve(ctx, &symbol, mint_address, decimals)
     }
 
+    /// Remove a reserve that is still Pending; reserves that were ever Active are retired instead.
     pub fn remove_reserve(
         ctx: Context<Maint>,
         symbol: String
@@ -188,13 +1029,25 @@ pub mod irma {
         pricing::remove_reserve(ctx, &symbol)
     }
 
+    /// Pause minting against a reserve; same as set_reserve_status to MintPaused.
+    /// Only the Core owner may call this.
     pub fn disable_reserve(
-        ctx: Context<Maint>,
+        ctx: Context<ManageState>,
         symbol: String
     ) -> Result<()> {
         pricing::disable_reserve(ctx, &symbol)
     }
 
+    /// Move a reserve along its lifecycle: Pending -> Active once it has an LbPair, Active <-> MintPaused,
+    /// either of those -> RedeemOnly to wind it down, and RedeemOnly -> Retired. Only the Core owner may call this.
+    pub fn set_reserve_status(
+        ctx: Context<ManageState>,
+        symbol: String,
+        status: ReserveStatus
+    ) -> Result<()> {
+        pricing::set_reserve_status(ctx, &symbol, status)
+    }
+
     /// This connects a reserve stablecoin to its corresponding LBPair.
     /// There can only be a single LbPair per stablecoin reserve.
     pub fn update_reserve_lbpair<'info>(
@@ -205,7 +1058,7 @@ pub mod irma {
             let stablecoin = ctx.accounts.state.reserves.iter().find(|r| r.symbol == symbol)
                 .ok_or(error!(CustomError::ReserveNotFound))?;
             let lb_pair_state = fetch_lb_pair_state(
-                &ctx.remaining_accounts,
+                ctx.remaining_accounts,
                 &lb_pair_key,
             )?;
             // check that the input LbPair is valid and matches the reserve stablecoin mint
@@ -225,10 +1078,10 @@ pub mod irma {
                 mode: MarketMakingMode::ModeBoth,
             });
             core_mut.position_data.all_positions.push(
-                position_manager::SinglePosition::new(lb_pair_key.clone())
+                position_manager::SinglePosition::new(lb_pair_key)
             );
             // Skip fetch_token_info to avoid lifetime issues in Anchor instructions
-            let _ = core_mut.fetch_token_info(&ctx.remaining_accounts)?;
+            core_mut.fetch_token_info(ctx.remaining_accounts)?;
             // remove extraneous LbPair configs if any
             let reserves = &ctx.accounts.state.reserves;
             for i in (0..core.config.len()).rev() {
@@ -242,24 +1095,316 @@ pub mod irma {
         let reserves = &mut ctx.accounts.state.reserves;
         let stablecoin_mut = &mut reserves.iter_mut().find(|r| r.symbol == symbol)
             .ok_or(error!(CustomError::ReserveNotFound))?;
-        stablecoin_mut.pool_id = lb_pair_key.clone();
+        stablecoin_mut.pool_id = lb_pair_key;
         Ok(())
     }
 
+    /// Rewrite the state account written by an earlier program version into the current layout
+    /// (Q64.64 mint prices, amounts in base units). Only the Core owner may call this.
+    pub fn migrate_state(ctx: Context<MigrateState>) -> Result<()> {
+        migration::migrate_state(ctx)
+    }
+
+    /// Create the vault PDA that holds the given reserve stablecoin. Only the Core owner may call this.
+    pub fn create_reserve_vault(ctx: Context<CreateReserveVault>) -> Result<()> {
+        custody::create_reserve_vault(ctx)
+    }
+
+    /// Mint IRMA: deposit amount (base units) of a reserve stablecoin into its vault
+    /// and receive IRMA at the current mint price. The remaining accounts may hold reserves' LbPair
+    /// accounts, to signal redemption positions that queued redemptions filled by the mint moved.
+    pub fn mint<'info>(ctx: Context<'_, '_, 'info, 'info, MintIrma<'info>>, amount: u64) -> Result<()> {
+        custody::mint(ctx, amount)
+    }
+
+    /// Redeem IRMA: burn irma_amount (base units) of IRMA and receive the reserve stablecoin
+    /// from its vault at the current redemption price. The remaining accounts may hold the LbPair
+    /// accounts of all reserves with a pool, or none, for distribute to weigh market prices.
+    pub fn redeem<'info>(ctx: Context<'_, '_, 'info, 'info, RedeemIrma<'info>>, irma_amount: u64) -> Result<()> {
+        custody::redeem(ctx, irma_amount)
+    }
+
+    /// Create a reserve's redemption queue and IRMA escrow. Only the Core owner may call this.
+    pub fn init_redemption_queue(ctx: Context<InitRedemptionQueue>) -> Result<()> {
+        queue::init_redemption_queue(ctx)
+    }
+
+    /// Escrow irma_amount (base units) of IRMA to be redeemed for the reserve stablecoin once mints
+    /// replenish it, at min_price (Q64.64 reserve tokens per IRMA, after the fee) or better.
+    pub fn queue_redemption(ctx: Context<QueueRedemption>, irma_amount: u64, min_price: u128) -> Result<()> {
+        queue::queue_redemption(ctx, irma_amount, min_price)
+    }
+
+    /// Cancel an unfilled queued redemption and get the escrowed IRMA back.
+    pub fn cancel_redemption(ctx: Context<CancelRedemption>, id: u64) -> Result<()> {
+        queue::cancel_redemption(ctx, id)
+    }
+
+    /// Receive the payout of a filled queued redemption.
+    pub fn claim_redemption(ctx: Context<ClaimRedemption>, id: u64) -> Result<()> {
+        queue::claim_redemption(ctx, id)
+    }
+
+    /// Mint IRMA from several reserve stablecoins in one step; deposits are (symbol, amount in base units).
+    /// Fails as a whole if any reserve is closed for minting, stale or below its minimum. The remaining accounts hold,
+    /// for each deposit in order, the reserve mint, the user's token account, the vault, price history
+    /// and token program.
+    pub fn mint_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MintBasket<'info>>, deposits: Vec<BasketDeposit>
+    ) -> Result<()> {
+        custody::mint_basket(ctx, deposits)
+    }
+
+    /// Redeem irma_amount (base units) of IRMA for a share of every reserve open for redemption, in proportion
+    /// to the USD value of its backing. The remaining accounts hold, for each such reserve in state order, its
+    /// mint, vault, treasury vault, the user's token account, price history and token program, optionally
+    /// followed by reserves' LbPair accounts to signal the redemption positions the redemption moved.
+    pub fn redeem_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RedeemBasket<'info>>, irma_amount: u64
+    ) -> Result<()> {
+        custody::redeem_basket(ctx, irma_amount)
+    }
+
+    /// Swap amount (base units) of one reserve stablecoin for another, paying the redemption fee.
+    /// Fails if the output would be less than min_out. The remaining accounts may hold reserves' LbPair
+    /// accounts, to signal the redemption positions the swap moved.
+    pub fn swap_reserves<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapReserves<'info>>,
+        from_symbol: String,
+        to_symbol: String,
+        amount: u64,
+        min_out: u64
+    ) -> Result<()> {
+        custody::swap_reserves(ctx, &from_symbol, &to_symbol, amount, min_out)
+    }
+
     pub fn list_reserves(ctx: Context<Maint>) -> Result<String> {
         Ok(pricing::list_reserves(ctx))
     }
 
-    pub fn get_redemption_price(ctx: Context<Maint>, quote_token: String) -> Result<f64> {
+    /// Returns the redemption price as a Q64.64 fixed-point number.
+    pub fn get_redemption_price(ctx: Context<Maint>, quote_token: String) -> Result<u128> {
         pricing::get_redemption_price(&ctx.accounts.state.reserves, &quote_token)
     }
     
-    pub fn get_prices(ctx: Context<Maint>, quote_token: String) -> Result<(f64, f64)> {
+    /// Returns (mint price, redemption price) as Q64.64 fixed-point numbers.
+    pub fn get_prices(ctx: Context<Maint>, quote_token: String) -> Result<(u128, u128)> {
         pricing::get_prices(&ctx.accounts.state.reserves, &quote_token)
     }
 
-    pub fn set_mint_price(ctx: Context<Maint>, quote_token: String, new_price: f64) -> Result<()> {
-        pricing::set_mint_price(ctx, &quote_token, new_price)
+    /// Set the redemption fee in basis points (at most 1%) and whether fees go to the backing or the treasury.
+    /// Only the Core owner may call this.
+    pub fn set_fee_policy(ctx: Context<ManageState>, redemption_fee_bps: u16, fee_policy: FeePolicy) -> Result<()> {
+        pricing::set_fee_policy(ctx, redemption_fee_bps, fee_policy)
+    }
+
+    /// Select how distribute takes redeemed IRMA off the reserves' circulation. Only the Core owner may call this.
+    pub fn set_allocation_strategy(ctx: Context<ManageState>, strategy: AllocationStrategy) -> Result<()> {
+        allocation::set_allocation_strategy(ctx, strategy)
+    }
+
+    /// Withdraw amount (base units) of fees from a reserve's treasury vault. Only the Core owner may call this.
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        custody::withdraw_fees(ctx, amount)
+    }
+
+    /// Create the protocol config with the default parameters. Only the Core owner may call this.
+    pub fn init_protocol_config(ctx: Context<InitProtocolConfig>) -> Result<()> {
+        config::init_protocol_config(ctx)
+    }
+
+    /// Set the minimum mint (reserve base units) and the maximum redemption per transaction (whole IRMA).
+    /// Only the Core owner may call this.
+    pub fn set_mint_redeem_limits(
+        ctx: Context<ManageProtocolConfig>, min_mint_amount: u64, max_redeem_amount: u64
+    ) -> Result<()> {
+        config::set_mint_redeem_limits(ctx, min_mint_amount, max_redeem_amount)
+    }
+
+    /// Set the mint price (Q64.64, above 1.0 and at most 10,000) at which minting against a reserve stops.
+    /// Only the Core owner may call this.
+    pub fn set_max_mint_price(ctx: Context<ManageProtocolConfig>, max_mint_price: u128) -> Result<()> {
+        config::set_max_mint_price(ctx, max_mint_price)
+    }
+
+    /// Set the spread difference (Q64.64, at most 0.1) below which redemptions only adjust their own reserve.
+    /// Only the Core owner may call this.
+    pub fn set_min_price_diff(ctx: Context<ManageProtocolConfig>, min_price_diff: u128) -> Result<()> {
+        config::set_min_price_diff(ctx, min_price_diff)
+    }
+
+    /// Set the slippage allowed on DLMM swaps, in basis points (at most 10%). Only the Core owner may call this.
+    pub fn set_slippage(ctx: Context<ManageProtocolConfig>, slippage_bps: u16) -> Result<()> {
+        config::set_slippage(ctx, slippage_bps)
+    }
+
+    /// Set the amounts deposited into minting (IRMA base units) and redemption (reserve base units)
+    /// positions when they are shifted. Only the Core owner may call this.
+    pub fn set_position_amounts(
+        ctx: Context<ManageProtocolConfig>, minting_position_amount: u64, redemption_position_amount: u64
+    ) -> Result<()> {
+        config::set_position_amounts(ctx, minting_position_amount, redemption_position_amount)
+    }
+
+    /// Create the inflation index (1.0) that all mint prices derive from. Only the Core owner may call this.
+    pub fn init_inflation_index(ctx: Context<InitInflationIndex>) -> Result<()> {
+        inflation::init_inflation_index(ctx)
+    }
+
+    /// Compound one period of inflation at the annualized rate_bps into the index and reprice
+    /// every reserve. Can be applied at most once per period. Rates within the tolerable band leave
+    /// the index unchanged; a negative rate lowers it unless the deflation policy is Hold.
+    /// The reserves' price histories are passed as remaining accounts.
+    pub fn apply_inflation(ctx: Context<UpdatePrices>, rate_bps: i32) -> Result<()> {
+        inflation::apply_inflation(ctx, rate_bps)
+    }
+
+    /// Set the tolerable annual inflation (bps) below which apply_inflation leaves the index alone,
+    /// and whether deflation lowers the index.
+    pub fn set_inflation_params(
+        ctx: Context<UpdatePrices>, tolerable_rate_bps: i32, deflation_policy: DeflationPolicy
+    ) -> Result<()> {
+        inflation::set_inflation_params(ctx, tolerable_rate_bps, deflation_policy)
+    }
+
+    /// Set the minimum time in seconds between two applications of inflation, each of which compounds
+    /// one period of the annual rate. Must be positive and at most a year.
+    pub fn set_inflation_period(ctx: Context<UpdatePrices>, period: i64) -> Result<()> {
+        inflation::set_inflation_period(ctx, period)
+    }
+
+    /// usd_rate is the number of quote_token per USD, as a Q64.64 fixed-point number (1.0 == 1 << 64).
+    /// The mint price becomes inflation index * usd_rate. The change must be within the reserve's price bounds.
+    /// The reserve's price history is passed as a remaining account.
+    pub fn set_usd_rate(ctx: Context<UpdatePrices>, quote_token: String, usd_rate: u128) -> Result<()> {
+        pricing::set_usd_rate(ctx, &quote_token, usd_rate)
+    }
+
+    /// Set quote_token's USD rate even if the change exceeds the reserve's bounds, starting a new
+    /// bound window. Both the Core owner and the emergency authority must sign.
+    pub fn emergency_set_usd_rate(
+        ctx: Context<EmergencyUpdatePrices>, quote_token: String, usd_rate: u128
+    ) -> Result<()> {
+        pricing::emergency_set_usd_rate(ctx, &quote_token, usd_rate)
+    }
+
+    /// Set the IRMA (whole tokens) that may be redeemed from a reserve per redemption window; 0 for no cap.
+    /// Only the Core owner may call this.
+    pub fn set_reserve_redemption_cap(ctx: Context<ManageState>, quote_token: String, redeem_window_cap: u64) -> Result<()> {
+        pricing::set_reserve_redemption_cap(ctx, &quote_token, redeem_window_cap)
+    }
+
+    /// Set the redemption window in seconds and the IRMA (whole tokens) that may be redeemed from all
+    /// reserves together per window; 0 for no cap. Only the Core owner may call this.
+    pub fn set_redemption_window(ctx: Context<ManageState>, redeem_window: u32, redeem_window_cap: u64) -> Result<()> {
+        pricing::set_redemption_window(ctx, redeem_window, redeem_window_cap)
+    }
+
+    /// Set how many seconds quote_token's USD rate may go without an update before minting against it stops.
+    /// Only the Core owner may call this.
+    pub fn set_max_price_age(ctx: Context<ManageState>, quote_token: String, max_price_age: u32) -> Result<()> {
+        pricing::set_max_price_age(ctx, &quote_token, max_price_age)
+    }
+
+    /// Set the largest change of quote_token's USD rate, in basis points, per update and per day.
+    /// Only the Core owner may call this.
+    pub fn set_price_bounds(
+        ctx: Context<ManageState>, quote_token: String, max_update_change_bps: u16, max_daily_change_bps: u16
+    ) -> Result<()> {
+        pricing::set_price_bounds(ctx, &quote_token, max_update_change_bps, max_daily_change_bps)
+    }
+
+    /// Set a reserve's minimum mint in whole reserve tokens and maximum redemption per transaction in
+    /// whole IRMA. Zero uses the protocol-wide limit from ProtocolConfig. Only the Core owner may call this.
+    pub fn set_reserve_limits(
+        ctx: Context<ManageState>, quote_token: String, min_mint_amount: u64, max_redeem_amount: u64
+    ) -> Result<()> {
+        pricing::set_reserve_limits(ctx, &quote_token, min_mint_amount, max_redeem_amount)
+    }
+
+    /// Submit quote_token's USD rate from a price feed (USD per token), passed as the first remaining
+    /// account, to the reserve's price aggregator. Anyone may call this. The feed must be a registered
+    /// price source, owned by the program feed_kind expects, recent, and within confidence bounds.
+    pub fn update_usd_rate_from_oracle(
+        ctx: Context<SubmitOraclePrice>, quote_token: String, feed_kind: FeedKind
+    ) -> Result<()> {
+        oracle::update_usd_rate_from_oracle(ctx, &quote_token, feed_kind)
+    }
+
+    /// Create the registry of keys allowed to sign price attestations. Only the Core owner may call this.
+    pub fn init_price_authorities(ctx: Context<InitPriceAuthorities>) -> Result<()> {
+        attestation::init_price_authorities(ctx)
+    }
+
+    /// Register a key whose signed price attestations anyone may relay. Only the Core owner may call this.
+    pub fn add_price_authority(ctx: Context<ManagePriceAuthorities>, authority: Pubkey) -> Result<()> {
+        attestation::add_price_authority(ctx, authority)
+    }
+
+    /// Deregister a price authority. Only the Core owner may call this.
+    pub fn remove_price_authority(ctx: Context<ManagePriceAuthorities>, authority: Pubkey) -> Result<()> {
+        attestation::remove_price_authority(ctx, authority)
+    }
+
+    /// Register an oracle feed account whose readings update_usd_rate_from_oracle accepts.
+    /// Only the Core owner may call this.
+    pub fn add_price_feed(ctx: Context<ManagePriceAuthorities>, feed: Pubkey) -> Result<()> {
+        attestation::add_price_feed(ctx, feed)
+    }
+
+    /// Deregister an oracle feed account. Only the Core owner may call this.
+    pub fn remove_price_feed(ctx: Context<ManagePriceAuthorities>, feed: Pubkey) -> Result<()> {
+        attestation::remove_price_feed(ctx, feed)
+    }
+
+    /// Set the key that must co-sign emergency_set_usd_rate. Only the Core owner may call this.
+    pub fn set_emergency_authority(ctx: Context<ManagePriceAuthorities>, emergency_authority: Pubkey) -> Result<()> {
+        attestation::set_emergency_authority(ctx, emergency_authority)
+    }
+
+    /// Submit a USD rate signed by a registered price authority to the reserve's price aggregator.
+    /// Anyone may submit it; the preceding instruction must be the Ed25519 program verifying
+    /// the authority's signature over the attestation.
+    pub fn submit_signed_price(
+        ctx: Context<SubmitSignedPrice>, authority: Pubkey, attestation: PriceAttestation
+    ) -> Result<()> {
+        attestation::submit_signed_price(ctx, authority, attestation)
+    }
+
+    /// Create a listed reserve's price aggregator. Only the Core owner may call this.
+    pub fn init_price_aggregator(ctx: Context<InitPriceAggregator>) -> Result<()> {
+        aggregation::init_price_aggregator(ctx)
+    }
+
+    /// Set how many price sources must agree (quorum), how long a submission stays valid (window, seconds)
+    /// and how far from the median a submission may be (max_deviation_bps). Only the Core owner may call this.
+    pub fn configure_price_aggregator(
+        ctx: Context<ConfigurePriceAggregator>, quorum: u8, window: i64, max_deviation_bps: u16
+    ) -> Result<()> {
+        aggregation::configure_price_aggregator(ctx, quorum, window, max_deviation_bps)
+    }
+
+    /// Create a listed reserve's price history. Only the Core owner may call this.
+    pub fn init_price_history(ctx: Context<InitPriceHistory>) -> Result<()> {
+        history::init_price_history(ctx)
+    }
+
+    /// Record a reserve's current mint price, redemption price, backing and circulation in its history.
+    /// Anyone may call this.
+    pub fn record_price(ctx: Context<RecordPrice>) -> Result<()> {
+        history::record_price(ctx)
+    }
+
+    /// Returns the time-weighted (mint price, redemption price) of a reserve over the last window seconds,
+    /// as Q64.64 fixed-point numbers. Fails if the history does not cover the whole window.
+    pub fn get_twap(ctx: Context<GetTwap>, window: i64) -> Result<(u128, u128)> {
+        history::get_twap(ctx, window)
+    }
+
+    /// Apply one period of inflation at the annual rate read from an inflation feed
+    /// (e.g. Truflation) passed as the first remaining account; the reserves' price histories follow it.
+    pub fn apply_inflation_from_oracle(ctx: Context<UpdatePrices>, feed_kind: FeedKind) -> Result<()> {
+        oracle::apply_inflation_from_oracle(ctx, feed_kind)
     }
 
     // NOTE: In the two functions below, the Common accounts struct previously allowed the trader herself
@@ -278,22 +1423,34 @@ pub mod irma {
         // Extract references to avoid double mutable borrow
         let core = &mut ctx.accounts.core;
         let state = &mut ctx.accounts.state;
+        let protocol_config = &ctx.accounts.protocol_config;
         let remaining_accounts = ctx.remaining_accounts;
 
-        core.refresh_position_data_with_accounts(state, &remaining_accounts, bought_token, bought_amount, true)
+        core.refresh_position_data_with_accounts(
+            state, protocol_config, remaining_accounts, bought_token, bought_amount, true)
     }
 
     /// Let pricing know about a buy-back trade event
     /// Note that IRMA is what we are buying back (burning) and we just sold the backing token.
-    pub fn buy_trade_event(
-        ctx: Context<Maint>, sold_token: String, irma_amount: u64
+    /// Redemption positions whose price moved to another bin of a pair among the remaining accounts are
+    /// shifted right away; the remaining accounts must then also hold what check_shift_price_ranges needs
+    /// for that pair. Positions whose LbPair is not passed wait for the check_shift_price_ranges crank.
+    pub fn buy_trade_event<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Maint<'info>>, sold_token: String, irma_amount: u64
     ) -> Result<()> {
         // Extract references to avoid double mutable borrow
         let core = &mut ctx.accounts.core;
+        let payer = &mut ctx.accounts.irma_admin;
         let state = &mut ctx.accounts.state;
-        let remaining_accounts = ctx.remaining_accounts;
+        let protocol_config = &ctx.accounts.protocol_config;
+        let remaining_accounts: &[AccountInfo<'info>] = ctx.remaining_accounts;
 
-        core.refresh_position_data_with_accounts(state, &remaining_accounts, sold_token, irma_amount, false)
+        let prices_before = pricing::redemption_prices(&state.reserves)?;
+        core.refresh_position_data_with_accounts(
+            state, protocol_config, remaining_accounts, sold_token, irma_amount, false)?;
+        let pools = allocation::pool_states(&state.reserves, remaining_accounts)?;
+        let crossed = pricing::crossed_redemption_bins(&prices_before, &state.reserves, &pools)?;
+        core.shift_crossed_redemption_positions(payer, remaining_accounts, &state.reserves, protocol_config, &crossed)
     }
 
     /// Check all LB pair positions and update from pricing.rs/
@@ -306,7 +1463,8 @@ pub mod irma {
         let core = &mut ctx.accounts.core;
         let payer = &mut ctx.accounts.irma_admin;
         let reserves = &mut ctx.accounts.state.reserves;
-        let remaining_accounts: &[AccountInfo<'info>] = &ctx.remaining_accounts;
+        let protocol_config = &ctx.accounts.protocol_config;
+        let remaining_accounts: &[AccountInfo<'info>] = ctx.remaining_accounts;
 
         for position in corei.position_data.all_positions.iter_mut() {
             Core::check_shift_price_range(
@@ -314,6 +1472,7 @@ pub mod irma {
                 payer,
                 remaining_accounts,
                 reserves,
+                protocol_config,
                 position,
             )?;
         }
@@ -321,8 +1480,8 @@ pub mod irma {
         Ok(())
     }
 
-    /// Helper instruction to ensure Core type is included in IDL
-    /// Returns the Core account data for debugging
+    // Helper instruction to ensure Core type is included in IDL
+    // Returns the Core account data for debugging
     // pub fn get_core_data<'a>(ctx: Context<'a, Core>) -> Result<Account<'a, Core>> {
     //     // Simple read operation to include Core type in IDL
     //     let core = &ctx.accounts.core;
@@ -15,6 +15,9 @@ use commons::{
 use crate::position_manager::*;
 use crate::pair_config::*;
 use crate::pricing;
+use crate::allocation;
+use crate::config::ProtocolConfig;
+use crate::fixed_point::{self, FixedPrice};
 use crate::errors::CustomError;
 use crate::IRMA_ID;
 use crate::{Maint, StateMap, StableState};
@@ -41,10 +44,6 @@ pub enum AccountData<T> {
     Anchor(T),
 }
 
-const MAX_POSITIONS: usize = 3; // usually 2, but allow 3 during shifts
-const MINTING_POSITION_AMOUNT: u64 = 1_100_000_000; // 1.1 billion units for minting positions
-const REDEMPTION_POSITION_AMOUNT: u64 = 100_000_000; // 100 million units for redemption positions
-
 impl<T> AccountData<T> {
     pub fn into_inner(self) -> T {
         match self {
@@ -82,7 +81,7 @@ impl Core {
         Ok(Core {
             owner,
             config: vec![],
-            position_data: AllPosition::new(&vec![]).unwrap(),
+            position_data: AllPosition::new(&[]).unwrap(),
         })
     }
 
@@ -94,7 +93,7 @@ impl Core {
 
     fn get_multiple_anchor_accounts<T: anchor_lang::AccountDeserialize + std::fmt::Debug>(
         remaining_accounts: &[AccountInfo],
-        pubkeys: &Vec<Pubkey>
+        pubkeys: &[Pubkey]
     ) -> Result<HashMap<Pubkey, Option<T>>> {
         msg!("==> get_multiple_anchor_accounts: {} pubkeys", pubkeys.len());
         let mut data = HashMap::new();
@@ -161,10 +160,10 @@ impl Core {
                     key.as_ref(),
                     &[bump],
                 ];
-                invoke_signed(&instruction, remaining_accounts, &[seeds])?;
+                invoke_signed(instruction, remaining_accounts, &[seeds])?;
             }
             else {
-                invoke(&instruction, remaining_accounts)?;
+                invoke(instruction, remaining_accounts)?;
             }
         }
         Ok(())
@@ -174,6 +173,7 @@ impl Core {
     pub fn refresh_position_data_with_accounts(
         &mut self,
         state: &mut Account<StateMap>,
+        config: &ProtocolConfig,
         remaining_accounts: &[AccountInfo],
         token: String, // symbol of the stablecoin
         amount: u64,
@@ -181,9 +181,9 @@ impl Core {
     ) -> Result<()> {
         // Call pricing functions directly on the state first
         if is_sale {
-            pricing::mint_irma(state, &token, amount)?;
+            pricing::mint_irma(state, config, &token, amount, Clock::get()?.unix_timestamp)?;
         } else {
-            pricing::redeem_irma(state, &token, amount)?;
+            pricing::redeem_irma(state, config, &token, amount, Clock::get()?.unix_timestamp)?;
         }
 
         // Call the core position refresh logic without needing a full context
@@ -219,7 +219,7 @@ impl Core {
             remaining_accounts,
             &self.owner, 
             &pair_address
-        ).or_else(|error| Err(error)).unwrap();
+        ).unwrap();
 
         let mut position_pks = vec![];
         // Note: We'll fetch positions and bin_arrays dynamically when needed
@@ -230,10 +230,10 @@ impl Core {
 
         msg!("    Found {} positions", position_key_with_state.len());
         let mut bin_array_keys = vec![];
-        if position_key_with_state.len() > 0 {
+        if !position_key_with_state.is_empty() {
             // sort position by bin id
             position_key_with_state
-                .sort_by(|(_, a), (_, b)| a.lower_bin_id.cmp(&b.lower_bin_id));
+                .sort_by_key(|(_, a)| a.lower_bin_id);
 
             min_bin_id = position_key_with_state
                 .first()
@@ -432,7 +432,7 @@ impl Core {
         state: &SinglePosition,
         old_position_key: Pubkey, // we should not withdraw from the new position
     ) -> Result<()> {
-        if state.position_pks.len() == 0 {
+        if state.position_pks.is_empty() {
             return Ok(());
         }
 
@@ -448,7 +448,7 @@ impl Core {
 
         if let Some((slices, remaining_accounts)) =
             get_potential_token_2022_related_ix_data_and_accounts(
-                &lb_pair_state,
+                lb_pair_state,
                 remaining_accounts_in,
                 ActionType::Liquidity,
             )?
@@ -458,8 +458,7 @@ impl Core {
         }
 
         let vec_positions = fetch_positions(remaining_accounts_in, &[old_position_key])?;
-        let position_state = vec_positions
-            .get(0)
+        let position_state = vec_positions.first()
             .ok_or(error!(CustomError::PositionNotFound))?;
 
         let bin_arrays_account_meta = position_state.get_bin_array_accounts_meta_coverage()?;
@@ -582,7 +581,7 @@ impl Core {
 
         instructions.push(close_position_ix);
 
-        let _result = Core::execute_meteora_instruction(payer, remaining_accounts_in, instructions, true)?;
+        Core::execute_meteora_instruction(payer, remaining_accounts_in, instructions, true)?;
         msg!("Close old_position_key {old_position_key} {result}");
 
         Ok(())
@@ -599,7 +598,8 @@ impl Core {
         remaining_accounts: &'a [AccountInfo<'a>],
         state: &SinglePosition,
         amount_in: u64,
-        swap_for_y: bool
+        swap_for_y: bool,
+        slippage_bps: u16
     ) -> Result<()> {
 
         let lb_pair_state = fetch_lb_pair_state(remaining_accounts, &state.lb_pair)?;
@@ -632,8 +632,8 @@ impl Core {
 
         let bin_arrays_account_meta = get_bin_array_pubkeys_for_swap(
             lb_pair,
-            &lb_pair_state,
-            Some(&bitmap_extension),
+            lb_pair_state,
+            Some(bitmap_extension),
             swap_for_y,
             3,
         )?
@@ -678,7 +678,7 @@ impl Core {
 
         if let Some((slices, transfer_hook_remaining_accounts)) =
             get_potential_token_2022_related_ix_data_and_accounts(
-                &lb_pair_state,
+                lb_pair_state,
                 remaining_accounts,
                 ActionType::Liquidity,
             )?
@@ -713,7 +713,7 @@ impl Core {
 
         let data = dlmm::client::args::Swap2 {
             amount_in,
-            min_amount_out: state.get_min_out_amount_with_slippage_rate(amount_in, swap_for_y, &lb_pair_state)?,
+            min_amount_out: state.get_min_out_amount_with_slippage_rate(amount_in, swap_for_y, lb_pair_state, slippage_bps)?,
             remaining_accounts_info,
         }
         .data();
@@ -732,7 +732,7 @@ impl Core {
 
         let instructions = [swap_ix];
 
-        let _result = Core::execute_meteora_instruction(payer, remaining_accounts, instructions.to_vec(), true)?;
+        Core::execute_meteora_instruction(payer, remaining_accounts, instructions.to_vec(), true)?;
         msg!("Swap {amount_in} {swap_for_y} {result:?}");
 
         Ok(())
@@ -893,7 +893,7 @@ impl Core {
 
         if let Some((slices, transfer_hook_remaining_accounts)) =
             get_potential_token_2022_related_ix_data_and_accounts(
-                &lb_pair_state,
+                lb_pair_state,
                 remaining_accounts,
                 ActionType::Liquidity,
             )?
@@ -958,7 +958,7 @@ impl Core {
 
         instructions.push(instruction);
 
-        let _result = Core::execute_meteora_instruction(payer, remaining_accounts, instructions, true)?;
+        Core::execute_meteora_instruction(payer, remaining_accounts, instructions, true)?;
         msg!("deposit {amount_x} {amount_y} {_result}");
 
         state.position_pks.push(position);
@@ -1004,7 +1004,7 @@ impl Core {
 
         let accounts: HashMap<Pubkey, Option<TokenAccount>> 
                 = Core::get_multiple_anchor_accounts::<TokenAccount>(
-                    context.remaining_accounts, &vec![user_token_x, user_token_y])?;
+                    context.remaining_accounts, &[user_token_x, user_token_y])?;
 
         let user_token_x_state = accounts.get(&user_token_x).unwrap().as_ref().unwrap();
         let user_token_y_state = accounts.get(&user_token_y).unwrap().as_ref().unwrap();
@@ -1049,37 +1049,46 @@ impl Core {
     /// Note that each position in IRMA is single-sided and single-bin.
     /// In other words, min_bin_id == max_bin_id for each position, and 
     /// there are two positions: one for each side of the stablecoin pair.
+    /// Only the sides the reserve's status leaves open are shifted: the mint position of a reserve closed
+    /// for minting and the redemption position of one closed for redemption stay where they are.
     pub fn check_shift_price_range<'a>(
         core: &mut Core,
         payer: &mut Signer,
         remaining_accounts: &'a [AccountInfo<'a>],
-        reserves: &Vec<StableState>,
+        reserves: &[StableState],
+        config: &ProtocolConfig,
         core_position: &mut SinglePosition,
     ) -> Result<()> {
         // ensure that this position is single-bin
         require!(core_position.min_bin_id == core_position.max_bin_id, CustomError::PositionNotSingleBin);
 
         // Find the reserve coin for this position
-        let (reserve_symbol, backing_decimals) = {
+        let (reserve_symbol, backing_decimals, status) = {
             let reserve_coin = reserves.iter().find(|stablecoin| stablecoin.pool_id == core_position.lb_pair);
             require!(reserve_coin.is_some(), CustomError::ReserveListPositionListMismatch);
             let reserve_coin = reserve_coin.unwrap();
-            (reserve_coin.symbol.clone(), reserve_coin.backing_decimals)
+            (reserve_coin.symbol.clone(), reserve_coin.backing_decimals, reserve_coin.status)
         };
+        if !status.can_mint() && !status.can_redeem() {
+            return Ok(()); // Pending or Retired, nothing to shift
+        }
         
         let (mint_price, redemption_price) = pricing::get_prices(
             reserves, &reserve_symbol)?;
 
-        // convert prices from f64 to u128 using token decimals
-        let mint_price_u128 = (mint_price * 10.0f64.powi(backing_decimals as i32)) as u128;
-        let redemption_price_u128 = (redemption_price * 10.0f64.powi(backing_decimals as i32)) as u128;
+        // convert prices to DLMM Q64.64 prices (reserve base units per IRMA base unit)
+        let irma_decimals = pricing::IRMA.backing_decimals as u32;
+        let mint_price_u128 = fixed_point::rescale(
+            mint_price, irma_decimals, backing_decimals as u32, Rounding::Up)?;
+        let redemption_price_u128 = fixed_point::rescale(
+            redemption_price, irma_decimals, backing_decimals as u32, Rounding::Down)?;
 
         let lb_pair_state = fetch_lb_pair_state(
             remaining_accounts, 
             &core_position.lb_pair
         )?;
-        let mut mint_price_bin_id = SinglePosition::search_bin_given_price(&lb_pair_state, mint_price_u128)?;
-        let redemption_price_bin_id = SinglePosition::search_bin_given_price(&lb_pair_state, redemption_price_u128)?;
+        let mut mint_price_bin_id = SinglePosition::search_bin_given_price(lb_pair_state, mint_price_u128)?;
+        let redemption_price_bin_id = SinglePosition::search_bin_given_price(lb_pair_state, redemption_price_u128)?;
         // ensure that mint bin id is higher than redemption bin id
         if mint_price_bin_id <= redemption_price_bin_id {
             // adjust mint price bin id by one to ensure they are different
@@ -1087,14 +1096,16 @@ impl Core {
         }
         
         // check whether out of price range
-        if mint_price_bin_id != core_position.max_bin_id {
-            core.shift_mint_position(payer, remaining_accounts, reserves, core_position, mint_price_bin_id)?;
+        if status.can_mint() && mint_price_bin_id != core_position.max_bin_id {
+            core.shift_mint_position(
+                payer, remaining_accounts, reserves, core_position, mint_price_bin_id, config.minting_position_amount)?;
             core.inc_rebalance_time(core_position.lb_pair);
         }
         // else if equal, it's ok, do nothing
 
-        if redemption_price_bin_id != core_position.min_bin_id {
-            core.shift_redeem_position(payer, remaining_accounts, reserves, core_position, redemption_price_bin_id)?;
+        if status.can_redeem() && redemption_price_bin_id != core_position.min_bin_id {
+            core.shift_redeem_position(
+                payer, remaining_accounts, reserves, core_position, redemption_price_bin_id, config.redemption_position_amount)?;
             core.inc_rebalance_time(core_position.lb_pair);
         }
         // else if equal, it's ok, do nothing
@@ -1103,6 +1114,43 @@ impl Core {
     }
 
 
+    /// Shift the redemption positions of the reserves whose redemption price moved into another bin (see
+    /// pricing::crossed_redemption_bins) to the bin of their new price, in the same instruction.
+    /// remaining_accounts must hold each such LbPair and the accounts check_shift_price_ranges needs for it.
+    pub fn shift_crossed_redemption_positions<'a>(
+        &mut self,
+        payer: &mut Signer,
+        remaining_accounts: &'a [AccountInfo<'a>],
+        reserves: &[StableState],
+        config: &ProtocolConfig,
+        crossed: &[(String, FixedPrice)],
+    ) -> Result<()> {
+        for (symbol, redemption_price) in crossed {
+            let reserve = reserves.iter().find(|r| &r.symbol == symbol).ok_or(CustomError::ReserveNotFound)?;
+            if !reserve.status.can_redeem() {
+                continue;
+            }
+            let Some(mut core_position) = self.position_data.get_position(&reserve.pool_id).cloned() else {
+                continue; // no DLMM position for this reserve
+            };
+            require!(core_position.min_bin_id == core_position.max_bin_id, CustomError::PositionNotSingleBin);
+
+            // DLMM Q64.64 price: reserve base units per IRMA base unit
+            let redemption_price_u128 = fixed_point::rescale(
+                *redemption_price, pricing::IRMA.backing_decimals as u32, reserve.backing_decimals as u32, Rounding::Down)?;
+            let lb_pair_state = fetch_lb_pair_state(remaining_accounts, &core_position.lb_pair)?;
+            let redemption_price_bin_id = allocation::price_bin(lb_pair_state, redemption_price_u128)?;
+            if redemption_price_bin_id != core_position.min_bin_id {
+                self.shift_redeem_position(
+                    payer, remaining_accounts, reserves, &mut core_position, redemption_price_bin_id,
+                    config.redemption_position_amount)?;
+                self.inc_rebalance_time(core_position.lb_pair);
+            }
+            // else the position is already there, nothing to do
+        }
+        Ok(())
+    }
+
     /// Shift mint position
     /// For IRMA, we should deposit first, then withdraw from the old, single bin position.
     /// Note: this can involve shifting to the right or left, depending on the new_price_bin_id.
@@ -1110,9 +1158,10 @@ impl Core {
         &mut self,
         payer: &mut Signer,
         remaining_accounts: &'a [AccountInfo<'a>],
-        reserves: &Vec<StableState>,
+        reserves: &[StableState],
         state: &mut SinglePosition,
         new_price_bin_id: i32, // new mint price bin id
+        position_amount: u64, // IRMA to deposit, ProtocolConfig::minting_position_amount
     ) -> Result<()> {
         // validate that y amount is zero because this position must be for x:
         // there should be no y deposit in any position
@@ -1127,10 +1176,10 @@ impl Core {
         // this also creates a new position and returns its key
         msg!("mint deposit for {}", state.lb_pair);
         let new_position_key = match self
-            .deposit(payer, remaining_accounts, state, MINTING_POSITION_AMOUNT, amount_y, new_price_bin_id)
+            .deposit(payer, remaining_accounts, state, position_amount, amount_y, new_price_bin_id)
         {
             Err(_) => {
-                self.deposit(payer, remaining_accounts, state, MINTING_POSITION_AMOUNT, amount_y, new_price_bin_id)?
+                self.deposit(payer, remaining_accounts, state, position_amount, amount_y, new_price_bin_id)?
             }
             Ok(pos_key) => pos_key,
         };
@@ -1171,9 +1220,10 @@ impl Core {
         &mut self,
         payer: &mut Signer,
         remaining_accounts: &'a [AccountInfo<'a>],
-        reserves: &Vec<StableState>,
+        reserves: &[StableState],
         state: &mut SinglePosition,
         new_price_bin_id: i32, // new redemption price bin id
+        position_amount: u64, // reserve to deposit, ProtocolConfig::redemption_position_amount
     ) -> Result<()> {
         // let pair_config = get_pair_config(&self.config, state.lb_pair);
         msg!("shift redeem position {}", state.lb_pair);
@@ -1188,10 +1238,10 @@ impl Core {
         // let (amount_x, amount_y) = self.get_deposit_amount(context, state, amount_x, amount_y)?;
         msg!("redemption deposit for {}", state.lb_pair);
         let new_position_key = match self
-            .deposit(payer, remaining_accounts, state, 0, REDEMPTION_POSITION_AMOUNT, new_price_bin_id)
+            .deposit(payer, remaining_accounts, state, 0, position_amount, new_price_bin_id)
         {
             Err(_) => {
-                self.deposit(payer, remaining_accounts, state, 0, REDEMPTION_POSITION_AMOUNT, new_price_bin_id)?
+                self.deposit(payer, remaining_accounts, state, 0, position_amount, new_price_bin_id)?
             }
             Ok(pos_key) => pos_key,
         };
@@ -1249,7 +1299,7 @@ impl Core {
             let position_raw = position.get_positions_total(context.remaining_accounts)?;
             position_infos.push(position_raw.to_position_info(x_decimals, y_decimals)?);
         }
-        return Ok(position_infos);
+        Ok(position_infos)
     }
 }
 
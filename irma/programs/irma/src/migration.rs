@@ -0,0 +1,135 @@
+// programs/irma/src/migration.rs
+//
+// Migration of the state_v5 account from the original StateMap layout.
+// The legacy layout is kept here as a frozen struct so that migrate_state can read it and
+// convert it to the current StableState.
+
+use anchor_lang::prelude::*;
+
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice, PRICE_ONE};
+use crate::pricing::{
+    ReserveStatus, StableState, StateMap, IRMA, MAX_BACKING_DECIMALS, MAX_MINT_PRICE, STATE_VERSION,
+    DEFAULT_MAX_UPDATE_CHANGE_BPS, DEFAULT_MAX_DAILY_CHANGE_BPS, DEFAULT_MAX_PRICE_AGE,
+};
+use crate::MigrateState;
+
+/// Version 0: original state_v5 layout of StableState: f64 mint price, amounts in whole tokens.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct LegacyStableState {
+    pub symbol: String,
+    pub mint_address: Pubkey,
+    pub backing_decimals: u64,
+    pub mint_price: f64,
+    pub backing_reserves: u128,
+    pub irma_in_circulation: u128,
+    pub pool_id: Pubkey,
+    pub active: bool,
+    pub extra: [u8; 15],
+}
+
+/// Version 0: original state_v5 layout of StateMap (same discriminator as StateMap).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct LegacyStateMap {
+    pub reserves: Vec<LegacyStableState>,
+    pub bump: u8,
+    pub padding: [u8; 7],
+}
+
+impl LegacyStableState {
+    /// Convert to the current layout: Q64.64 mint price, base-unit amounts.
+    /// Any f64 is an exact binary fraction, so scaling by 2^64 loses only bits below 2^-64.
+    /// The inflation index starts at 1.0, so the USD rate that keeps the mint price is the mint price
+    /// itself. The time of the last rate update is unknown, so the rate counts as stale and minting
+    /// resumes once it has been updated. Fee counters start at zero, price bounds at their defaults,
+    /// and mint and redemption limits and redemption caps unset. An active reserve stays Active; an
+    /// inactive one still holds backing against its circulation, so it becomes MintPaused, from where
+    /// the admin can resume it or wind it down.
+    pub fn migrate(&self) -> Result<StableState> {
+        require!(self.backing_decimals <= MAX_BACKING_DECIMALS, CustomError::InvalidBacking);
+        require!(self.mint_price.is_finite() && self.mint_price >= 0.0, CustomError::InvalidAmount);
+        let mint_price: FixedPrice = (self.mint_price * PRICE_ONE as f64) as u128;
+        require!(mint_price < MAX_MINT_PRICE, CustomError::MintPriceTooHigh);
+        let backing_reserves = self.backing_reserves
+            .checked_mul(fixed_point::pow10(self.backing_decimals as u32)?)
+            .ok_or(CustomError::MathError)?;
+        let irma_in_circulation = self.irma_in_circulation
+            .checked_mul(fixed_point::pow10(IRMA.backing_decimals as u32)?)
+            .ok_or(CustomError::MathError)?;
+        Ok(StableState {
+            symbol: self.symbol.clone(),
+            mint_address: self.mint_address,
+            backing_decimals: self.backing_decimals,
+            mint_price,
+            usd_rate: mint_price,
+            backing_reserves,
+            irma_in_circulation,
+            pool_id: self.pool_id,
+            status: if self.active { ReserveStatus::Active } else { ReserveStatus::MintPaused },
+            fees_collected: 0,
+            treasury_fees: 0,
+            max_update_change_bps: DEFAULT_MAX_UPDATE_CHANGE_BPS,
+            max_daily_change_bps: DEFAULT_MAX_DAILY_CHANGE_BPS,
+            window_start_rate: mint_price,
+            window_start: 0,
+            price_updated_at: 0,
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            min_mint_amount: 0,
+            max_redeem_amount: 0,
+            redeem_window_cap: 0,
+            redeemed_in_window: 0,
+            extra: [0; 1],
+        })
+    }
+}
+
+/// Read the state account in the legacy layout and convert it to the current StateMap.
+/// Settings the legacy layout lacks start at the defaults of StateMap::new.
+pub fn read_state(data: &[u8]) -> Result<StateMap> {
+    require!(data.len() >= 8 && data[..8] == *StateMap::DISCRIMINATOR, CustomError::InvalidReserveList);
+    if let Ok(current) = StateMap::try_deserialize(&mut &data[..]) {
+        require!(current.version != STATE_VERSION, CustomError::StateAlreadyMigrated);
+    }
+    let legacy = LegacyStateMap::deserialize(&mut &data[8..])?;
+    let mut state_map = StateMap::new();
+    state_map.bump = legacy.bump;
+    for reserve in legacy.reserves.iter() {
+        state_map.reserves.push(reserve.migrate()?);
+    }
+    Ok(state_map)
+}
+
+/// Rewrite the state_v5 account from the legacy layout into the current one.
+/// The account is read manually because it no longer deserializes as StateMap.
+/// Calling this on an already migrated account fails with StateAlreadyMigrated.
+pub fn migrate_state(ctx: Context<MigrateState>) -> Result<()> {
+    let state_info = ctx.accounts.state.to_account_info();
+    require_keys_eq!(*state_info.owner, crate::ID, CustomError::InvalidPubkey);
+
+    let migrated: StateMap = read_state(&state_info.try_borrow_data()?)?;
+
+    let mut new_data: Vec<u8> = Vec::new();
+    migrated.try_serialize(&mut new_data)?;
+    if new_data.len() > state_info.data_len() {
+        let rent_due = Rent::get()?
+            .minimum_balance(new_data.len())
+            .saturating_sub(state_info.lamports());
+        if rent_due > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.irma_admin.to_account_info(),
+                        to: state_info.clone(),
+                    },
+                ),
+                rent_due,
+            )?;
+        }
+        state_info.resize(new_data.len())?;
+    }
+    let mut data = state_info.try_borrow_mut_data()?;
+    data[..new_data.len()].copy_from_slice(&new_data);
+    msg!("Migrated {} reserves to state layout version {}", migrated.reserves.len(), STATE_VERSION);
+    Ok(())
+}
@@ -0,0 +1,278 @@
+// programs/irma/src/oracle.rs
+//
+// On-chain price oracles.
+// A feed account is passed as the first of the remaining accounts and read through the PriceFeed
+// trait, which has one adapter per feed layout: Pyth price updates, Switchboard on-demand pull feeds,
+// Truflation inflation published as a Switchboard feed, and a mock feed owned by this program
+// for tests. Every reading is checked for owner, staleness and confidence before it is used.
+// Reserve rates read from a registered feed go to the reserve's price aggregator (aggregation.rs).
+//
+// Reserve feeds quote USD per reserve token; the USD rate (reserve tokens per USD) is its inverse.
+// Inflation feeds quote the annual rate as a fraction (0.0285 == 2.85%).
+
+use anchor_lang::prelude::*;
+use commons::dlmm::types::Rounding;
+
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice, PRICE_ONE};
+use crate::pricing::BASIS_POINTS_MAX;
+use crate::aggregation::{self, PriceSubmission};
+use crate::{SubmitOraclePrice, UpdatePrices};
+
+/// Pyth Solana receiver program, owner of PriceUpdateV2 accounts.
+pub const PYTH_RECEIVER_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+/// Switchboard on-demand program, owner of PullFeedAccountData accounts.
+pub const SWITCHBOARD_ON_DEMAND_ID: Pubkey = pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+
+/// Anchor discriminator of Pyth's PriceUpdateV2.
+pub const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+/// Anchor discriminator of Switchboard's PullFeedAccountData.
+pub const SWITCHBOARD_PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+/// Byte offsets into PullFeedAccountData (zero-copy, discriminator included).
+pub const SWITCHBOARD_LAST_UPDATE_OFFSET: usize = 2216; // i64 last_update_timestamp
+pub const SWITCHBOARD_RESULT_OFFSET: usize = 2264; // i128 result.value, then i128 result.std_dev
+/// Switchboard results are fixed point with 18 decimals.
+pub const SWITCHBOARD_DECIMALS: i32 = 18;
+
+/// Oldest reserve price accepted, in seconds.
+pub const MAX_PRICE_AGE: i64 = 300;
+/// Oldest inflation reading accepted, in seconds (inflation is published daily).
+pub const MAX_INFLATION_AGE: i64 = 2 * 86_400;
+/// Widest confidence interval accepted for a price, relative to the price (1%).
+pub const MAX_CONFIDENCE_BPS: u128 = 100;
+/// Widest confidence interval accepted for an inflation rate, in absolute basis points.
+pub const MAX_RATE_CONFIDENCE_BPS: u128 = 25;
+
+/// Which adapter reads the feed account.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FeedKind {
+    Pyth,
+    Switchboard,
+    Truflation,
+    Mock,
+}
+
+/// A decimal reading: value = mantissa * 10^exponent, +/- confidence (same exponent).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct OracleReading {
+    pub mantissa: i128,
+    pub confidence: u128,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+impl OracleReading {
+    /// Fail if the reading was published more than max_age seconds before now.
+    pub fn check_age(&self, now: i64, max_age: i64) -> Result<()> {
+        let age = now.saturating_sub(self.publish_time);
+        require!(age <= max_age, CustomError::StaleOraclePrice);
+        Ok(())
+    }
+
+    /// The reading as a positive Q64.64 price, rounded down.
+    /// Fails if the confidence interval is wider than MAX_CONFIDENCE_BPS of the price.
+    pub fn to_price(&self) -> Result<FixedPrice> {
+        require!(self.mantissa > 0, CustomError::InvalidOracle);
+        let mantissa = self.mantissa as u128;
+        let max_confidence = fixed_point::mul_div(mantissa, MAX_CONFIDENCE_BPS, BASIS_POINTS_MAX, Rounding::Down)?;
+        require!(self.confidence <= max_confidence, CustomError::OracleConfidenceTooWide);
+        if self.exponent >= 0 {
+            mantissa
+                .checked_mul(fixed_point::pow10(self.exponent as u32)?)
+                .and_then(|value| value.checked_mul(PRICE_ONE))
+                .ok_or(error!(CustomError::MathError))
+        } else {
+            fixed_point::from_decimal(mantissa, self.exponent.unsigned_abs(), Rounding::Down)
+        }
+    }
+
+    /// The reading, a fraction, in basis points truncated toward zero.
+    /// Fails if the confidence interval is wider than MAX_RATE_CONFIDENCE_BPS.
+    pub fn to_rate_bps(&self) -> Result<i32> {
+        let shift = self.exponent.checked_add(4).ok_or(CustomError::MathError)?;
+        let (rate_bps, confidence_bps) = if shift >= 0 {
+            let scale = fixed_point::pow10(shift as u32)?;
+            (
+                self.mantissa.checked_mul(scale as i128).ok_or(CustomError::MathError)?,
+                self.confidence.checked_mul(scale).ok_or(CustomError::MathError)?,
+            )
+        } else {
+            let scale = fixed_point::pow10(shift.unsigned_abs())?;
+            (self.mantissa / scale as i128, self.confidence.div_ceil(scale))
+        };
+        require!(confidence_bps <= MAX_RATE_CONFIDENCE_BPS, CustomError::OracleConfidenceTooWide);
+        i32::try_from(rate_bps).map_err(|_| error!(CustomError::InvalidInflationRate))
+    }
+}
+
+/// A feed account layout.
+pub trait PriceFeed {
+    /// Program that must own the feed account.
+    fn owner() -> Pubkey;
+
+    /// Parse the account data into a reading.
+    fn parse(data: &[u8]) -> Result<OracleReading>;
+
+    /// Check the owner of the feed account and parse it.
+    fn read(feed: &AccountInfo) -> Result<OracleReading> {
+        require_keys_eq!(*feed.owner, Self::owner(), CustomError::InvalidOracle);
+        Self::parse(&feed.try_borrow_data()?)
+    }
+}
+
+#[derive(AnchorDeserialize)]
+enum PythVerificationLevel {
+    Partial { _num_signatures: u8 },
+    Full,
+}
+
+/// Borsh layout of Pyth's PriceUpdateV2 after the discriminator.
+#[derive(AnchorDeserialize)]
+struct PythPriceUpdate {
+    _write_authority: Pubkey,
+    verification_level: PythVerificationLevel,
+    _feed_id: [u8; 32],
+    price: i64,
+    conf: u64,
+    exponent: i32,
+    publish_time: i64,
+}
+
+/// Pyth pull-oracle price update (PriceUpdateV2), fully verified updates only.
+pub struct PythFeed;
+
+impl PriceFeed for PythFeed {
+    fn owner() -> Pubkey {
+        PYTH_RECEIVER_ID
+    }
+
+    fn parse(data: &[u8]) -> Result<OracleReading> {
+        require!(data.len() > 8 && data[..8] == PYTH_PRICE_UPDATE_DISCRIMINATOR, CustomError::InvalidOracle);
+        let update = PythPriceUpdate::deserialize(&mut &data[8..])?;
+        require!(
+            matches!(update.verification_level, PythVerificationLevel::Full),
+            CustomError::InvalidOracle
+        );
+        Ok(OracleReading {
+            mantissa: update.price as i128,
+            confidence: update.conf as u128,
+            exponent: update.exponent,
+            publish_time: update.publish_time,
+        })
+    }
+}
+
+/// Switchboard on-demand pull feed (PullFeedAccountData); reads the latest aggregated result.
+pub struct SwitchboardFeed;
+
+impl PriceFeed for SwitchboardFeed {
+    fn owner() -> Pubkey {
+        SWITCHBOARD_ON_DEMAND_ID
+    }
+
+    fn parse(data: &[u8]) -> Result<OracleReading> {
+        require!(
+            data.len() >= SWITCHBOARD_RESULT_OFFSET + 32 && data[..8] == SWITCHBOARD_PULL_FEED_DISCRIMINATOR,
+            CustomError::InvalidOracle
+        );
+        let read_i128 = |offset: usize| i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap());
+        let publish_time = i64::from_le_bytes(
+            data[SWITCHBOARD_LAST_UPDATE_OFFSET..SWITCHBOARD_LAST_UPDATE_OFFSET + 8].try_into().unwrap());
+        Ok(OracleReading {
+            mantissa: read_i128(SWITCHBOARD_RESULT_OFFSET),
+            confidence: read_i128(SWITCHBOARD_RESULT_OFFSET + 16).unsigned_abs(),
+            exponent: -SWITCHBOARD_DECIMALS,
+            publish_time,
+        })
+    }
+}
+
+/// Truflation inflation published as a Switchboard pull feed, quoted in percent.
+/// The reading is converted to a fraction.
+pub struct TruflationFeed;
+
+impl PriceFeed for TruflationFeed {
+    fn owner() -> Pubkey {
+        SWITCHBOARD_ON_DEMAND_ID
+    }
+
+    fn parse(data: &[u8]) -> Result<OracleReading> {
+        let percent = SwitchboardFeed::parse(data)?;
+        Ok(OracleReading { exponent: percent.exponent - 2, ..percent })
+    }
+}
+
+/// Mock feed for tests. It is owned by this program, which has no instruction that writes it,
+/// so such accounts only exist in test environments.
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct MockPriceFeed {
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+impl MockPriceFeed {
+    pub const LEN: usize = 8 + 8 + 4 + 8;
+}
+
+impl PriceFeed for MockPriceFeed {
+    fn owner() -> Pubkey {
+        crate::ID
+    }
+
+    fn parse(data: &[u8]) -> Result<OracleReading> {
+        let feed = MockPriceFeed::try_deserialize(&mut &data[..])?;
+        Ok(OracleReading {
+            mantissa: feed.price as i128,
+            confidence: feed.conf as u128,
+            exponent: feed.exponent,
+            publish_time: feed.publish_time,
+        })
+    }
+}
+
+/// Read a feed account with the adapter for feed_kind.
+pub fn read_feed(feed_kind: FeedKind, feed: &AccountInfo) -> Result<OracleReading> {
+    match feed_kind {
+        FeedKind::Pyth => PythFeed::read(feed),
+        FeedKind::Switchboard => SwitchboardFeed::read(feed),
+        FeedKind::Truflation => TruflationFeed::read(feed),
+        FeedKind::Mock => MockPriceFeed::read(feed),
+    }
+}
+
+/// USD rate (reserve tokens per USD, Q64.64) from a reading of USD per reserve token, rounded up
+/// so that the derived mint price errs in favor of the protocol.
+pub fn usd_rate_from_reading(reading: &OracleReading) -> Result<FixedPrice> {
+    fixed_point::from_ratio(PRICE_ONE, reading.to_price()?, Rounding::Up)
+}
+
+/// Submit quote_token's USD rate from the feed in remaining_accounts[0] to the reserve's price aggregator.
+/// The feed account must be registered with add_price_feed.
+pub fn update_usd_rate_from_oracle(ctx: Context<SubmitOraclePrice>, quote_token: &str, feed_kind: FeedKind) -> Result<()> {
+    let feed = ctx.remaining_accounts.first().ok_or(CustomError::MissingOracleAccount)?;
+    require!(ctx.accounts.price_authorities.has_feed(feed.key), CustomError::UnknownPriceFeed);
+    let reading = read_feed(feed_kind, feed)?;
+    let now = Clock::get()?.unix_timestamp;
+    reading.check_age(now, MAX_PRICE_AGE)?;
+    let usd_rate = usd_rate_from_reading(&reading)?;
+    msg!("USD rate {} for {} read from {:?} feed {}", usd_rate, quote_token, feed_kind, feed.key());
+
+    let submission = PriceSubmission { source: feed.key(), usd_rate, timestamp: reading.publish_time };
+    let index = ctx.accounts.inflation_index.index;
+    aggregation::submit_rate(
+        &mut ctx.accounts.price_aggregator, &mut ctx.accounts.state, &mut ctx.accounts.price_history,
+        &ctx.accounts.protocol_config, index, quote_token, submission, now)
+}
+
+/// Apply one period of inflation at the annual rate read from the feed in remaining_accounts[0].
+/// The remaining accounts after the feed are the reserves' price histories.
+pub fn apply_inflation_from_oracle(ctx: Context<UpdatePrices>, feed_kind: FeedKind) -> Result<()> {
+    let (feed, histories) = ctx.remaining_accounts.split_first().ok_or(CustomError::MissingOracleAccount)?;
+    let reading = read_feed(feed_kind, feed)?;
+    reading.check_age(Clock::get()?.unix_timestamp, MAX_INFLATION_AGE)?;
+    let rate_bps = reading.to_rate_bps()?;
+    crate::inflation::inflate(ctx.accounts, rate_bps, histories)
+}
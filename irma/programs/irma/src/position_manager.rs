@@ -71,7 +71,7 @@ pub struct AllPosition {
 }
 
 impl AllPosition {
-    pub fn new(config: &Vec<PairConfig>) -> Result<Self> {
+    pub fn new(config: &[PairConfig]) -> Result<Self> {
         let mut all_positions = Vec::new();
         for pair in config.iter() {
             let pool_pk = Pubkey::from_str(&pair.pair_address).unwrap();
@@ -119,7 +119,6 @@ pub struct SinglePosition {
     pub last_update_timestamp: u64,
 }
 
-const SLIPPAGE_RATE: u64 = 300; // 3%
 const BASIC_POINT_MAX: u64 = 10_000;
 
 impl SinglePosition {
@@ -132,14 +131,16 @@ impl SinglePosition {
         amount_in: u64,
         swap_for_y: bool,
         lb_pair_state: &LbPair,  // Pass as parameter instead of storing
+        slippage_bps: u16, // ProtocolConfig::slippage_bps
     ) -> Result<u64> {
+        let slippage_rate = slippage_bps as u64;
         let price = PositionRaw::get_price_from_id(lb_pair_state.active_id, lb_pair_state.bin_step)?;
         let out_amount = Bin::get_amount_out(amount_in, price, swap_for_y)?;
 
         let min_out_amount =
-            match out_amount.checked_mul(BASIC_POINT_MAX - SLIPPAGE_RATE) {
+            match out_amount.checked_mul(BASIC_POINT_MAX - slippage_rate) {
                 Some(val) => val.checked_div(BASIC_POINT_MAX).unwrap(),
-                None => out_amount.checked_div(BASIC_POINT_MAX).unwrap().checked_mul(BASIC_POINT_MAX - SLIPPAGE_RATE).unwrap(),
+                None => out_amount.checked_div(BASIC_POINT_MAX).unwrap().checked_mul(BASIC_POINT_MAX - slippage_rate).unwrap(),
             };
 
         msg!("    min_out_amount {}", min_out_amount);
@@ -158,7 +159,7 @@ impl SinglePosition {
         let positions = fetch_positions(acct_infos, &self.position_pks)?;
         msg!("    fetched {} positions", positions.len());
 
-        if positions.len() == 0 {
+        if positions.is_empty() {
             return Ok(PositionRaw::default());
         }
         
@@ -167,7 +168,7 @@ impl SinglePosition {
         msg!("    fetched {} bin arrays", bin_arrays.len());
 
         // bin arrays must be present because positions exist
-        if bin_arrays.len() == 0 {
+        if bin_arrays.is_empty() {
             Err(Error::from(CustomError::FailedToFetchBinArrays))?;
         }
 
@@ -302,7 +303,7 @@ impl PositionRaw {
         let min_price =
             Decimal::from_u128(min_price_fp).unwrap() / Decimal::from(ONE);
         let adjusted_min_price = min_price
-            .checked_mul(ui_price_adjustment_factor.into())
+            .checked_mul(ui_price_adjustment_factor)
             .unwrap()
             .to_f64()
             .unwrap();
@@ -311,7 +312,7 @@ impl PositionRaw {
         let max_price =
             Decimal::from_u128(max_price_fp).unwrap() / Decimal::from(ONE);
         let adjusted_max_price = max_price
-            .checked_mul(ui_price_adjustment_factor.into())
+            .checked_mul(ui_price_adjustment_factor)
             .unwrap()
             .to_f64()
             .unwrap();
@@ -320,7 +321,7 @@ impl PositionRaw {
         let current_price =
             Decimal::from_u128(current_price_fp).unwrap() / Decimal::from(ONE);
         let adjusted_current_price = current_price
-            .checked_mul(ui_price_adjustment_factor.into())
+            .checked_mul(ui_price_adjustment_factor)
             .unwrap()
             .to_f64()
             .unwrap();
@@ -331,7 +332,7 @@ impl PositionRaw {
         let fee_x = self.fee_x as f64 / token_x_ui_adjustment_factor;
         let fee_y = self.fee_y as f64 / token_y_ui_adjustment_factor;
 
-        return Ok(PositionInfo {
+        Ok(PositionInfo {
             position_len: self.position_len,
             rebalance_time: self.rebalance_time,
             max_price: adjusted_max_price,
@@ -342,7 +343,7 @@ impl PositionRaw {
             fee_x,
             fee_y,
             last_update_timestamp: self.last_update_timestamp,
-        });
+        })
     }
 
     pub fn get_price_from_id(active_id: i32, bin_step: u16) -> Result<u128> {
@@ -382,5 +383,5 @@ pub fn get_decimals(token_mint_pk: Pubkey, all_tokens: &[TokenEntry]) -> u8 {
     let token = all_tokens.iter()
         .find(|entry| entry.pubkey == token_mint_pk)
         .unwrap();
-    return token.mint_with_program.mint_info.decimals;
+    token.mint_with_program.mint_info.decimals
 }
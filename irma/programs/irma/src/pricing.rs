@@ -2,50 +2,80 @@
 
 use std::string::String;
 use std::option::Option;
-use std::collections::BTreeMap;
 use std::mem::size_of;
 
 
 use anchor_lang::prelude::*;
 use static_assertions::const_assert;
+use commons::dlmm::accounts::LbPair;
+use commons::dlmm::types::Rounding;
 
-use crate::{Init, Maint};
+use crate::{EmergencyUpdatePrices, Init, Maint, ManageState, UpdatePrices};
 use crate::errors::CustomError;
+use crate::config::ProtocolConfig;
+use crate::allocation::{self, AllocationStrategy};
+use crate::fixed_point::{self, FixedPrice, PRICE_ONE};
+use crate::history;
 
 
 // Maximum number of stablecoins supported
 // This is limited by the maximum size of the account data (10,240 bytes).
-// Each stablecoin entry in the reserves requires approximately 120 + 32 bytes of storage.
-// Therefore, the maximum number of stablecoins is calculated as 10,240 / 152 = 67 (rounded down).
-pub const MAX_BACKING_COUNT: usize = 40; // 67;
-
-// Minimum minatable amount. Any request to mint below this amount will error out.
-// There is no maximum mint amount. Large mint requests are good for the system.
-// Users are advised to start small to test the system, then increase their mint amounts gradually.
-pub const MIN_MINT_AMOUNT: u128 = 100u128;
-
-// Maximum redeemable amount per transaction.
-// Any small amount below this amount is OK.
-// Any amount above this will error out, but multiple transactions totalling more than this are allowed.
-// Users are required to breack up large redemptions into multiple transactions.
-// This is to prevent sudden large price movements that can destabilize the system.
-pub const MAX_REDEEM_AMOUNT: u128 = 100_000u128;
+// Each stablecoin entry in the reserves takes StableState::LEN bytes of storage, so at most
+// 10,240 / StableState::LEN of them fit, less the rest of StateMap (checked by a const_assert below).
+pub const MAX_BACKING_COUNT: usize = 40;
+
+// The minimum mint and maximum redemption amounts are ProtocolConfig parameters (see config.rs).
 
 // Maximum mint price.
 // If the mint price exceeds this value, minting will be disabled for that stablecoin.
+// ProtocolConfig::max_mint_price, at most this ceiling, is the limit actually enforced: USD rate
+// updates and repricing refuse to take a mint price to it. The ceiling also keeps Q64.64 price
+// arithmetic far from overflow.
 // The reason is that it means this reserve stablecoin would have lost significant value,
 // and therefore it is no longer suitable as a backing stablecoin for IRMA.
 // Users should choose another stablecoin to mint IRMA.
 // If the USD itself loses significant value, then it's "goodbye" USD.
 // If IRMA has gained significant network effects by then, then it should be able to survive.
-pub const MAX_MINT_PRICE: f64 = 10_000.0;
-
-/// IRMA module
+// (Q64.64 fixed point, i.e. 10,000.0)
+pub const MAX_MINT_PRICE: FixedPrice = 10_000 * PRICE_ONE;
+
+// Default redemption fee in basis points (0.01%). Minting is free; redemptions and swaps pay this fee.
+// The fee actually charged is StateMap::redemption_fee_bps, which the admin can change up to the maximum.
+pub const DEFAULT_REDEMPTION_FEE_BPS: u16 = 1;
+pub const MAX_REDEMPTION_FEE_BPS: u16 = 100;
+pub const BASIS_POINTS_MAX: u128 = 10_000;
+
+// Default bounds on how far a reserve's USD rate, and so its mint price, may move: per update, and
+// per 24h window measured from the rate at the start of the window. Changes from the inflation index
+// are not bounded here; apply_inflation has its own rate limits.
+pub const DEFAULT_MAX_UPDATE_CHANGE_BPS: u16 = 200;
+pub const DEFAULT_MAX_DAILY_CHANGE_BPS: u16 = 500;
+pub const PRICE_BOUND_WINDOW: i64 = 86_400;
+
+// Default age in seconds after which a reserve's USD rate is stale and minting against it stops.
+// Redemptions are still allowed against a stale rate.
+pub const DEFAULT_MAX_PRICE_AGE: u32 = 86_400;
+
+// Length in seconds of the window over which redemptions count against the redemption caps.
+// Caps are off (zero) until the admin sets them, per reserve and for all reserves together;
+// all counters restart when a window ends, so a cap bounds what can leave within any one window.
+pub const DEFAULT_REDEEM_WINDOW: u32 = 86_400;
+
+// Largest number of decimals a reserve stablecoin may have.
+// Amounts are kept in base units as u128, so 18 decimals still leaves room for ~3.4e20 whole tokens.
+pub const MAX_BACKING_DECIMALS: u64 = 18;
+
+// Layout version of the StateMap account, stored in StateMap::version.
+// Version 0 is the original state_v5 layout (f64 mint price, whole-token amounts, no version field);
+// version 1 is the current layout. See migration.rs.
+pub const STATE_VERSION: u8 = 1;
+
+// IRMA module
 
 pub fn init_pricing(ctx: &mut Context<Init>) -> Result<()> {
     msg!("Greetings from: {:?}", ctx.program_id);
     let state = &ctx.accounts.state;
-    if state.reserves.len() > 0 {
+    if !state.reserves.is_empty() {
         msg!("State already initialized, skipping init...");
         return Ok(());
     }
@@ -72,108 +102,407 @@ pub fn add_reserve(
         msg!("Maximum number of stablecoins reached.");
         return Err(error!(CustomError::InvalidBacking));
     }
-    let stablecoin = StableState::new(symbol, mint_address, backing_decimals as u64).unwrap();
+    let mut stablecoin = StableState::new(symbol, mint_address, backing_decimals as u64).unwrap();
+    stablecoin.status = ReserveStatus::Pending; // until it has an LbPair, see set_reserve_status
     state.add_reserve(stablecoin.clone());
     msg!("Added stablecoin: {:?}", stablecoin);
     Ok(())
 }
 
 /// Remove a stablecoin from the reserves by its symbol.
+/// Only a Pending reserve can be removed; one that was ever Active is wound down and Retired instead,
+/// so its backing and circulation stay on record.
 pub fn remove_reserve(ctx: Context<Maint>, symbol: &str) -> Result<()> {
     let state = &mut ctx.accounts.state;
     if !state.contains_reserve(symbol) {
         msg!("Stablecoin {} not found in reserves.", symbol);
         return Err(error!(CustomError::InvalidBacking));
     }
-    state.remove_reserve(symbol);
+    state.remove_reserve(symbol)?;
     msg!("Removed stablecoin: {}", symbol);
     Ok(())
 }
 
-/// Deactivate a reserve stablecoin.
-pub fn disable_reserve(ctx: Context<Maint>, symbol: &str) -> Result<()> {
+/// Pause minting against a reserve stablecoin; it stays open for redemption.
+pub fn disable_reserve(ctx: Context<ManageState>, symbol: &str) -> Result<()> {
+    set_reserve_status(ctx, symbol, ReserveStatus::MintPaused)
+}
+
+/// Move a reserve stablecoin to another lifecycle status; see ReserveStatus::can_become.
+pub fn set_reserve_status(ctx: Context<ManageState>, symbol: &str, status: ReserveStatus) -> Result<()> {
     let state = &mut ctx.accounts.state;
     if !state.contains_reserve(symbol) {
         msg!("Stablecoin {} not found in reserves.", symbol);
         return Err(error!(CustomError::InvalidBacking));
     }
-    state.disable_reserve(symbol);
-    msg!("Deactivated stablecoin: {}", symbol);
+    state.set_reserve_status(symbol, status)?;
+    msg!("Stablecoin {} is now {:?}", symbol, status);
     Ok(())
 }
 
-fn validate_params(reserves: &Vec<StableState>, quote_token: &str) -> Result<()> {
-    require!(reserves.len() > 0, CustomError::InvalidReserveList);
+fn validate_params(reserves: &[StableState], quote_token: &str) -> Result<()> {
+    require!(!reserves.is_empty(), CustomError::InvalidReserveList);
     require!(reserves.iter().any(|r| r.symbol == quote_token), CustomError::InvalidQuoteToken);
     let stablecoin = reserves.iter().find(|r| r.symbol == quote_token).unwrap();
-    require!(stablecoin.active, CustomError::InvalidQuoteToken);
+    require!(stablecoin.status != ReserveStatus::Retired, CustomError::InvalidQuoteToken);
     require!(stablecoin.backing_decimals > 0, CustomError::InvalidQuoteToken);
-    require!(stablecoin.mint_price > 0.0, CustomError::InvalidAmount);
+    require!(stablecoin.mint_price > 0, CustomError::InvalidAmount);
     require!(stablecoin.irma_in_circulation > 0u128, CustomError::InsufficientCirculation);
     Ok(())
 }
 
-/// Set mint price for a given quote token based on inflation data.
-/// This should be called for every backing stablecoin supported, only once per day
-/// because Truflation updates the inflation data only once per day.
-/// The mint price is the ACTUAL price of IRMA in terms of the quote token (no decimals).
-pub fn set_mint_price(ctx: Context<Maint>, quote_token: &str, mint_price: f64) -> Result<()> {
+/// Set the USD exchange rate of a reserve stablecoin (units of the stablecoin per USD, Q64.64)
+/// and reprice it: mint price = inflation index * usd_rate.
+/// The rate comes from the market price of the stablecoin; 1.0 for a stablecoin exactly at peg.
+pub fn set_usd_rate(ctx: Context<UpdatePrices>, quote_token: &str, usd_rate: FixedPrice) -> Result<()> {
+    let index: FixedPrice = ctx.accounts.inflation_index.index;
+    let now = Clock::get()?.unix_timestamp;
+    ctx.accounts.state.set_usd_rate(&ctx.accounts.protocol_config, index, quote_token, usd_rate, now)?;
+    history::record_all(ctx.remaining_accounts, &ctx.accounts.state, now)
+}
+
+/// Set a reserve's USD rate outside its bounds; the emergency authority co-signs.
+pub fn emergency_set_usd_rate(ctx: Context<EmergencyUpdatePrices>, quote_token: &str, usd_rate: FixedPrice) -> Result<()> {
+    let index: FixedPrice = ctx.accounts.inflation_index.index;
+    let now = Clock::get()?.unix_timestamp;
+    ctx.accounts.state.override_usd_rate(&ctx.accounts.protocol_config, index, quote_token, usd_rate, now)?;
+    history::record(&mut ctx.accounts.price_history, &ctx.accounts.state, now)?;
+    msg!("Emergency USD rate for {} set to {}, co-signed by {}",
+        quote_token, usd_rate, ctx.accounts.emergency_authority.key());
+    Ok(())
+}
+
+/// Set how long, in seconds, a reserve's USD rate may go without an update before minting stops.
+pub fn set_max_price_age(ctx: Context<ManageState>, quote_token: &str, max_price_age: u32) -> Result<()> {
+    validate_params(&ctx.accounts.state.reserves, quote_token)?;
+    require!(max_price_age > 0, CustomError::InvalidAmount);
+    ctx.accounts.state.get_mut_stablecoin(quote_token)?.max_price_age = max_price_age;
+    msg!("Max price age for {} set to {}s", quote_token, max_price_age);
+    Ok(())
+}
+
+/// Set a reserve's minimum mint (whole reserve tokens) and maximum redemption per transaction (whole IRMA).
+/// Zero falls back to the protocol-wide limit in ProtocolConfig.
+pub fn set_reserve_limits(
+    ctx: Context<ManageState>, quote_token: &str, min_mint_amount: u64, max_redeem_amount: u64
+) -> Result<()> {
+    validate_params(&ctx.accounts.state.reserves, quote_token)?;
+    let stablecoin = ctx.accounts.state.get_mut_stablecoin(quote_token)?;
+    stablecoin.min_mint_amount = min_mint_amount;
+    stablecoin.max_redeem_amount = max_redeem_amount;
+    msg!("Limits for {} set to a minimum mint of {} and a maximum redemption of {} IRMA (0: protocol default)",
+        quote_token, min_mint_amount, max_redeem_amount);
+    Ok(())
+}
+
+/// Set the IRMA (whole tokens) that may be redeemed from a reserve within one redemption window, 0 for no cap.
+pub fn set_reserve_redemption_cap(ctx: Context<ManageState>, quote_token: &str, redeem_window_cap: u64) -> Result<()> {
+    validate_params(&ctx.accounts.state.reserves, quote_token)?;
+    ctx.accounts.state.get_mut_stablecoin(quote_token)?.redeem_window_cap = redeem_window_cap;
+    msg!("Redemption cap for {} set to {} IRMA per window (0: no cap)", quote_token, redeem_window_cap);
+    Ok(())
+}
+
+/// Set the length of the redemption window in seconds and the IRMA (whole tokens) that may be redeemed
+/// from all reserves together within one window, 0 for no cap. The current window keeps its start.
+pub fn set_redemption_window(ctx: Context<ManageState>, redeem_window: u32, redeem_window_cap: u64) -> Result<()> {
+    require!(redeem_window > 0, CustomError::InvalidAmount);
     let state_map = &mut ctx.accounts.state;
-    validate_params(&state_map.reserves, quote_token)?;
-    require!(mint_price > 0.0, CustomError::InvalidAmount);
-    require!(
-        mint_price < MAX_MINT_PRICE,
-        CustomError::RemoveReserve
-    ); // sanity check, mint price should not be too high
-    let stablecoin = state_map.get_mut_stablecoin(quote_token).unwrap();
-    stablecoin.mint_price = mint_price;
+    state_map.redeem_window = redeem_window;
+    state_map.redeem_window_cap = redeem_window_cap;
+    msg!("Redemption window set to {}s with a global cap of {} IRMA (0: no cap)", redeem_window, redeem_window_cap);
+    Ok(())
+}
+
+/// Set the largest relative change of a reserve's USD rate per update and per day.
+pub fn set_price_bounds(
+    ctx: Context<ManageState>, quote_token: &str, max_update_change_bps: u16, max_daily_change_bps: u16
+) -> Result<()> {
+    ctx.accounts.state.set_price_bounds(quote_token, max_update_change_bps, max_daily_change_bps)?;
+    msg!("Price bounds for {} set to {} bps per update, {} bps per day",
+        quote_token, max_update_change_bps, max_daily_change_bps);
+    Ok(())
+}
+
+/// Whether new is within bps of old, relative to old.
+pub fn within_bps(old: FixedPrice, new: FixedPrice, bps: u16) -> Result<bool> {
+    let max_change = fixed_point::mul_div(old, bps as u128, BASIS_POINTS_MAX, Rounding::Down)?;
+    Ok(old.abs_diff(new) <= max_change)
+}
+
+/// Mint price (units of the stablecoin per IRMA) from the inflation index (USD per IRMA)
+/// and the stablecoin's USD rate (units per USD). Rounds up, in favor of the protocol.
+pub fn derive_mint_price(index: FixedPrice, usd_rate: FixedPrice) -> Result<FixedPrice> {
+    fixed_point::mul_div(index, usd_rate, PRICE_ONE, Rounding::Up)
+}
+
+/// Set the redemption fee (basis points) and where collected fees go.
+pub fn set_fee_policy(ctx: Context<ManageState>, redemption_fee_bps: u16, fee_policy: FeePolicy) -> Result<()> {
+    require!(redemption_fee_bps <= MAX_REDEMPTION_FEE_BPS, CustomError::InvalidAmount);
+    let state_map = &mut ctx.accounts.state;
+    state_map.redemption_fee_bps = redemption_fee_bps;
+    state_map.fee_policy = fee_policy;
+    msg!("Redemption fee set to {} bps, policy {:?}", redemption_fee_bps, fee_policy);
     Ok(())
 }
 
 /// Mint IRMA tokens for a given amount of quote token.
 /// The mint price is the price of IRMA in terms of the quote token, which is set by the Truflation oracle.
-/// Input amount is  in quote token's smallest unit (e.g. 1 USDT = 10^6, 1 USDC = 10^6, etc.)
+/// Input amount is in quote token's smallest unit (e.g. 1 USDT = 10^6, 1 USDC = 10^6, etc.)
 /// Input amount therefore is an unsigned integer suitable for on-chain processing, not for 
 /// human consumption.
-pub fn mint_irma(state_map: &mut Account<StateMap>, quote_token: &str, amount: u64) -> Result<()> {
-    require!(amount >= 100_000_000u64, CustomError::InvalidAmount);
+/// Backing and circulation are both tracked in base units, so no fraction of the deposit is lost;
+/// the minted IRMA rounds down in favor of the protocol.
+/// Fails with StaleMintPrice if the reserve's USD rate is older than its max_price_age at time now,
+/// and with MintPriceTooHigh once its mint price reaches the configured maximum.
+pub fn mint_irma(
+    state_map: &mut StateMap, config: &ProtocolConfig, quote_token: &str, amount: u64, now: i64
+) -> Result<()> {
     validate_params(&state_map.reserves, quote_token)?;
 
-    if amount == 0u64 { return Ok(()); };
-
     let stablecoin = state_map.get_stablecoin(quote_token).unwrap();
-    let curr_price: f64 = stablecoin.mint_price;
-    // 10f64.powi(token_x_decimals.into())
-    let amount = (amount as f64 / (10.0_f64).powi(stablecoin.backing_decimals as i32)) as f64;
+    require!(stablecoin.status.can_mint(), CustomError::ReserveNotMintable);
+    let min_amount: u128 = stablecoin.min_mint_base_units(config)?;
+    if (amount as u128) < min_amount {
+        msg!("Minimum mint for {} is {} tokens ({} base units), got {} base units",
+            quote_token, stablecoin.min_mint_tokens(config), min_amount, amount);
+        return Err(error!(CustomError::MintBelowMinimum));
+    }
+    stablecoin.check_price_fresh(now)?;
+    require!(stablecoin.mint_price < config.max_mint_price, CustomError::MintPriceTooHigh);
+    // mint price in reserve base units per IRMA base unit; rounding it up rounds the IRMA minted down
+    let raw_price: FixedPrice = stablecoin.raw_mint_price(Rounding::Up)?;
+
+    // backing is kept in base units, so the full amount is credited
+    let backing_added: u128 = amount as u128;
+    let irma_minted: u128 = fixed_point::div_price(amount as u128, raw_price, Rounding::Down)?;
 
     let stablecoin = state_map.get_mut_stablecoin(quote_token).unwrap();
-    stablecoin.backing_reserves += amount.ceil() as u128; // backing should not have a fractional part
-    stablecoin.irma_in_circulation += (amount / curr_price).ceil() as u128;
+    stablecoin.backing_reserves = stablecoin.backing_reserves
+        .checked_add(backing_added)
+        .ok_or(CustomError::MathError)?;
+    stablecoin.irma_in_circulation = stablecoin.irma_in_circulation
+        .checked_add(irma_minted)
+        .ok_or(CustomError::MathError)?;
 
     Ok(())
 }
 
+/// Mint IRMA against several reserves at once. Each deposit goes through mint_irma, so every reserve must be
+/// open for minting, priced within its max_price_age and below the maximum mint price, and each amount must meet its
+/// reserve's minimum; any failure fails the whole basket and leaves state_map as it was.
+/// A reserve may appear only once. Returns the IRMA (base units) minted for each deposit, in order.
+pub fn mint_basket(
+    state_map: &mut StateMap, config: &ProtocolConfig, deposits: &[BasketDeposit], now: i64
+) -> Result<Vec<u64>> {
+    require!(!deposits.is_empty(), CustomError::InvalidAmount);
+    let mut basket: StateMap = state_map.clone();
+    let mut minted: Vec<u64> = Vec::with_capacity(deposits.len());
+    for (i, deposit) in deposits.iter().enumerate() {
+        require!(deposits[..i].iter().all(|d| d.symbol != deposit.symbol), CustomError::InvalidQuoteToken);
+        let circulation_before: u128 = basket.get_stablecoin(&deposit.symbol)?.irma_in_circulation;
+        mint_irma(&mut basket, config, &deposit.symbol, deposit.amount, now)?;
+        let irma: u64 = basket.get_stablecoin(&deposit.symbol)?.irma_in_circulation
+            .checked_sub(circulation_before)
+            .and_then(|irma| u64::try_from(irma).ok())
+            .ok_or(CustomError::MathError)?;
+        minted.push(irma);
+    }
+    *state_map = basket;
+    Ok(minted)
+}
+
 /// RedeemIRMA - user surrenders IRMA in irma_amount, expecting to get back quote_token according to redemption price.
-/// FIXME: If resulting redemption price increases by more than 0.0000001, then actual redemption price 
-/// should be updated immediately.
-pub fn redeem_irma(state_map: &mut Account<StateMap>, quote_token: &str, irma_amount: u64) -> Result<()> {
+/// irma_amount is in IRMA base units (10^6 per IRMA).
+/// The redemption counts against the reserve's and the global redemption caps of the window containing now.
+/// A failed redemption leaves state_map as it was.
+/// Redemption prices it raises into another DLMM bin (see crossed_redemption_bins) are for the caller to
+/// pass on to the DLMM redemption positions.
+pub fn redeem_irma(
+    state_map: &mut StateMap, config: &ProtocolConfig, quote_token: &str, irma_amount: u64, now: i64
+) -> Result<()> {
+    redeem_irma_at_market(state_map, config, quote_token, irma_amount, now, &[])
+}
+
+/// redeem_irma, with distribute weighing the reserves' market_prices (see allocation::market_prices).
+pub fn redeem_irma_at_market(
+    state_map: &mut StateMap, config: &ProtocolConfig, quote_token: &str, irma_amount: u64, now: i64,
+    market_prices: &[Option<FixedPrice>],
+) -> Result<()> {
     validate_params(&state_map.reserves, quote_token)?;
 
     if irma_amount == 0 { return Ok(()) };
 
     let state = state_map.get_stablecoin(quote_token).unwrap();
-    // There is a redemption rule: every redemption is limited to 100k IRMA or 10% of the IRMA in circulation (for
-    // the quote token) whichever is smaller.
+    require!(state.status.can_redeem(), CustomError::ReserveNotRedeemable);
+    // There is a redemption rule: every redemption is limited to the reserve's max_redeem_amount IRMA
+    // (100k by default) or 10% of the IRMA in circulation (for the quote token) whichever is smaller.
     let circulation: u128 = state.irma_in_circulation;
-    let irma_amount = (irma_amount as f64 / (10.0_f64).powi(IRMA.backing_decimals as i32)).ceil() as u64;
-    require!((irma_amount <= MAX_REDEEM_AMOUNT as u64), CustomError::InvalidIrmaAmount);
+    let max_irma: u128 = state.max_redeem_irma(config)?;
+    if irma_amount as u128 > max_irma {
+        msg!("Maximum redemption from {} is {} IRMA ({} base units), got {} base units",
+            quote_token, state.max_redeem_tokens(config), max_irma, irma_amount);
+        return Err(error!(CustomError::RedemptionAboveMaximum));
+    }
     require!(circulation >= irma_amount as u128, CustomError::InsufficientCirculation);
 
-    state_map.distribute(quote_token, irma_amount)?;
+    // caps are checked before and counted after distributing, so a failure leaves state_map as it was
+    state_map.check_redemption_caps(quote_token, irma_amount, now)?;
+    state_map.distribute_at_market(config, quote_token, irma_amount, market_prices)?;
+    state_map.record_redemption(quote_token, irma_amount, now)?;
 
     Ok(())
 }
 
+/// Emitted when an instruction raised a reserve's redemption price into another bin of its DLMM pool and its
+/// redemption position was not shifted in the same instruction; keepers should crank check_shift_price_ranges.
+#[event]
+pub struct RedemptionShiftNeeded {
+    pub symbol: String,
+    pub lb_pair: Pubkey, // the reserve's pool_id
+    pub redemption_price: FixedPrice,
+}
+
+/// Redemption prices of reserves, in their order; what crossed_redemption_bins compares against.
+pub fn redemption_prices(reserves: &[StableState]) -> Result<Vec<FixedPrice>> {
+    reserves.iter().map(|r| r.redemption_price()).collect()
+}
+
+/// Reserves whose redemption price rose from before (their redemption_prices before an instruction
+/// changed their backing or circulation) to after into a higher bin of their DLMM pool, with the new
+/// redemption price. Bins are those of the reserve's LbPair in pools (see allocation::pool_states), so the
+/// bin step of each pair decides what counts as a move. A reserve whose LbPair is not passed is not
+/// reported; the periodic check_shift_price_ranges crank moves its redemption position.
+pub fn crossed_redemption_bins(
+    before: &[FixedPrice], after: &[StableState], pools: &[Option<&LbPair>]
+) -> Result<Vec<(String, FixedPrice)>> {
+    require!(before.len() == after.len() && after.len() == pools.len(), CustomError::InvalidReserveList);
+    let mut crossed = Vec::new();
+    for ((old_price, new), lb_pair) in before.iter().zip(after).zip(pools) {
+        let Some(lb_pair) = lb_pair else {
+            continue;
+        };
+        let (old_price, price): (FixedPrice, FixedPrice) = (*old_price, new.redemption_price()?);
+        if price <= old_price || !new.status.can_redeem() {
+            continue;
+        }
+        // DLMM Q64.64 prices: reserve base units per IRMA base unit
+        let old_bin: i32 = allocation::price_bin(lb_pair, fixed_point::rescale(
+            old_price, IRMA.backing_decimals as u32, new.backing_decimals as u32, Rounding::Down)?)?;
+        let bin: i32 = allocation::price_bin(lb_pair, fixed_point::rescale(
+            price, IRMA.backing_decimals as u32, new.backing_decimals as u32, Rounding::Down)?)?;
+        if bin != old_bin {
+            crossed.push((new.symbol.clone(), price));
+        }
+    }
+    Ok(crossed)
+}
+
+/// Ask keepers, through RedemptionShiftNeeded, to shift the redemption positions of the crossed reserves
+/// (see crossed_redemption_bins).
+pub fn request_redemption_shifts(reserves: &[StableState], crossed: &[(String, FixedPrice)]) {
+    for (symbol, redemption_price) in crossed {
+        let lb_pair = reserves.iter().find(|r| &r.symbol == symbol).map(|r| r.pool_id).unwrap_or_default();
+        msg!("Redemption price of {} rose to {}; its redemption position needs a shift", symbol, redemption_price);
+        emit!(RedemptionShiftNeeded { symbol: symbol.clone(), lb_pair, redemption_price: *redemption_price });
+    }
+}
+
+/// Swap amount (base units) of from_token for to_token through IRMA, without the user holding IRMA.
+/// This is a mint of IRMA against from_token followed by a redemption of that IRMA for to_token,
+/// so both reserves are adjusted exactly as a mint and a redemption would adjust them.
+/// The redemption fee is withheld from the payout and booked according to the fee policy.
+/// Returns (amount of to_token paid out, fee withheld), both in to_token base units.
+/// Like a mint, fails if from_token's USD rate is stale at time now.
+pub fn swap_reserves(
+    state_map: &mut StateMap, config: &ProtocolConfig, from_token: &str, to_token: &str, amount: u64, now: i64
+) -> Result<(u64, u64)> {
+    require!(from_token != to_token, CustomError::InvalidQuoteToken);
+    validate_params(&state_map.reserves, to_token)?;
+
+    let circulation_before: u128 = state_map.get_stablecoin(from_token)?.irma_in_circulation;
+    mint_irma(state_map, config, from_token, amount, now)?;
+    let irma_amount: u64 = state_map.get_stablecoin(from_token)?.irma_in_circulation
+        .checked_sub(circulation_before)
+        .and_then(|irma| u64::try_from(irma).ok())
+        .ok_or(CustomError::MathError)?;
+
+    let backing_before: u128 = state_map.get_stablecoin(to_token)?.backing_reserves;
+    redeem_irma(state_map, config, to_token, irma_amount, now)?;
+    let payout: u128 = backing_before
+        .checked_sub(state_map.get_stablecoin(to_token)?.backing_reserves)
+        .ok_or(CustomError::MathError)?;
+    require!(payout > 0, CustomError::InsufficientReserve);
+
+    let fee: u128 = state_map.charge_redemption_fee(to_token, payout)?;
+    let amount_out = u64::try_from(payout - fee).map_err(|_| error!(CustomError::MathError))?;
+    Ok((amount_out, fee as u64))
+}
+
+/// Redeem irma_amount IRMA (base units) from all reserves open for redemption at once, split in proportion to the
+/// USD value of their backing; the rounding remainder goes to the most valuable reserve.
+/// Each share is redeemed from its own reserve at that reserve's redemption price, taking backing and
+/// circulation down together, so redemption prices stay where they are: payouts round down, and a payout
+/// capped at the mint price or a fee kept in the backing can only raise them, possibly into another DLMM
+/// bin (see crossed_redemption_bins). Each share counts against
+/// its reserve's redemption limits and caps and pays the redemption fee.
+/// Returns (symbol, amount paid out, fee withheld) per redeemable reserve in state order, in reserve base units.
+pub fn redeem_basket(
+    state_map: &mut StateMap, config: &ProtocolConfig, irma_amount: u64, now: i64
+) -> Result<Vec<(String, u64, u64)>> {
+    require!(irma_amount > 0, CustomError::InvalidIrmaAmount);
+    let basket: Vec<(String, u128)> = state_map.reserves.iter()
+        .filter(|r| r.status.can_redeem())
+        .map(|r| Ok((r.symbol.clone(), r.usd_value()?)))
+        .collect::<Result<_>>()?;
+    let total_value: u128 = basket.iter()
+        .try_fold(0u128, |total, (_, value)| total.checked_add(*value))
+        .ok_or(CustomError::MathError)?;
+    require!(total_value > 0, CustomError::InsufficientReserve);
+
+    let mut shares: Vec<u64> = basket.iter()
+        .map(|(_, value)| {
+            let share = fixed_point::mul_div(irma_amount as u128, *value, total_value, Rounding::Down)?;
+            u64::try_from(share).map_err(|_| error!(CustomError::MathError))
+        })
+        .collect::<Result<_>>()?;
+    let remainder: u64 = irma_amount - shares.iter().sum::<u64>();
+    let largest: usize = basket.iter().enumerate()
+        .max_by(|(i, (_, a)), (j, (_, b))| a.cmp(b).then(j.cmp(i)))
+        .map(|(i, _)| i)
+        .unwrap();
+    shares[largest] += remainder;
+
+    let mut payouts: Vec<(String, u64, u64)> = Vec::with_capacity(basket.len());
+    for ((symbol, _), share) in basket.into_iter().zip(shares) {
+        if share == 0 {
+            payouts.push((symbol, 0, 0));
+            continue;
+        }
+        let stablecoin = state_map.get_stablecoin(&symbol)?;
+        let max_irma: u128 = stablecoin.max_redeem_irma(config)?;
+        if share as u128 > max_irma {
+            msg!("Maximum redemption from {} is {} IRMA ({} base units), its share is {} base units",
+                symbol, stablecoin.max_redeem_tokens(config), max_irma, share);
+            return Err(error!(CustomError::RedemptionAboveMaximum));
+        }
+        require!(stablecoin.irma_in_circulation >= share as u128, CustomError::InsufficientCirculation);
+        let payout: u128 = stablecoin.redemption_payout(share as u128)?;
+        state_map.record_redemption(&symbol, share, now)?;
+
+        let mut_reserve = state_map.get_mut_stablecoin(&symbol)?;
+        mut_reserve.backing_reserves = mut_reserve.backing_reserves
+            .checked_sub(payout)
+            .ok_or(CustomError::InsufficientReserve)?;
+        mut_reserve.irma_in_circulation -= share as u128;
+
+        let fee: u128 = state_map.charge_redemption_fee(&symbol, payout)?;
+        let amount_out = u64::try_from(payout - fee).map_err(|_| error!(CustomError::MathError))?;
+        payouts.push((symbol, amount_out, fee as u64));
+    }
+    Ok(payouts)
+}
+
 pub fn list_reserves(ctx: Context<Maint>) -> String {
     let state_map = &mut ctx.accounts.state;
     let sorted_list = state_map.list_reserves();
@@ -188,24 +517,15 @@ pub fn get_reserve_info(ctx: Context<Maint>, quote_token: &str) -> Result<Stable
 }
 
 /// Get the current redemption price for a given quote token.
-/// Redemption price = total backing reserves / total IRMA in circulation
-pub fn get_redemption_price(reserves: &Vec<StableState>, quote_token: &str) -> Result<f64> {
+/// Redemption price = total backing reserves / total IRMA in circulation, in whole tokens
+pub fn get_redemption_price(reserves: &[StableState], quote_token: &str) -> Result<FixedPrice> {
     validate_params(reserves, quote_token)?;
     let stablecoin = reserves.iter().find(|r| r.symbol == quote_token).ok_or(error!(CustomError::ReserveNotFound))?;
-    let backing_reserves = stablecoin.backing_reserves;
-    let irma_in_circulation = stablecoin.irma_in_circulation;
-
-    if irma_in_circulation == 0u128 {
-        return Ok(1.0); // Default to 1.0 if no IRMA in circulation
-    }
-
-    let ten_pow_decimals =  10.0_f64.powi(IRMA.backing_decimals as i32 - stablecoin.backing_decimals as i32);
-    let redemption_price = (backing_reserves.checked_div(irma_in_circulation).unwrap_or(0) as f64) * ten_pow_decimals;
-    Ok(redemption_price)
+    stablecoin.redemption_price()
 }
 
 /// Get both mint and redemption prices for a given quote token.
-pub fn get_prices(reserves: &Vec<StableState>, quote_token: &str) -> Result<(f64, f64)> {
+pub fn get_prices(reserves: &[StableState], quote_token: &str) -> Result<(FixedPrice, FixedPrice)> {
     validate_params(reserves, quote_token)?;
 
     let stablecoin = reserves.iter().find(|r| r.symbol == quote_token).unwrap();
@@ -217,28 +537,46 @@ pub fn get_prices(reserves: &Vec<StableState>, quote_token: &str) -> Result<(f64
 
 /// This is the stablecoin struct with the specs for each reserve stablecoin.
 /// Pricing.rs maintains a Vec of these structs in the StateMap account.
-/// Each stablecoin struct uses 128 bytes.
+/// Each stablecoin struct takes StableState::LEN bytes of the account.
 // #[account]
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
 pub struct StableState {
     pub symbol: String, // symbol of the stablecoin, e.g. "USDT"
     pub mint_address: Pubkey, // mint address of the stablecoin
     pub backing_decimals: u64, // need only u8, but for alignment reasons we use u64
-    pub mint_price: f64, // mint price of IRMA in terms of the backing stablecoin
-    pub backing_reserves: u128, // backing reserves is in whole numbers (no decimals)
-    pub irma_in_circulation: u128, // in whole numbers (no decimals)
+    pub mint_price: FixedPrice, // mint price of IRMA in terms of the backing stablecoin (Q64.64), index * usd_rate
+    pub usd_rate: FixedPrice, // units of the backing stablecoin per USD (Q64.64)
+    pub backing_reserves: u128, // in base units of the backing stablecoin (10^backing_decimals per token)
+    pub irma_in_circulation: u128, // in IRMA base units (10^6 per IRMA)
     pub pool_id: Pubkey, // market ID in some Solana DEX
-    pub active: bool, // whether the stablecoin is active or not
-    pub extra: [u8; 15], // padding to make the size of the struct 25 * EnumCount + 8
+    pub status: ReserveStatus, // where the reserve is in its lifecycle
+    pub fees_collected: u128, // lifetime redemption fees withheld, in base units of the backing stablecoin
+    pub treasury_fees: u128, // fees routed to the treasury vault and not yet withdrawn, in base units
+    pub max_update_change_bps: u16, // largest change of usd_rate in a single update
+    pub max_daily_change_bps: u16, // largest change of usd_rate within a PRICE_BOUND_WINDOW
+    pub window_start_rate: FixedPrice, // usd_rate when the current window started
+    pub window_start: i64, // unix timestamp the current window started, 0 before the first update
+    pub price_updated_at: i64, // unix timestamp of the last usd_rate update, 0 if never set
+    pub max_price_age: u32, // seconds after price_updated_at that minting is still allowed
+    pub min_mint_amount: u64, // smallest mint in whole reserve tokens, 0 for ProtocolConfig::min_mint_amount
+    pub max_redeem_amount: u64, // largest redemption in whole IRMA, 0 for ProtocolConfig::max_redeem_amount
+    pub redeem_window_cap: u64, // whole IRMA that may be redeemed per redemption window, 0 for no cap
+    pub redeemed_in_window: u64, // IRMA base units redeemed since StateMap::redeem_window_start
+    pub extra: [u8; 1], // for future use
+}
+
+impl StableState {
+    // serialized size with a symbol of at most 8 bytes, field by field
+    pub const LEN: usize = 4 + 8 + 32 + 8 + 16 * 4 + 32 + 1 + 16 * 2 + 2 * 2 + 16 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 1;
 }
 
 const_assert!(
-    size_of::<StableState>() > 144 // 16 + 32 + 8 + 8 + 16 + 16 + 32 + 1 + 15 = 144 bytes
+    size_of::<StableState>() > 200 // in memory, where the String and alignment padding make it differ from LEN
 );
 
 // Additional useful assertions
 const_assert!(size_of::<StableState>() > 0);
-const_assert!(size_of::<StableState>() <= 160); // At least 152 bytes
+const_assert!(8 + StateMap::LEN <= 10240); // MAX_BACKING_COUNT reserves must fit the 10240 byte initial allocation
 const_assert!(MAX_BACKING_COUNT <= 67); // Ensure we don't exceed account size limits
 const_assert!(MAX_BACKING_COUNT > 0); // Must support at least one stablecoin
 // const_assert_eq!(align_of::<StableState>(), 8); // Ensure proper alignment
@@ -248,7 +586,72 @@ const_assert!(MAX_BACKING_COUNT > 0); // Must support at least one stablecoin
 pub struct StateMap {
     pub reserves: Vec<StableState>,
     pub bump: u8, // Bump seed for PDA
-    pub padding: [u8; 7], // padding to make the size of the struct 25 * EnumCount + 8
+    pub version: u8, // layout version, see STATE_VERSION
+    pub redemption_fee_bps: u16, // redemption fee in basis points
+    pub fee_policy: FeePolicy, // where collected redemption fees go
+    pub redeem_window: u32, // length of the redemption window in seconds
+    pub redeem_window_start: i64, // unix timestamp the current redemption window started
+    pub redeem_window_cap: u64, // whole IRMA that may be redeemed from all reserves per window, 0 for no cap
+    pub redeemed_in_window: u64, // IRMA base units redeemed from all reserves since redeem_window_start
+    pub allocation_strategy: AllocationStrategy, // how distribute splits redeemed IRMA over the reserves
+    pub padding: [u8; 2], // padding to make the size of the struct 25 * EnumCount + 8
+}
+
+/// Where redemption fees go.
+/// Backing: the fee stays in the reserve vault and is added to backing_reserves, raising the redemption price.
+/// Treasury: the fee is moved to the reserve's treasury vault and can be withdrawn by the admin.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FeePolicy {
+    #[default]
+    Backing,
+    Treasury,
+}
+
+/// Lifecycle of a reserve stablecoin; the admin moves it along with set_reserve_status.
+/// Pending: listed by add_reserve, without an LbPair yet; closed for minting and redemption, and can still be removed.
+/// Active: open for minting and redemption.
+/// MintPaused: open for redemption only, until minting is resumed or the reserve is wound down.
+/// RedeemOnly: winding down; open for redemption only, for good.
+/// Retired: closed; its backing and circulation stay on record.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReserveStatus {
+    Pending,
+    Active,
+    MintPaused,
+    RedeemOnly,
+    Retired,
+}
+
+impl ReserveStatus {
+    pub fn can_mint(&self) -> bool {
+        *self == ReserveStatus::Active
+    }
+
+    /// Open for redemption, which also makes the reserve a target of distribute's circulation reductions.
+    pub fn can_redeem(&self) -> bool {
+        matches!(self, ReserveStatus::Active | ReserveStatus::MintPaused | ReserveStatus::RedeemOnly)
+    }
+
+    /// Whether the admin may move a reserve in this status to next.
+    pub fn can_become(&self, next: ReserveStatus) -> bool {
+        use ReserveStatus::*;
+        matches!(
+            (self, next),
+            (Pending, Active)
+                | (Active, MintPaused)
+                | (Active, RedeemOnly)
+                | (MintPaused, Active)
+                | (MintPaused, RedeemOnly)
+                | (RedeemOnly, Retired)
+        )
+    }
+}
+
+/// One deposit of a basket mint: amount in base units of the reserve stablecoin symbol.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BasketDeposit {
+    pub symbol: String,
+    pub amount: u64,
 }
 
 /// Immutable data for IRMA itself.
@@ -258,12 +661,25 @@ pub const IRMA: StableState = StableState {
     symbol: String::new(), // should be "IRMA".to_string(), but doesn't work in const context
     mint_address: pubkey!("irmacFBRx7148dQ6qq1zpzUPq57Jr8V4vi5eXDxsDe1"), // IRMA mint address on Solana
     backing_decimals: 6,
-    mint_price: 1.0,
-    backing_reserves: 1u128,
-    irma_in_circulation: 1u128,
+    mint_price: PRICE_ONE,
+    usd_rate: PRICE_ONE,
+    backing_reserves: 1_000_000u128,
+    irma_in_circulation: 1_000_000u128,
     pool_id: pubkey!("11111111111111111111111111111111"), // unused for IRMA because it is the other side of every pair
-    active: false, // IRMA cannot be a reserve backing of itself
-    extra: [0; 15], // padding
+    status: ReserveStatus::Retired, // IRMA cannot be a reserve backing of itself
+    fees_collected: 0u128,
+    treasury_fees: 0u128,
+    max_update_change_bps: 0,
+    max_daily_change_bps: 0,
+    window_start_rate: PRICE_ONE,
+    window_start: 0,
+    price_updated_at: 0,
+    max_price_age: 0,
+    min_mint_amount: 0,
+    max_redeem_amount: 0,
+    redeem_window_cap: 0,
+    redeemed_in_window: 0,
+    extra: [0; 1], // padding
 };
 
 impl StableState {
@@ -271,32 +687,214 @@ impl StableState {
     pub fn new(symbol: &str, mint_address: Pubkey, backing_decimals: u64) -> Result<Self> {
         // msg!("StableState size: {}", size_of::<StableState>());
         // const len: usize = symbol.to_bytes().len();
-        require!(symbol.len() <= 8 && symbol.len() > 0, CustomError::InvalidBackingSymbol);
+        require!(symbol.len() <= 8 && !symbol.is_empty(), CustomError::InvalidBackingSymbol);
         require!(mint_address != Pubkey::default(), CustomError::InvalidBackingAddress);
         require!(backing_decimals > 0, CustomError::InvalidBacking);
+        require!(backing_decimals <= MAX_BACKING_DECIMALS, CustomError::InvalidBacking);
+        // seed each reserve with one whole token of backing against one whole IRMA
+        let backing_reserves = fixed_point::pow10(backing_decimals as u32)?;
+        let irma_in_circulation = fixed_point::pow10(IRMA.backing_decimals as u32)?;
         Ok(StableState {
             symbol: symbol.to_string(), // symbol of the stablecoin, e.g. "USDT"
             mint_address,
             backing_decimals,
-            mint_price: 1.0f64, // default mint price is 1.0
-            backing_reserves: 1u128,
-            irma_in_circulation: 1u128,
+            mint_price: PRICE_ONE, // default mint price is 1.0
+            usd_rate: PRICE_ONE, // at peg until set_usd_rate is called
+            backing_reserves,
+            irma_in_circulation,
             pool_id: Pubkey::default(), // to be set later, outside of pricing.rs
-            active: true,
-            extra: [0; 15], // for future use
+            status: ReserveStatus::Active, // add_reserve lists it as Pending instead
+            fees_collected: 0u128,
+            treasury_fees: 0u128,
+            max_update_change_bps: DEFAULT_MAX_UPDATE_CHANGE_BPS,
+            max_daily_change_bps: DEFAULT_MAX_DAILY_CHANGE_BPS,
+            window_start_rate: PRICE_ONE,
+            window_start: 0, // the first rate set after listing is not bounded
+            price_updated_at: 0, // no minting until the first usd_rate is set
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            min_mint_amount: 0, // protocol default
+            max_redeem_amount: 0, // protocol default
+            redeem_window_cap: 0, // no cap
+            redeemed_in_window: 0,
+            extra: [0; 1], // for future use
         })
     }
+
+    /// Check a new usd_rate at time now against the per-update and per-window bounds.
+    /// Returns the (rate, start) of the window the update falls in. The first rate after listing is
+    /// not bounded and starts the first window.
+    pub fn check_rate_change(&self, usd_rate: FixedPrice, now: i64) -> Result<(FixedPrice, i64)> {
+        if self.window_start == 0 {
+            return Ok((usd_rate, now));
+        }
+        require!(
+            within_bps(self.usd_rate, usd_rate, self.max_update_change_bps)?,
+            CustomError::PriceChangeOutOfBounds
+        );
+        let window = if now >= self.window_start.saturating_add(PRICE_BOUND_WINDOW) {
+            (self.usd_rate, now)
+        } else {
+            (self.window_start_rate, self.window_start)
+        };
+        require!(
+            within_bps(window.0, usd_rate, self.max_daily_change_bps)?,
+            CustomError::PriceChangeOutOfBounds
+        );
+        Ok(window)
+    }
+
+    /// Smallest mint in whole reserve tokens: the reserve's own limit, or the protocol's if unset.
+    pub fn min_mint_tokens(&self, config: &ProtocolConfig) -> u64 {
+        if self.min_mint_amount > 0 { self.min_mint_amount } else { config.min_mint_amount }
+    }
+
+    /// Largest redemption per transaction in whole IRMA: the reserve's own limit, or the protocol's if unset.
+    pub fn max_redeem_tokens(&self, config: &ProtocolConfig) -> u64 {
+        if self.max_redeem_amount > 0 { self.max_redeem_amount } else { config.max_redeem_amount }
+    }
+
+    /// Smallest mint in reserve base units, min_mint_tokens * 10^backing_decimals.
+    /// Mint amounts are u64, so at 18 decimals no more than about 18 whole tokens can be minted at once;
+    /// such reserves need a min_mint_amount below that.
+    pub fn min_mint_base_units(&self, config: &ProtocolConfig) -> Result<u128> {
+        (self.min_mint_tokens(config) as u128)
+            .checked_mul(fixed_point::pow10(self.backing_decimals as u32)?)
+            .ok_or(error!(CustomError::MathError))
+    }
+
+    /// Largest redemption per transaction in IRMA base units.
+    pub fn max_redeem_irma(&self, config: &ProtocolConfig) -> Result<u128> {
+        (self.max_redeem_tokens(config) as u128)
+            .checked_mul(fixed_point::pow10(IRMA.backing_decimals as u32)?)
+            .ok_or(error!(CustomError::MathError))
+    }
+
+    /// Largest payout per redemption in reserve base units: as many whole reserve tokens as max_redeem_tokens.
+    pub fn max_redeem_payout(&self, config: &ProtocolConfig) -> Result<u128> {
+        (self.max_redeem_tokens(config) as u128)
+            .checked_mul(fixed_point::pow10(self.backing_decimals as u32)?)
+            .ok_or(error!(CustomError::MathError))
+    }
+
+    /// Fail with StaleMintPrice if the USD rate was last updated more than max_price_age seconds before now.
+    pub fn check_price_fresh(&self, now: i64) -> Result<()> {
+        let age = now.saturating_sub(self.price_updated_at);
+        require!(age <= self.max_price_age as i64, CustomError::StaleMintPrice);
+        Ok(())
+    }
+
+    /// Mint price in reserve base units per IRMA base unit, i.e. mint_price * 10^backing_decimals / 10^6.
+    pub fn raw_mint_price(&self, rounding: Rounding) -> Result<FixedPrice> {
+        fixed_point::rescale(self.mint_price, IRMA.backing_decimals as u32, self.backing_decimals as u32, rounding)
+    }
+
+    /// Reserve base units paid out for irma_amount IRMA base units at the redemption price, rounded down.
+    /// Computed directly from the base-unit ratio, so no precision is lost to the Q64.64 price.
+    /// The payout never exceeds the mint price: when the mint price is cut below the redemption price
+    /// (deflation), IRMA minted at the lower price cannot be redeemed for more backing than it brought in.
+    pub fn redemption_payout(&self, irma_amount: u128) -> Result<u128> {
+        require!(self.irma_in_circulation > 0u128, CustomError::InsufficientCirculation);
+        let payout = fixed_point::mul_div(irma_amount, self.backing_reserves, self.irma_in_circulation, Rounding::Down)?;
+        if self.mint_price == 0 {
+            return Ok(payout);
+        }
+        let mint_payout = fixed_point::mul_price(irma_amount, self.raw_mint_price(Rounding::Down)?, Rounding::Down)?;
+        Ok(payout.min(mint_payout))
+    }
+
+    /// USD value of the backing in IRMA base units (6 decimals): backing_reserves / usd_rate, rounded down.
+    pub fn usd_value(&self) -> Result<u128> {
+        let value = fixed_point::div_price(self.backing_reserves, self.usd_rate, Rounding::Down)?;
+        fixed_point::rescale(value, self.backing_decimals as u32, IRMA.backing_decimals as u32, Rounding::Down)
+    }
+
+    /// Redemption price = backing reserves / IRMA in circulation in whole tokens, rounded down.
+    /// Both amounts are in base units, so the base-unit ratio is rescaled by 10^6 / 10^backing_decimals.
+    /// Defaults to 1.0 if no IRMA is in circulation.
+    pub fn redemption_price(&self) -> Result<FixedPrice> {
+        if self.irma_in_circulation == 0u128 {
+            return Ok(PRICE_ONE);
+        }
+        let ratio = fixed_point::from_ratio(self.backing_reserves, self.irma_in_circulation, Rounding::Down)?;
+        fixed_point::rescale(ratio, self.backing_decimals as u32, IRMA.backing_decimals as u32, Rounding::Down)
+    }
+}
+
+impl Default for StateMap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StateMap {
+    pub const LEN: usize = 4 + StableState::LEN * MAX_BACKING_COUNT + 1 + 1 + 2 + 1 + 4 + 8 + 8 + 8 + 1 + 2;
+
     pub fn new() -> Self {
         StateMap {
             reserves: Vec::with_capacity(MAX_BACKING_COUNT), // Initialize with capacity for MAX_BACKING_COUNT stablecoins
             bump: 0,
-            padding: [0; 7], // padding to make the size of the struct 25 * EnumCount + 8
+            version: STATE_VERSION,
+            redemption_fee_bps: DEFAULT_REDEMPTION_FEE_BPS,
+            fee_policy: FeePolicy::Backing,
+            redeem_window: DEFAULT_REDEEM_WINDOW,
+            redeem_window_start: 0,
+            redeem_window_cap: 0, // no cap
+            redeemed_in_window: 0,
+            allocation_strategy: AllocationStrategy::default(),
+            padding: [0; 2], // padding to make the size of the struct 25 * EnumCount + 8
         }
     }
 
+    /// Whether the first redemption at now starts a new redemption window.
+    fn window_expired(&self, now: i64) -> bool {
+        now >= self.redeem_window_start.saturating_add(self.redeem_window as i64)
+    }
+
+    /// Check that irma_amount more from quote_token stays within the redemption caps of the window containing now,
+    /// without counting it. Fails with RedemptionCapReached, logging when the window resets, if the reserve's cap
+    /// or the global cap would be exceeded.
+    pub fn check_redemption_caps(&self, quote_token: &str, irma_amount: u64, now: i64) -> Result<()> {
+        let stablecoin = self.get_stablecoin(quote_token)?;
+        let (window_start, global_redeemed, reserve_redeemed): (i64, u64, u64) = if self.window_expired(now) {
+            (now, 0, 0)
+        } else {
+            (self.redeem_window_start, self.redeemed_in_window, stablecoin.redeemed_in_window)
+        };
+        let resets_at: i64 = window_start.saturating_add(self.redeem_window as i64);
+        let irma_scale: u128 = fixed_point::pow10(IRMA.backing_decimals as u32)?;
+
+        let global_after: u64 = global_redeemed.checked_add(irma_amount).ok_or(CustomError::MathError)?;
+        if self.redeem_window_cap > 0 && global_after as u128 > self.redeem_window_cap as u128 * irma_scale {
+            msg!("Global redemption cap of {} IRMA per window reached ({} base units redeemed); the window resets at {}",
+                self.redeem_window_cap, global_redeemed, resets_at);
+            return Err(error!(CustomError::RedemptionCapReached));
+        }
+        let reserve_after: u64 = reserve_redeemed.checked_add(irma_amount).ok_or(CustomError::MathError)?;
+        if stablecoin.redeem_window_cap > 0 && reserve_after as u128 > stablecoin.redeem_window_cap as u128 * irma_scale {
+            msg!("Redemption cap of {} IRMA per window for {} reached ({} base units redeemed); the window resets at {}",
+                stablecoin.redeem_window_cap, quote_token, reserve_redeemed, resets_at);
+            return Err(error!(CustomError::RedemptionCapReached));
+        }
+        Ok(())
+    }
+
+    /// Count irma_amount redeemed from quote_token against the redemption caps of the window containing now.
+    /// A new window, with every counter at zero, starts at the first redemption redeem_window seconds or more
+    /// after the current one started. Fails as check_redemption_caps does; nothing is counted then.
+    pub fn record_redemption(&mut self, quote_token: &str, irma_amount: u64, now: i64) -> Result<()> {
+        self.check_redemption_caps(quote_token, irma_amount, now)?;
+        if self.window_expired(now) {
+            self.redeem_window_start = now;
+            self.redeemed_in_window = 0;
+            for reserve in self.reserves.iter_mut() {
+                reserve.redeemed_in_window = 0;
+            }
+        }
+        self.redeemed_in_window += irma_amount;
+        self.get_mut_stablecoin(quote_token)?.redeemed_in_window += irma_amount;
+        Ok(())
+    }
+
     /// Add a stablecoin to the reserves, maintaining the order by symbol.
     pub fn add_reserve(&mut self, stablecoin: StableState) {
         if self.contains_reserve(&stablecoin.symbol) {
@@ -311,7 +909,7 @@ impl StateMap {
 
     /// Get a stablecoin by its symbol.
     pub fn get_stablecoin(&self, symbol: &str) -> Result<StableState> {
-        require!(symbol.len() <= 8 && symbol.len() > 0, CustomError::InvalidBackingSymbol);
+        require!(symbol.len() <= 8 && !symbol.is_empty(), CustomError::InvalidBackingSymbol);
         if !self.contains_reserve(symbol) {
             msg!("Symbol {} not found in reserves.", symbol);
             return Err(Error::from(CustomError::SymbolNotFound));
@@ -334,7 +932,7 @@ impl StateMap {
     }
 
     pub fn get_mut_stablecoin(&mut self, symbol: &str) -> Result<&mut StableState> {
-        require!(symbol.len() <= 8 && symbol.len() > 0, CustomError::InvalidBackingSymbol);
+        require!(symbol.len() <= 8 && !symbol.is_empty(), CustomError::InvalidBackingSymbol);
         if !self.contains_reserve(symbol) {
             msg!("Input {} not found in reserves.", symbol);
             return Err(Error::from(CustomError::SymbolNotFound));
@@ -367,38 +965,141 @@ impl StateMap {
         None
     }
 
-    pub fn remove_reserve(&mut self, symbol: &str) -> Option<StableState> {
-        if !self.contains_reserve(symbol) {
-            msg!("Stablecoin {} not found in reserves.", symbol);
-            return None;
+    /// Remove a Pending reserve; any other fails with InvalidStatusTransition.
+    pub fn remove_reserve(&mut self, symbol: &str) -> Result<StableState> {
+        require!(
+            self.get_stablecoin(symbol)?.status == ReserveStatus::Pending,
+            CustomError::InvalidStatusTransition
+        );
+        let i = self.reserves.partition_point(|e| e.symbol.as_str() < symbol);
+        Ok(self.reserves.remove(i))
+    }
+
+    /// Move a reserve to status, if ReserveStatus::can_become allows it.
+    /// A Pending reserve becomes Active only once it has an LbPair (pool_id).
+    pub fn set_reserve_status(&mut self, symbol: &str, status: ReserveStatus) -> Result<()> {
+        let stablecoin = self.get_mut_stablecoin(symbol)?;
+        if !stablecoin.status.can_become(status) {
+            msg!("Stablecoin {} cannot go from {:?} to {:?}", symbol, stablecoin.status, status);
+            return Err(error!(CustomError::InvalidStatusTransition));
+        }
+        if stablecoin.status == ReserveStatus::Pending {
+            require!(stablecoin.pool_id != Pubkey::default(), CustomError::InvalidStatusTransition);
         }
-        let i = self.reserves.partition_point(|e| e.symbol > symbol.to_string());
-        Some(self.reserves.remove(i - 1))
+        stablecoin.status = status;
+        Ok(())
     }
 
-    pub fn disable_reserve(&mut self, symbol: &str) {
-        let result = self.get_mut_stablecoin(symbol);
-        if result.is_err() {    
-            msg!("Stablecoin {} not found in reserves.", symbol);
-            return;
+    /// Set a reserve's USD rate at time now and derive its mint price from the inflation index.
+    /// Fails with PriceChangeOutOfBounds if the change exceeds the reserve's bounds, and with
+    /// MintPriceTooHigh if the mint price would reach config.max_mint_price.
+    pub fn set_usd_rate(
+        &mut self, config: &ProtocolConfig, index: FixedPrice, quote_token: &str, usd_rate: FixedPrice, now: i64
+    ) -> Result<()> {
+        validate_params(&self.reserves, quote_token)?;
+        let window = self.get_stablecoin(quote_token)?.check_rate_change(usd_rate, now)?;
+        self.write_usd_rate(config, index, quote_token, usd_rate, window, now)
+    }
+
+    /// Set a reserve's USD rate at time now regardless of its bounds, starting a new window.
+    /// Only for emergencies; see emergency_set_usd_rate.
+    pub fn override_usd_rate(
+        &mut self, config: &ProtocolConfig, index: FixedPrice, quote_token: &str, usd_rate: FixedPrice, now: i64
+    ) -> Result<()> {
+        validate_params(&self.reserves, quote_token)?;
+        self.write_usd_rate(config, index, quote_token, usd_rate, (usd_rate, now), now)
+    }
+
+    fn write_usd_rate(
+        &mut self, config: &ProtocolConfig, index: FixedPrice, quote_token: &str, usd_rate: FixedPrice,
+        window: (FixedPrice, i64), now: i64
+    ) -> Result<()> {
+        require!(usd_rate > 0, CustomError::InvalidAmount);
+        let mint_price = derive_mint_price(index, usd_rate)?;
+        require!(mint_price < config.max_mint_price, CustomError::MintPriceTooHigh);
+        let stablecoin = self.get_mut_stablecoin(quote_token)?;
+        stablecoin.usd_rate = usd_rate;
+        stablecoin.mint_price = mint_price;
+        (stablecoin.window_start_rate, stablecoin.window_start) = window;
+        stablecoin.price_updated_at = now;
+        Ok(())
+    }
+
+    /// Set the largest relative change of a reserve's USD rate per update and per PRICE_BOUND_WINDOW.
+    pub fn set_price_bounds(&mut self, quote_token: &str, max_update_change_bps: u16, max_daily_change_bps: u16) -> Result<()> {
+        validate_params(&self.reserves, quote_token)?;
+        require!(
+            max_update_change_bps > 0
+                && max_update_change_bps <= max_daily_change_bps
+                && max_daily_change_bps as u128 <= BASIS_POINTS_MAX,
+            CustomError::InvalidAmount
+        );
+        let stablecoin = self.get_mut_stablecoin(quote_token)?;
+        stablecoin.max_update_change_bps = max_update_change_bps;
+        stablecoin.max_daily_change_bps = max_daily_change_bps;
+        Ok(())
+    }
+
+    /// Recompute every reserve's mint price from a new inflation index.
+    /// Fails with MintPriceTooHigh, repricing no reserve, if a mint price would reach config.max_mint_price:
+    /// every mint price must stay index * usd_rate, so the admin has to lower that reserve's USD rate first.
+    pub fn reprice(&mut self, config: &ProtocolConfig, index: FixedPrice) -> Result<()> {
+        let mut mint_prices: Vec<FixedPrice> = Vec::with_capacity(self.reserves.len());
+        for stablecoin in self.reserves.iter() {
+            let mint_price = derive_mint_price(index, stablecoin.usd_rate)?;
+            if mint_price >= config.max_mint_price {
+                msg!("Mint price too high for {}", stablecoin.symbol);
+                return Err(error!(CustomError::MintPriceTooHigh));
+            }
+            mint_prices.push(mint_price);
         }
-        let stablecoin = result.unwrap();
-        if stablecoin.backing_decimals > 0 {
-            stablecoin.active = false;
-            msg!("Deactivated stablecoin: {}", symbol);
-        } else {
-            msg!("Stablecoin found in reserves: {}, but it's not valid", symbol);
+        for (stablecoin, mint_price) in self.reserves.iter_mut().zip(mint_prices) {
+            stablecoin.mint_price = mint_price;
+        }
+        Ok(())
+    }
+
+    /// Redemption fee on payout (base units of a reserve), rounded up.
+    pub fn redemption_fee(&self, payout: u128) -> Result<u128> {
+        fixed_point::mul_div(payout, self.redemption_fee_bps as u128, BASIS_POINTS_MAX, Rounding::Up)
+    }
+
+    /// Withhold the redemption fee from payout (base units of quote_token) and book it
+    /// according to the fee policy. Returns the fee, rounded up.
+    pub fn charge_redemption_fee(&mut self, quote_token: &str, payout: u128) -> Result<u128> {
+        let fee: u128 = self.redemption_fee(payout)?;
+        let fee_policy = self.fee_policy;
+        let stablecoin = self.get_mut_stablecoin(quote_token)?;
+        stablecoin.fees_collected = stablecoin.fees_collected
+            .checked_add(fee)
+            .ok_or(CustomError::MathError)?;
+        match fee_policy {
+            FeePolicy::Backing => {
+                stablecoin.backing_reserves = stablecoin.backing_reserves
+                    .checked_add(fee)
+                    .ok_or(CustomError::MathError)?;
+            }
+            FeePolicy::Treasury => {
+                stablecoin.treasury_fees = stablecoin.treasury_fees
+                    .checked_add(fee)
+                    .ok_or(CustomError::MathError)?;
+            }
         }
+        Ok(fee)
     }
 
     pub fn contains_reserve(&self, symbol: &str) -> bool {
-        self.reserves.iter().any(|e| &e.symbol == symbol)
+        self.reserves.iter().any(|e| e.symbol == symbol)
     }
     
     pub fn len(&self) -> usize {
         self.reserves.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.reserves.is_empty()
+    }
+
     pub fn list_reserves(&self) -> Vec<String> {
         let sorted_reserves = self.reserves.iter()
             .map(|e| e.symbol.clone())
@@ -408,224 +1109,46 @@ impl StateMap {
 
     /// Distrubute (ReduceCirculations) implementation
     /// This now deals with mint_price being less than redemption_price (a period of deflation).
-    /// If the price of the underlying reserve goes up with respect to USD, its exchange rate with IRMA
-    /// would improve (i.e. IRMA would be worth less in terms of the reserve). In this case, the system
-    /// would be expected to have a higher redemption price for IRMA than mint price; however, because
-    /// the objective is always to preserve the backing, the system will not allow the mint price 
-    /// to be less than the redemption price. Instead, it will simply set the redemption price to the mint price.
-    /// NOTE: irma_amount is now scaled down by the backing_decimals of IRMA.
-    pub fn distribute(&mut self, quote_token: &str, irma_amount: u64) -> Result<()> {
+    /// If the price of the underlying reserve goes up with respect to USD, or the inflation index is
+    /// deliberately cut during deflation, the mint price can fall below the redemption price; however,
+    /// because the objective is always to preserve the backing, redemptions are then paid at the mint price
+    /// (see StableState::redemption_payout) and the excess backing stays in the reserve.
+    /// The payout always comes out of quote_token's backing; which reserves' circulation the redeemed IRMA
+    /// comes off is up to the allocator of allocation_strategy (see allocation.rs), whose split must pass
+    /// allocation::check_allocation.
+    /// NOTE: irma_amount is in IRMA base units; backing and circulation are in base units as well.
+    pub fn distribute(&mut self, config: &ProtocolConfig, quote_token: &str, irma_amount: u64) -> Result<()> {
+        self.distribute_at_market(config, quote_token, irma_amount, &[])
+    }
+
+    /// distribute, with the allocator also weighing the reserves' market prices (see allocation::market_prices).
+    pub fn distribute_at_market(
+        &mut self, config: &ProtocolConfig, quote_token: &str, irma_amount: u64, market_prices: &[Option<FixedPrice>]
+    ) -> Result<()> {
 
         msg!("Distributing redemption for {} IRMA in {}", irma_amount, quote_token);
 
         require!(quote_token.len() > 2, CustomError::InvalidQuoteToken);
-        let reserves = &mut self.reserves;
-        let clone_reserves = reserves.clone();
-
-        // if there's only a single reserve, save ourselves the trouble and just adjust
-        // quantities for this one stablecoin.
-        if clone_reserves.len() == 1 {
-            let stablecoin = &self.get_stablecoin(quote_token).unwrap();
-            let ro_circulation: u128 = stablecoin.irma_in_circulation;
-            let reserve: u128 = stablecoin.backing_reserves;
-            let ten_pow_decimals =  10.0_f64.powi(
-                IRMA.backing_decimals as i32 - stablecoin.backing_decimals as i32
-            );
-            let redemption_price = ((reserve.checked_div(ro_circulation).unwrap_or(0) as f64)
-                 * ten_pow_decimals) as f64;
-            let subject_adjustment: u64 = (irma_amount as f64 * redemption_price).ceil() as u64; // irma_amount is in whole numbers, so we can use it directly
-
-            require!(reserve >= subject_adjustment as u128, CustomError::InsufficientReserve);
-            require!(ro_circulation >= irma_amount as u128, CustomError::InsufficientCirculation);
-            
-            let mut_reserve = self.get_mut_stablecoin(quote_token).unwrap();
-            
-            // Subtract from backing reserves
-            mut_reserve.backing_reserves = mut_reserve.backing_reserves
-                .checked_sub(subject_adjustment.into())
-                .ok_or(CustomError::InsufficientReserve)?;
-                
-            // Subtract from circulation
-            mut_reserve.irma_in_circulation = mut_reserve.irma_in_circulation
-                .checked_sub(irma_amount.into())
-                .ok_or(CustomError::InsufficientCirculation)?;
+        let subject: usize = self.reserves.iter().position(|r| r.symbol == quote_token)
+            .ok_or(CustomError::InvalidQuoteToken)?;
 
-            return Ok(());
-        }
+        // payouts round down
+        let subject_adjustment: u128 = self.reserves[subject].redemption_payout(irma_amount as u128)?;
+        require!(self.reserves[subject].backing_reserves >= subject_adjustment, CustomError::InsufficientReserve);
 
-        // determine what this redemption does:
-        // does it keep the relative spreads even, or does it skew the spreads?
-        let mut count: u8 = 0;
-        let mut average_diff: f64 = 0.0;
-        let price_differences: BTreeMap<String, f64> = clone_reserves.iter()
-            .enumerate()
-            .filter_map(|(i, reserve)| {
-                let key = reserve.symbol.to_string();
-                let reserve = reserve.clone(); // clone to get a copy of the StableState
-                // msg!("{}: {}", i, reserve.symbol.to_string());
-                let circulation = reserve.irma_in_circulation;
-                let backing_reserves = reserve.backing_reserves;
-                let stablecoin = reserve.clone();
-                let ten_pow_decimals = 10.0_f64.powi(
-                    IRMA.backing_decimals as i32 - stablecoin.backing_decimals as i32
-                );
-                let redemption_price = (backing_reserves.checked_div(circulation).unwrap_or(0) as f64)
-                     * (ten_pow_decimals as f64);
-                let mint_price = reserve.mint_price;
-                if mint_price == 0.0 || reserve.backing_decimals == 0 || reserve.active == false {
-                    // msg!("Skipping {}: mint_price is 0.0 or backing_decimals is 0", Stablecoins::from_index(i).unwrap().to_string());
-                    return Some((key, 0.0));
-                }
-                count += 1;
-                if count != i as u8 + 1 {
-                    msg!("Warning: count is not equal to index + 1, count: {}, index: {}", count, i);
-                }
-                let x: f64 = mint_price - redemption_price;
-                average_diff += x;
-                Some((key, x))
-            })
-            .collect();
-        require!(count > 0, CustomError::InvalidBacking);
-        // if count == 0 {
-        //     // msg!("No price differences found, returning early.");
-        //     return Ok(());
-        // }
-        average_diff /= count as f64;
-        // msg!("Average price difference: {}", average_diff);
-
-        let min_diff: f64 = 0.001; // price differences below this are ignored
-
-        let mut max_price_diff: f64 = average_diff;
-        let mut other_target: &String = &quote_token.to_string();
-        for (_i, (key, price_diff)) in price_differences.iter().enumerate() {
-            // msg!("{}: {}, max {}", i, *price_diff, max_price_diff);
-            if (*price_diff - max_price_diff).abs() > min_diff && *price_diff > max_price_diff {
-                max_price_diff = *price_diff;
-                other_target = key;
-            }
-        }
-        // msg!("Max token: {}", other_target.to_string());
-        // msg!("Max price diff: {}", max_price_diff);
-
-        let stablecoin = &self.get_stablecoin(quote_token).unwrap();
-        let ro_circulation: u128 = stablecoin.irma_in_circulation;
-        let reserve: u128 = stablecoin.backing_reserves;
-        let ten_pow_decimals =  10.0_f64.powi(
-            IRMA.backing_decimals as i32 - stablecoin.backing_decimals as i32
-        );
-        let redemption_price = ((reserve.checked_div(ro_circulation).unwrap_or(0) as f64)
-             * ten_pow_decimals) as f64;
-        let subject_adjustment: u64 = (irma_amount as f64 * redemption_price).ceil() as u64; // irma_amount is in whole numbers, so we can use it directly
+        let reductions: Vec<u128> = self.allocation_strategy.allocator()
+            .allocate(&self.reserves, subject, irma_amount, config, market_prices)?;
+        allocation::check_allocation(&self.reserves, subject, irma_amount, &reductions)?;
 
-        // no matter what, we need to reduce the subject reserve (quote_token)
-        require!(reserve >= subject_adjustment as u128, CustomError::InsufficientReserve);
-        let mut_reserve = self.get_mut_stablecoin(quote_token).unwrap();
+        let mut_reserve = &mut self.reserves[subject];
         mut_reserve.backing_reserves = mut_reserve.backing_reserves
-            .checked_sub(subject_adjustment.into())
+            .checked_sub(subject_adjustment)
             .ok_or(CustomError::InsufficientReserve)?;
-
-        // Now determine which other stableoin this redeemed circulation can be subtracted from.
-        // The objective is to reduce the price spread between mint price and redemption price,
-        // and we choose that stablecoin which has the greatest spread.
-        
-        // if max price diff does not deviate much from average diff or all inflation-adjusted prices 
-        // are less than the redemption prices, then reductions pertain to quote_token only.
-        if (average_diff.abs() < min_diff) || (average_diff < 0.0) {
-            // msg!("No significant price differences found");
-            if price_differences[&quote_token.to_string()] >= 0.0 || *other_target == *quote_token {
-                // msg!("If quote_token m price is larger than r price, then situation is normal.");
-                // If the price difference is positive, it means that the mint price is higher than the redemption price;
-                // in this case, we need to reduce IRMA in circulation by the irma_amount.
-                // Note that this keeps price differences the same (it's minting that adjusts redemption price).
-                let circulation: u128 = self.get_stablecoin(quote_token).unwrap().irma_in_circulation;
-                require!(circulation >= irma_amount as u128, CustomError::InsufficientCirculation);
-                let mut_reserve = self.get_mut_stablecoin(quote_token).unwrap();
-                mut_reserve.irma_in_circulation = mut_reserve.irma_in_circulation
-                    .checked_sub(irma_amount.into())
-                    .ok_or(CustomError::InsufficientCirculation)?;
-            } else {
-                msg!("m price <= r price for quote token, adjust backing reserve only for {:?}.", quote_token);
-                // If the price difference is negative, it means that the mint price is lower than the redemption price;
-                // in this case, we need to set the redemption price eq to the mint price in order to preserve the backing.
-                // We also do not reduce IRMA in circulation, which effectively means that we are still draining the reserve,
-                // but not by much, while the reduction in the ratio of reserve to IRMA in circulation (normally the
-                // redemption price) goes down faster than if we also reduced IRMA in circulation. 
-                // And we're done!
-            }
-            // msg!("New reserve for {}: {}", quote_token.to_string(), *reserve);
-            // let ro_circulation: u64 = reserves[quote_token].irma_in_circulation;
-            // msg!("New circulation for {}: {}", quote_token.to_string(), ro_circulation);
-            return Ok(());
-        }
-        // All the following code is for the semi-normal case, in which the mint price 
-        // is higher than or equal to the redemption price; but the price differences
-        // can be large.
-        // msg!("Other target for normal adjustments: {}", other_target.to_string());
-
-        let other_stablecoin = &self.get_stablecoin(other_target).unwrap();
-        let other_circulation: u128 = other_stablecoin.irma_in_circulation;
-        let other_price: f64 = other_stablecoin.mint_price;
-        let other_reserve: u128 = other_stablecoin.backing_reserves;
-        let ten_pow_decimals =  10.0_f64.powi(
-            IRMA.backing_decimals as i32 - stablecoin.backing_decimals as i32
-        );
-        let other_red_price: f64 = ((other_reserve.checked_div(other_circulation).unwrap_or(0) as f64) 
-            * ten_pow_decimals) as f64;
-
-        let price: f64 = stablecoin.mint_price;
-        let reserve: u128 = stablecoin.backing_reserves;
-        let ro_circulation: u128 = stablecoin.irma_in_circulation;
-
-        let other_price_diff: f64 = other_price - other_red_price as f64;
-        let post_price_diff: f64 = price
-            - ((reserve as i128 - (irma_amount as f64 / price) as i128) / ro_circulation as i128) as f64;
-        let post_other_price_diff: f64 = other_price 
-            - (other_reserve as i128 / (other_circulation as i128 - irma_amount as i128)) as f64;
-
-        if other_price_diff < post_other_price_diff {
-            // msg!("--> Other price diff is less than or equal to post other price diff, adjusting second circulation only.");
-            // if irma_amount is such that it could not improve the redemption price when applied to other stabecoin reserve,
-            // we can just subtract from the circulation (same as normal case).
-            // Note that the normal case does not change redemtion prices.
-            // let circulation: u128 = stablecoin.irma_in_circulation;
-            // require!(irma_amount <= circulation, CustomError::InsufficientCirculation);
-            let mut_reserve = self.get_mut_stablecoin(quote_token).unwrap();
-            mut_reserve.irma_in_circulation = mut_reserve.irma_in_circulation
-                .checked_sub(irma_amount.into())
-                .ok_or(CustomError::InsufficientCirculation)?;
-        } else
-        if post_other_price_diff < post_price_diff {
-            // msg!("--> Post other price diff is less than or equal to second price diff, 
-            //         adjusting other circulation only.");
-            // if irma_amount is such that it would reduce discrepancy for other stablecoin more post 
-            // adjustment, we can choose to subtract irma_amount from the other_circulation only
-            require!(irma_amount as u128 <= other_circulation, CustomError::InsufficientCirculation);
-            let mut_reserve = self.get_mut_stablecoin(other_target).unwrap();
-            mut_reserve.irma_in_circulation = mut_reserve.irma_in_circulation
-                .checked_sub(irma_amount.into())
+        for (reserve, reduction) in self.reserves.iter_mut().zip(reductions) {
+            reserve.irma_in_circulation = reserve.irma_in_circulation
+                .checked_sub(reduction)
                 .ok_or(CustomError::InsufficientCirculation)?;
-        } else {
-            // if irma amount is such that it doesn't improve the redemption price for either stablecoin,
-            // we can do a linear adjustment of both other and second circulations.
-            // msg!("--> First and second prices are close enough, adjusting both circulations linearly.");
-            // Do simple linear adjustment of both other and second circulations
-            let adjustment_amount: f64 = irma_amount as f64 * (other_price_diff - post_price_diff) / (other_price_diff + post_price_diff);
-            // msg!("Adjustment amount: {}", adjustment_amount);
-            require!(adjustment_amount > 0.0, CustomError::InvalidAmount);
-            require!(adjustment_amount <= irma_amount as f64, CustomError::InvalidAmount);
-            // msg!("Adjusting other circulation by {} and second circulation by {}", adjustment_amount.ceil(), irma_amount as f64 - adjustment_amount.ceil());
-            let mut_reserve = self.get_mut_stablecoin(other_target).unwrap();
-            mut_reserve.irma_in_circulation = mut_reserve.irma_in_circulation
-                .checked_sub(adjustment_amount.ceil() as u128)
-                .ok_or(CustomError::InsufficientCirculation)?;
-            let mut_reserve = self.get_mut_stablecoin(quote_token).unwrap();
-            mut_reserve.irma_in_circulation = ro_circulation.checked_sub(
-                irma_amount.checked_add((adjustment_amount.ceil() as u128).try_into()
-                .unwrap_or(0)).unwrap_or(0).into()
-            ).ok_or(CustomError::InsufficientCirculation)?;
         }
-
-        return Ok(());
+        Ok(())
     }
 }
-
@@ -0,0 +1,254 @@
+// programs/irma/src/queue.rs
+//
+// Queued redemptions.
+// A redemption that a reserve cannot cover now can wait in the reserve's RedemptionQueue instead of
+// failing. The user escrows the IRMA in the reserve's escrow token account, owned by the vault authority,
+// together with the smallest price they accept. Every mint against the reserve, alone, in a basket or as
+// the input of a swap, then fills queued requests in FIFO order as far as the new backing allows: the
+// escrowed IRMA is burned, and the payout less the redemption fee stays in the reserve vault, set aside
+// for the owner to claim. Requests whose price is not met are passed over and stay queued; the first
+// request the reserve cannot cover stops the filling, so later requests never jump ahead of it. Until its
+// request is filled, the owner can cancel it and get the escrowed IRMA back.
+// Payouts set aside are no longer part of backing_reserves, so the vault holds at least backing_reserves
+// plus the unclaimed payouts of the queue.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, TransferChecked};
+use commons::dlmm::types::Rounding;
+
+use crate::config::ProtocolConfig;
+use crate::custody::pay_from_vault;
+use crate::errors::CustomError;
+use crate::fixed_point::{self, FixedPrice};
+use crate::pricing::{self, FeePolicy, StateMap, IRMA};
+use crate::{CancelRedemption, ClaimRedemption, InitRedemptionQueue, QueueRedemption};
+
+/// Seed of a reserve's RedemptionQueue PDA, followed by the reserve mint.
+pub const REDEMPTION_QUEUE_SEED: &[u8] = b"redemption_queue";
+/// Seed of a reserve's IRMA escrow token account, followed by the reserve mint.
+pub const ESCROW_SEED: &[u8] = b"irma_escrow";
+pub const MAX_QUEUED_REDEMPTIONS: usize = 32;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct QueuedRedemption {
+    pub id: u64,
+    pub owner: Pubkey,
+    pub irma_amount: u64, // IRMA base units in escrow, 0 once filled
+    pub min_price: FixedPrice, // smallest accepted price after the fee, reserve tokens per IRMA
+    pub queued_at: i64,
+    pub payout: u64, // reserve base units set aside for the owner once filled
+    pub treasury_fee: u64, // reserve base units to move to the treasury vault when claimed
+}
+
+impl QueuedRedemption {
+    pub const LEN: usize = 8 + 32 + 8 + 16 + 8 + 8 + 8;
+
+    pub fn is_filled(&self) -> bool {
+        self.irma_amount == 0
+    }
+}
+
+#[account]
+#[derive(PartialEq, Debug)]
+pub struct RedemptionQueue {
+    pub mint: Pubkey, // reserve mint
+    pub requests: Vec<QueuedRedemption>, // in the order they were queued
+    pub next_id: u64,
+    pub bump: u8, // Bump seed for PDA
+}
+
+impl RedemptionQueue {
+    pub const LEN: usize = 32 + 4 + MAX_QUEUED_REDEMPTIONS * QueuedRedemption::LEN + 8 + 1;
+
+    pub fn new(mint: Pubkey, bump: u8) -> Self {
+        RedemptionQueue {
+            mint,
+            requests: Vec::with_capacity(MAX_QUEUED_REDEMPTIONS),
+            next_id: 0,
+            bump,
+        }
+    }
+
+    /// Append a request of owner for irma_amount IRMA at min_price or better; returns its id.
+    pub fn enqueue(&mut self, owner: Pubkey, irma_amount: u64, min_price: FixedPrice, now: i64) -> Result<u64> {
+        require!(irma_amount > 0, CustomError::InvalidIrmaAmount);
+        require!(self.requests.len() < MAX_QUEUED_REDEMPTIONS, CustomError::RedemptionQueueFull);
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).ok_or(CustomError::MathError)?;
+        self.requests.push(QueuedRedemption {
+            id,
+            owner,
+            irma_amount,
+            min_price,
+            queued_at: now,
+            payout: 0,
+            treasury_fee: 0,
+        });
+        Ok(id)
+    }
+
+    /// Position of owner's request id.
+    fn position(&self, owner: &Pubkey, id: u64) -> Result<usize> {
+        let position = self.requests.iter().position(|r| r.id == id)
+            .ok_or(CustomError::RedemptionNotFound)?;
+        require_keys_eq!(self.requests[position].owner, *owner, CustomError::Unauthorized);
+        Ok(position)
+    }
+
+    /// Remove owner's unfilled request id; returns the IRMA to give back from escrow.
+    pub fn cancel(&mut self, owner: &Pubkey, id: u64) -> Result<u64> {
+        let position = self.position(owner, id)?;
+        require!(!self.requests[position].is_filled(), CustomError::RedemptionAlreadyFilled);
+        Ok(self.requests.remove(position).irma_amount)
+    }
+
+    /// Remove owner's filled request id; returns it with the payout to transfer.
+    pub fn claim(&mut self, owner: &Pubkey, id: u64) -> Result<QueuedRedemption> {
+        let position = self.position(owner, id)?;
+        require!(self.requests[position].is_filled(), CustomError::RedemptionNotFilled);
+        Ok(self.requests.remove(position))
+    }
+
+    /// Reserve base units in the vault that belong to filled, unclaimed requests.
+    pub fn unclaimed(&self) -> Result<u64> {
+        self.requests.iter()
+            .try_fold(0u64, |total, r| total.checked_add(r.payout)?.checked_add(r.treasury_fee))
+            .ok_or(error!(CustomError::MathError))
+    }
+}
+
+/// Fill the queued requests against symbol's reserve in FIFO order, with vault_amount the reserve vault's
+/// balance. Each fill is a redemption through pricing::redeem_irma, subject to the same limits and caps,
+/// and pays the redemption fee. Returns the escrowed IRMA (base units) of the filled requests, to be burned.
+/// Whether a request can be filled is decided from the reserve's state before redeeming, so state_map
+/// only changes for the requests that are filled.
+pub fn fill(
+    queue: &mut RedemptionQueue, state_map: &mut StateMap, config: &ProtocolConfig, symbol: &str,
+    vault_amount: u64, now: i64,
+) -> Result<u64> {
+    let stablecoin = state_map.get_stablecoin(symbol)?;
+    require_keys_eq!(stablecoin.mint_address, queue.mint, CustomError::InvalidQuoteToken);
+    let max_payout: u128 = stablecoin.max_redeem_payout(config)?;
+    let backing_decimals: u32 = stablecoin.backing_decimals as u32;
+    let mut available: u64 = vault_amount.checked_sub(queue.unclaimed()?).ok_or(CustomError::InsufficientReserve)?;
+    let mut filled_irma: u64 = 0;
+
+    for request in queue.requests.iter_mut().filter(|r| !r.is_filled()) {
+        // payout and fee as redeem_irma and charge_redemption_fee will take them
+        let Ok(payout) = state_map.get_stablecoin(symbol)?.redemption_payout(request.irma_amount as u128) else {
+            break;
+        };
+        if payout > max_payout || payout > available as u128 {
+            break;
+        }
+        let payout: u64 = payout as u64;
+        let fee: u64 = state_map.redemption_fee(payout as u128)? as u64;
+        let amount_out: u64 = payout - fee;
+        let min_price: FixedPrice = fixed_point::rescale(
+            request.min_price, IRMA.backing_decimals as u32, backing_decimals, Rounding::Up)?;
+        let min_out: u128 = fixed_point::mul_price(request.irma_amount as u128, min_price, Rounding::Up)?;
+        if (amount_out as u128) < min_out {
+            // price not met; the request keeps its place in the queue
+            continue;
+        }
+        if pricing::redeem_irma(state_map, config, symbol, request.irma_amount, now).is_err() {
+            break;
+        }
+        state_map.charge_redemption_fee(symbol, payout as u128)?;
+
+        request.payout = amount_out;
+        request.treasury_fee = if state_map.fee_policy == FeePolicy::Treasury { fee } else { 0 };
+        available -= amount_out + request.treasury_fee;
+        filled_irma = filled_irma.checked_add(request.irma_amount).ok_or(CustomError::MathError)?;
+        msg!("Filled queued redemption {} of {} IRMA for {} {} (fee {})",
+            request.id, request.irma_amount, amount_out, symbol, fee);
+        request.irma_amount = 0;
+    }
+    Ok(filled_irma)
+}
+
+/// Create the redemption queue and IRMA escrow of a listed reserve.
+pub fn init_redemption_queue(ctx: Context<InitRedemptionQueue>) -> Result<()> {
+    let mint = ctx.accounts.reserve_mint.key();
+    ctx.accounts.state.get_stablecoin_symbol(mint).ok_or(error!(CustomError::ReserveNotFound))?;
+    *ctx.accounts.redemption_queue = RedemptionQueue::new(mint, ctx.bumps.redemption_queue);
+    Ok(())
+}
+
+/// Escrow irma_amount IRMA (base units) to be redeemed for the reserve once it can pay at least
+/// min_price (reserve tokens per IRMA after the fee, Q64.64).
+pub fn queue_redemption(ctx: Context<QueueRedemption>, irma_amount: u64, min_price: FixedPrice) -> Result<()> {
+    let accounts = ctx.accounts;
+    let symbol = accounts.state.get_stablecoin_symbol(accounts.reserve_mint.key())
+        .ok_or(error!(CustomError::ReserveNotFound))?;
+    let stablecoin = accounts.state.get_stablecoin(&symbol)?;
+    let max_irma: u128 = stablecoin.max_redeem_irma(&accounts.protocol_config)?;
+    if irma_amount as u128 > max_irma {
+        msg!("Maximum redemption from {} is {} IRMA ({} base units), got {} base units",
+            symbol, stablecoin.max_redeem_tokens(&accounts.protocol_config), max_irma, irma_amount);
+        return Err(error!(CustomError::RedemptionAboveMaximum));
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let id = accounts.redemption_queue.enqueue(accounts.user.key(), irma_amount, min_price, now)?;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            accounts.irma_token_program.to_account_info(),
+            TransferChecked {
+                from: accounts.user_irma_account.to_account_info(),
+                mint: accounts.irma_mint.to_account_info(),
+                to: accounts.irma_escrow.to_account_info(),
+                authority: accounts.user.to_account_info(),
+            },
+        ),
+        irma_amount,
+        accounts.irma_mint.decimals,
+    )?;
+    msg!("Queued redemption {} of {} IRMA for {} at {} or better", id, irma_amount, symbol, min_price);
+    Ok(())
+}
+
+/// Cancel the user's unfilled request id and return its escrowed IRMA.
+pub fn cancel_redemption(ctx: Context<CancelRedemption>, id: u64) -> Result<()> {
+    let accounts = ctx.accounts;
+    let irma_amount = accounts.redemption_queue.cancel(&accounts.user.key(), id)?;
+    pay_from_vault(
+        &accounts.irma_token_program,
+        &accounts.irma_escrow,
+        &accounts.irma_mint,
+        accounts.user_irma_account.to_account_info(),
+        accounts.vault_authority.to_account_info(),
+        ctx.bumps.vault_authority,
+        irma_amount,
+    )?;
+    msg!("Cancelled queued redemption {}, returned {} IRMA", id, irma_amount);
+    Ok(())
+}
+
+/// Pay out the user's filled request id from the reserve vault.
+pub fn claim_redemption(ctx: Context<ClaimRedemption>, id: u64) -> Result<()> {
+    let accounts = ctx.accounts;
+    let request = accounts.redemption_queue.claim(&accounts.user.key(), id)?;
+    pay_from_vault(
+        &accounts.reserve_token_program,
+        &accounts.reserve_vault,
+        &accounts.reserve_mint,
+        accounts.user_reserve_account.to_account_info(),
+        accounts.vault_authority.to_account_info(),
+        ctx.bumps.vault_authority,
+        request.payout,
+    )?;
+    if request.treasury_fee > 0 {
+        pay_from_vault(
+            &accounts.reserve_token_program,
+            &accounts.reserve_vault,
+            &accounts.reserve_mint,
+            accounts.treasury_vault.to_account_info(),
+            accounts.vault_authority.to_account_info(),
+            ctx.bumps.vault_authority,
+            request.treasury_fee,
+        )?;
+    }
+    msg!("Claimed queued redemption {}: {} base units", id, request.payout);
+    Ok(())
+}
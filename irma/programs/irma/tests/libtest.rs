@@ -1,7 +1,5 @@
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
     use anchor_lang::prelude::*;
     use anchor_lang::prelude::Pubkey;
     // use anchor_lang::prelude::Clock;
@@ -15,24 +13,24 @@ mod tests {
     // use anchor_lang::Accounts;
 
     use irma::irma as money;
-    use irma::pricing::{StateMap, StableState};
+    use irma::pricing::StateMap;
     use irma::IRMA_ID;
     use irma::pricing::MAX_BACKING_COUNT;
-    use irma::pricing::{init_pricing, set_mint_price, mint_irma, redeem_irma, list_reserves};
-    use irma::{Init, Maint, InitBumps, MaintBumps};
+    use irma::{Init, InitBumps};
+    use irma::meteora_integration::Core;
     // use irma::State;
 
 
 
     #[test]
-    fn test_crank<'info>() -> Result<()> {
+    fn test_crank() -> Result<()> {
         let program_id: &Pubkey = &IRMA_ID;
         msg!("Starting crank test...");
 
         fn prep_accounts<'info>(owner: &'info Pubkey, state_account: Pubkey) -> (AccountInfo<'info>, AccountInfo<'info>, AccountInfo<'info>) {
             // Create a buffer for StateMap and wrap it in AccountInfo
             let lamports: &mut u64 = Box::leak(Box::new(100000u64));
-            let mut state: StateMap = allocate_state();
+            let state: StateMap = allocate_state();
             // let _ = state.init_reserves(); // Add initial stablecoins to the state
 
             // Prepare the account data with the correct discriminator
@@ -87,7 +85,7 @@ mod tests {
         }
 
         fn allocate_state() -> StateMap {
-            let mut state: StateMap = StateMap::new();
+            let state: StateMap = StateMap::new();
             // state.init_reserves().unwrap(); // Initialize reserves
             state
         }
@@ -96,16 +94,28 @@ mod tests {
         let (state_account_info, irma_admin_account_info, sys_account_info) 
                  = prep_accounts(program_id, state_account);
         // Bind to variables to extend their lifetime
-        let state_account_static: &'info AccountInfo<'info> = Box::leak(Box::new(state_account_info));
-        let irma_admin_account_static: &'info AccountInfo<'info> = Box::leak(Box::new(irma_admin_account_info));
-        let sys_account_static: &'info AccountInfo<'info> = Box::leak(Box::new(sys_account_info));
-        let mut accounts = Init {
+        let state_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(state_account_info));
+        let irma_admin_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(irma_admin_account_info));
+        let sys_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(sys_account_info));
+        let mut core_data: Vec<u8> = Vec::new();
+        Core::create_core(Pubkey::default(), vec![])?.try_serialize(&mut core_data)?;
+        let core_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false, // is_signer
+            true,  // is_writable
+            Box::leak(Box::new(100000u64)),
+            Box::leak(core_data.into_boxed_slice()),
+            program_id,
+            false,
+            0,
+        )));
+        let accounts = Init {
             state: Account::try_from(state_account_static).unwrap(),
             irma_admin: Signer::try_from(irma_admin_account_static).unwrap(),
             system_program: Program::try_from(sys_account_static).unwrap(),
-            core: Account::try_from(state_account_static).unwrap(), // Placeholder
+            core: Account::try_from(core_account_static).unwrap(),
         };
-        let mut accounts_static: &mut Init = Box::leak(Box::new(accounts));
+        let accounts_static: &mut Init = Box::leak(Box::new(accounts));
         let ctx: Context<Init> = Context::new(
             program_id,
             accounts_static, // &mut accounts,
@@ -113,7 +123,7 @@ mod tests {
             InitBumps::default(), // Use default bumps if not needed
         );
         let crank_result: std::result::Result<(), Error> = money::initialize(ctx,
-            "OwnerPubkeyString".to_string(),
+            Pubkey::new_unique().to_string(),
             vec![]
         );
         assert!(crank_result.is_ok());
@@ -6,7 +6,6 @@ mod core_test {
     use super::*;
     // use super::*;
     use anchor_lang::prelude::*;
-    use std::env;
     // use std::mem::size_of;
     // use std::sync::Arc;
     use irma::IRMA_ID;
@@ -14,12 +13,12 @@ mod core_test {
     use irma::position_manager::{AllPosition};
     use irma::pricing::init_pricing;
     use irma::pricing::MAX_BACKING_COUNT;
-    use irma::pricing::StateMap;
+    use irma::pricing::{StateMap, StableState};
     use irma::meteora_integration::Core;
+    use irma::config::{ProtocolConfig, DEFAULT_SLIPPAGE_BPS};
     use irma::{MarketMakingMode, Init, Maint, InitBumps, MaintBumps};
     use commons::dlmm::accounts::{LbPair, PositionV2};
     use commons::dlmm::types::{UserRewardInfo, FeeInfo, StaticParameters, VariableParameters, ProtocolFee, RewardInfo};
-    use anchor_lang::prelude::borsh::BorshSerialize;
 
     // Use SystemTime
     fn get_current_time_test() -> u64 {
@@ -29,6 +28,17 @@ mod core_test {
             .as_secs()
     }
 
+    // Syscall stubs that give instructions the Clock sysvar off-chain
+    struct ClockStubs;
+
+    impl solana_program::program_stubs::SyscallStubs for ClockStubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock { unix_timestamp: get_current_time_test() as i64, ..Clock::default() };
+            unsafe { *(var_addr as *mut Clock) = clock; }
+            0 // SUCCESS
+        }
+    }
+
     // Helper function to create mock AccountInfo
     fn create_mock_account_info<'a>(
         key: &'a Pubkey,
@@ -94,6 +104,16 @@ mod core_test {
         }
     }
 
+    fn protocol_config_account<'info>() -> Account<'info, ProtocolConfig> {
+        let mut data: Vec<u8> = Vec::new();
+        ProtocolConfig::default().try_serialize(&mut data).unwrap();
+        let key: &'info Pubkey = Box::leak(Box::new(Pubkey::new_unique()));
+        let lamports: &'info mut u64 = Box::leak(Box::new(0u64));
+        let info: &'info AccountInfo<'info> = Box::leak(Box::new(
+            create_mock_account_info(key, lamports, Box::leak(data.into_boxed_slice()), &IRMA_ID)));
+        Account::try_from(info).unwrap()
+    }
+
     fn prep_accounts<'info>(
             owner: &'info Pubkey, // program owner, not user owner
             state_account: Pubkey,
@@ -108,7 +128,7 @@ mod core_test {
         ) {
         // Create a buffer for StateMap and wrap it in AccountInfo
         let lamports: &mut u64 = Box::leak(Box::new(100000u64));
-        let mut state: StateMap = allocate_state();
+        let state: StateMap = allocate_state();
         // let _ = state.init_reserves(); // Add initial stablecoins to the state
 
         // Prepare the account data with the correct discriminator
@@ -140,12 +160,13 @@ mod core_test {
             = Box::leak(Box::new(Pubkey::from_str_const("68bjdGBTr4yRxLW56s7LvpQehMn9jBvaJvV134NQjpmP")));
 
         let lamportsc: &mut u64 = Box::leak(Box::new(1000000u64));
-        let all_positions = AllPosition::new(&config).unwrap().all_positions;
-        let all_positions = all_positions.into_iter().map(|pe| pe.lb_pair).collect::<Vec<_>>();
+        // Core starts without pairs; add the test pair the way update_reserve_lbpair does
         let core_state = &mut Core::create_core(
             *signer_pubkey, // owner
-            all_positions,
+            vec![],
         ).unwrap();
+        core_state.config = config.clone();
+        core_state.position_data = AllPosition::new(&config).unwrap();
         let mut core_data_vec: Vec<u8> = Vec::with_capacity(std::mem::size_of::<Core>());
         core_state.try_serialize(&mut core_data_vec).unwrap();
         let core_data: &'info mut Vec<u8> = Box::leak(Box::new(core_data_vec));
@@ -227,9 +248,13 @@ mod core_test {
             _reserved: [0u8; 22],
         };
         let lb_pair_data_vec = bytemuck::bytes_of(&lb_pair_state).to_vec();
-        let mut lb_pair_data = vec![33, 11, 49, 98, 181, 101, 177, 13]; // discriminator
-        lb_pair_data.extend_from_slice(&lb_pair_data_vec);
-        let lb_pair_data: &'info mut Vec<u8> = Box::leak(Box::new(lb_pair_data));
+        let mut lb_pair_bytes = vec![33, 11, 49, 98, 181, 101, 177, 13]; // discriminator
+        lb_pair_bytes.extend_from_slice(&lb_pair_data_vec);
+        // LbPair is read in place, so the data after the discriminator must be aligned like LbPair
+        let buffer: &'info mut [u8] = bytemuck::cast_slice_mut(
+            Box::leak(vec![0u128; lb_pair_bytes.len() / 16 + 2].into_boxed_slice()));
+        let lb_pair_data: &'info mut [u8] = &mut buffer[8..8 + lb_pair_bytes.len()];
+        lb_pair_data.copy_from_slice(&lb_pair_bytes);
         let lb_pair_lamports: &mut u64 = Box::leak(Box::new(100000u64));
         let lb_pair_owner: &'info mut Pubkey = Box::leak(Box::new(Pubkey::default()));
         let lb_pair_account_info: AccountInfo<'info> = AccountInfo::new(
@@ -338,25 +363,31 @@ mod core_test {
         );
         let result: std::result::Result<(), Error> = init_pricing(&mut ctx);
         assert!(result.is_ok());
+        // the reserve traded in the test pair
+        let mut usdc = StableState::new(
+            "devUSDC", Pubkey::from_str_const("BRjpCHtyQLNCo8gqRUr8jtdAj5AjPYQaoqbvcZiHok1k"), 6).unwrap();
+        usdc.pool_id = *lb_pair;
+        ctx.accounts.state.add_reserve(usdc);
         // msg!("StateMap account: {:?}", accounts.state);
-        return (accounts.state,
+        (accounts.state,
             accounts.irma_admin,
             accounts.system_program,
             position_account_info,
             lb_pair_account_info,
-            accounts.core);
+            accounts.core)
     }
 
     #[test]
     fn test_withdraw() {
         let program_id: &Pubkey = &IRMA_ID;
 
+        solana_program::program_stubs::set_syscall_stubs(Box::new(ClockStubs));
         let lb_pair = Pubkey::from_str_const("FoSDw2L5DmTuQTFe55gWPDXf88euaxAEKFre74CnvQbX");
         let (state_account,
             mut irma_admin_account,
             sys_account,
             position_account_info,
-            lb_pair_account_info,
+            _lb_pair_account_info,
             core_account)
                 = initialize_anchor(program_id, &lb_pair);
 
@@ -364,6 +395,7 @@ mod core_test {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
 
@@ -371,7 +403,7 @@ mod core_test {
         let mut core = accounts.core.clone();
         
         let remaining_accounts: &[AccountInfo] = &[position_account_info];
-        let mut ctx: Context<Maint> = Context::new(
+        let _ctx: Context<Maint> = Context::new(
             program_id,
             &mut accounts,
             remaining_accounts,
@@ -381,12 +413,12 @@ mod core_test {
         core.refresh_position_data(
             &state_account.reserves,
             remaining_accounts,
-            "BRjpCHtyQLNCo8gqRUr8jtdAj5AjPYQaoqbvcZiHok1k".to_string() // devUSDC
+            "devUSDC".to_string()
         ).unwrap();
 
         let state = {
             let mut_state = core.get_mut_position_state(lb_pair);
-            let lb_pair_data = &lb_pair_account_info.data.borrow()[8..];
+            // let lb_pair_data = &lb_pair_account_info.data.borrow()[8..];
             // let lb_pair_state = bytemuck::pod_read_unaligned::<LbPair>(
             //     &lb_pair_data
             // );
@@ -399,9 +431,11 @@ mod core_test {
     }
 
     #[test]
+    #[ignore = "the DLMM swap CPI only runs on the Solana runtime"]
     fn test_swap() {
         let program_id: &Pubkey = &IRMA_ID;
 
+        solana_program::program_stubs::set_syscall_stubs(Box::new(ClockStubs));
         let lb_pair = Pubkey::from_str_const("FoSDw2L5DmTuQTFe55gWPDXf88euaxAEKFre74CnvQbX");
         let (state_account,
             mut irma_admin_account,
@@ -415,6 +449,7 @@ mod core_test {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
 
@@ -422,7 +457,7 @@ mod core_test {
         let mut core = accounts.core.clone();
         
         let remaining_accounts: &[AccountInfo] = &[position_account_info];
-        let mut ctx: Context<Maint> = Context::new(
+        let _ctx: Context<Maint> = Context::new(
             program_id,
             &mut accounts,
             remaining_accounts,
@@ -432,12 +467,12 @@ mod core_test {
         core.refresh_position_data(
             &state_account.reserves,
             remaining_accounts,
-            "BRjpCHtyQLNCo8gqRUr8jtdAj5AjPYQaoqbvcZiHok1k".to_string() // devUSDC
+            "devUSDC".to_string()
         ).unwrap();
 
         let state = {
             let mut_state = core.get_mut_position_state(lb_pair);
-            let lb_pair_data = &lb_pair_account_info.data.borrow()[8..];
+            // let lb_pair_data = &lb_pair_account_info.data.borrow()[8..];
             // let lb_pair_state = bytemuck::pod_read_unaligned::<LbPair>(
             //     &lb_pair_data
             // );
@@ -445,6 +480,8 @@ mod core_test {
             mut_state.clone() // Clone the state to end the mutable borrow
         };
 
-        core.swap(&mut irma_admin_account, remaining_accounts, &state, 1000000, true).unwrap();
+        // the swap reads the pair as well
+        let swap_accounts: &[AccountInfo] = &[remaining_accounts[0].clone(), lb_pair_account_info.clone()];
+        core.swap(&mut irma_admin_account, swap_accounts, &state, 1000000, true, DEFAULT_SLIPPAGE_BPS).unwrap();
     }
 }
@@ -1,8 +1,6 @@
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
     use anchor_lang::prelude::*;
     use anchor_lang::prelude::Pubkey;
     use anchor_lang::system_program;
@@ -14,21 +12,89 @@ mod tests {
     // use bytemuck::bytes_of_mut;
     // use anchor_lang::Discriminator;
     use irma::IRMA_ID;
-    use irma::pricing::{StateMap, StableState};
-    use irma::pricing::{init_pricing, set_mint_price, mint_irma, redeem_irma, list_reserves};
-    use irma::pricing::MAX_BACKING_COUNT;
-    use irma::{Init, Maint, InitBumps, MaintBumps};
+    use irma::pricing::{StateMap, StableState, FeePolicy, BasketDeposit, ReserveStatus, MAX_MINT_PRICE};
+    use irma::migration::{self, LegacyStableState, LegacyStateMap};
+    use irma::pricing::{mint_irma, redeem_irma, list_reserves, swap_reserves};
+    use irma::inflation::{InflationIndex, DeflationPolicy, DEFAULT_INFLATION_PERIOD, MAX_INFLATION_PERIOD};
+    use irma::oracle::{self, FeedKind, MockPriceFeed, PriceFeed, PythFeed, TruflationFeed};
+    use irma::attestation::{self, PriceAttestation, PriceAuthorities};
+    use irma::aggregation::{self, PriceAggregator, PriceSubmission};
+    use irma::history::{PriceHistory, PRICE_HISTORY_LEN};
+    use irma::config::{ProtocolConfig, MAX_SLIPPAGE_BPS, MAX_MIN_PRICE_DIFF};
+    use irma::queue::{self, RedemptionQueue};
+    use irma::allocation::{self, AllocationStrategy};
+    use irma::pricing::{self, MAX_BACKING_COUNT, MAX_REDEMPTION_FEE_BPS};
+    use irma::{Init, Maint, InitBumps, MaintBumps, ManageState, ManageStateBumps};
+    use irma::errors::CustomError;
     use irma::meteora_integration::Core;
+    use irma::fixed_point::{self, PRICE_ONE};
+    use commons::dlmm::accounts::LbPair;
+    use commons::MAX_BIN_ID;
+    use commons::dlmm::types::Rounding;
 
     
     fn allocate_state() -> StateMap {
         StateMap::new()
     }
 
+    fn config() -> ProtocolConfig {
+        ProtocolConfig::default()
+    }
+
+    fn protocol_config_account() -> Account<'static, ProtocolConfig> {
+        let mut data: Vec<u8> = Vec::new();
+        config().try_serialize(&mut data).unwrap();
+        let info: &'static AccountInfo<'static> = Box::leak(Box::new(AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false, // is_signer
+            false, // is_writable
+            Box::leak(Box::new(0u64)),
+            Box::leak(data.into_boxed_slice()),
+            &IRMA_ID,
+            false,
+            0,
+        )));
+        Account::try_from(info).unwrap()
+    }
+
+    /// A program-owned account at key holding data.
+    fn program_account(key: Pubkey, is_signer: bool, data: Vec<u8>) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(key)),
+            is_signer,
+            !is_signer, // is_writable
+            Box::leak(Box::new(0u64)),
+            Box::leak(data.into_boxed_slice()),
+            &IRMA_ID,
+            false,
+            0,
+        )
+    }
+
+    /// ManageState accounts for state, with a Core owned by owner, signed by signer.
+    fn manage_state(state: &StateMap, owner: Pubkey, signer: Pubkey) -> Result<ManageState<'static>> {
+        let pda = |seed: &[u8]| Pubkey::find_program_address(&[seed], &IRMA_ID).0;
+        let mut state_data: Vec<u8> = Vec::new();
+        state.try_serialize(&mut state_data)?;
+        let mut core_data: Vec<u8> = Vec::new();
+        Core::create_core(owner, vec![])?.try_serialize(&mut core_data)?;
+        let accounts: &'static [AccountInfo<'static>] = Box::leak(Box::new([
+            program_account(pda(b"state_v5"), false, state_data),
+            program_account(signer, true, vec![]),
+            program_account(pda(b"core_v5"), false, core_data),
+        ]));
+        ManageState::try_accounts(
+            &IRMA_ID, &mut &accounts[..], &[], &mut ManageStateBumps::default(), &mut std::collections::BTreeSet::new())
+    }
+
+    fn manage_ctx<'a>(accounts: &'a mut ManageState<'static>) -> Context<'static, 'a, 'static, 'static, ManageState<'static>> {
+        Context::new(&IRMA_ID, accounts, &[], ManageStateBumps::default())
+    }
+
     fn init_state() -> StateMap {
         let mut state: StateMap = allocate_state();
         let usdt: StableState = 
-            StableState::new("USDT", pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6 as u64).unwrap();
+            StableState::new("USDT", pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6_u64).unwrap();
         state.add_reserve(usdt);
         assert_eq!(state.len(), 1);
         state
@@ -38,14 +104,14 @@ mod tests {
     fn test_set_state_directly() -> Result<()> {
         let mut state: StateMap = init_state();
         let quote_token: &str = "USDT";
-        let new_price: f64 = 1.23;
+        let new_price: u128 = fixed_point::from_decimal(123, 2, Rounding::Down)?;
         {
             let mut_reserve = state.get_mut_stablecoin(quote_token).unwrap();
             // assert_eq!(mut_reserve.mint_price, 1.0);
-            mut_reserve.mint_price = 1.0;
+            mut_reserve.mint_price = PRICE_ONE;
         }
         {
-            assert_eq!(state.get_stablecoin(quote_token).unwrap().mint_price, 1.0);
+            assert_eq!(state.get_stablecoin(quote_token).unwrap().mint_price, PRICE_ONE);
         }
         {
             let mut_reserve = state.get_mut_stablecoin(quote_token).unwrap();
@@ -66,11 +132,11 @@ mod tests {
         // Simulate mint_irma logic
         let mut_reserve = state.get_mut_stablecoin(quote_token).unwrap();
         mut_reserve.backing_reserves += amount;
-        mut_reserve.irma_in_circulation += (amount as f64 / price).ceil() as u128;
+        mut_reserve.irma_in_circulation += fixed_point::div_price(amount, price, Rounding::Down)?;
         assert_eq!(state.get_stablecoin(quote_token).unwrap().backing_reserves, 
             prev_reserve + amount);
         assert_eq!(state.get_stablecoin(quote_token).unwrap().irma_in_circulation, 
-            prev_circulation + (amount as f64 / price).ceil() as u128);
+            prev_circulation + fixed_point::div_price(amount, price, Rounding::Down)?);
         Ok(())
     }
 
@@ -100,7 +166,7 @@ mod tests {
         {
             // Manipulate state to create a price difference
             let mut_reserve = state.get_mut_stablecoin("USDT").unwrap();
-            mut_reserve.mint_price = 2.0;
+            mut_reserve.mint_price = 2 * PRICE_ONE;
             mut_reserve.backing_reserves = 1000;
             mut_reserve.irma_in_circulation = 100;
             mut_reserve.irma_in_circulation -= irma_amount;
@@ -110,12 +176,1313 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fixed_point_rounding() -> Result<()> {
+        // 1.23 is not exact in Q64.64, so the rounding direction shows up in the product
+        let price_down = fixed_point::from_decimal(123, 2, Rounding::Down)?;
+        let price_up = fixed_point::from_decimal(123, 2, Rounding::Up)?;
+        assert_eq!(price_up - price_down, 1);
+        assert_eq!(fixed_point::mul_price(100, price_down, Rounding::Down)?, 122);
+        assert_eq!(fixed_point::mul_price(100, price_down, Rounding::Up)?, 123);
+        assert_eq!(fixed_point::mul_price(100, price_up, Rounding::Down)?, 123);
+        // 10 / 3 rounds in the requested direction
+        assert_eq!(fixed_point::div_price(10, 3 * PRICE_ONE, Rounding::Down)?, 3);
+        assert_eq!(fixed_point::div_price(10, 3 * PRICE_ONE, Rounding::Up)?, 4);
+        // rescaling between decimals
+        assert_eq!(fixed_point::rescale(1_500_000, 6, 9, Rounding::Down)?, 1_500_000_000);
+        assert_eq!(fixed_point::rescale(1_500_001, 6, 0, Rounding::Down)?, 1);
+        assert_eq!(fixed_point::rescale(1_500_001, 6, 0, Rounding::Up)?, 2);
+        assert!(fixed_point::div_price(10, 0, Rounding::Down).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fractional_redemption_price() -> Result<()> {
+        let mut state = init_state();
+        {
+            let mut_reserve = state.get_mut_stablecoin("USDT").unwrap();
+            mut_reserve.backing_reserves = 9_900;
+            mut_reserve.irma_in_circulation = 10_000;
+        }
+        // 0.99 is kept exactly instead of being truncated to 0
+        let redemption_price = state.get_stablecoin("USDT")?.redemption_price()?;
+        assert_eq!(redemption_price, fixed_point::from_decimal(99, 2, Rounding::Down)?);
+
+        // a single-reserve redemption pays out irma_amount * 0.99, rounded down
+        state.distribute(&config(), "USDT", 150)?;
+        let usdt = state.get_stablecoin("USDT")?;
+        assert_eq!(usdt.backing_reserves, 9_900 - 148);
+        assert_eq!(usdt.irma_in_circulation, 10_000 - 150);
+        Ok(())
+    }
+
+    #[test]
+    fn test_base_unit_amounts_across_decimals() -> Result<()> {
+        for decimals in [6u64, 8, 18] {
+            let mut state = allocate_state();
+            state.add_reserve(StableState::new(
+                "USDX", pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), decimals).unwrap());
+            let one_token: u128 = fixed_point::pow10(decimals as u32)?;
+            // the seed reserve prices at exactly 1.0 regardless of decimals
+            assert_eq!(state.get_stablecoin("USDX")?.redemption_price()?, PRICE_ONE);
+            // 18 decimals cannot reach the default 100 token minimum in a u64 amount
+            state.get_mut_stablecoin("USDX")?.min_mint_amount = 1;
+            let prev = state.get_stablecoin("USDX")?;
+
+            // 123.456789 tokens minted at 1.0 (12.345678 for 18 decimals, to fit the u64 amount)
+            let irma_expected: u128 = if decimals == 18 { 12_345_678 } else { 123_456_789 };
+            let amount: u64 = fixed_point::rescale(irma_expected, 6, decimals as u32, Rounding::Down)?
+                .try_into().unwrap();
+            mint_irma(&mut state, &config(), "USDX", amount, 0)?;
+            let after_mint = state.get_stablecoin("USDX")?;
+            // no fraction of the deposit is lost
+            assert_eq!(after_mint.backing_reserves, prev.backing_reserves + amount as u128);
+            assert_eq!(after_mint.irma_in_circulation, prev.irma_in_circulation + irma_expected);
+            assert_eq!(after_mint.redemption_price()?, PRICE_ONE);
+
+            // redeem half an IRMA
+            redeem_irma(&mut state, &config(), "USDX", 500_000, 0)?;
+            let after_redeem = state.get_stablecoin("USDX")?;
+            assert_eq!(after_redeem.backing_reserves, after_mint.backing_reserves - one_token / 2);
+            assert_eq!(after_redeem.irma_in_circulation, after_mint.irma_in_circulation - 500_000);
+        }
+        assert!(StableState::new("USDX", pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 19).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_reserves_accounting() -> Result<()> {
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        for symbol in ["USDC", "USDT"] {
+            let mut_reserve = state.get_mut_stablecoin(symbol).unwrap();
+            mut_reserve.backing_reserves = 1_000_000_000;
+            mut_reserve.irma_in_circulation = 1_000_000_000;
+        }
+
+        // 200 USDT in, 200 USDC out less the 0.01% fee, which stays in the USDC backing
+        let (amount_out, fee) = swap_reserves(&mut state, &config(), "USDT", "USDC", 200_000_000, 0)?;
+        assert_eq!(fee, 20_000);
+        assert_eq!(amount_out, 200_000_000 - 20_000);
+        let usdt = state.get_stablecoin("USDT")?;
+        let usdc = state.get_stablecoin("USDC")?;
+        assert_eq!(usdt.backing_reserves, 1_200_000_000);
+        assert_eq!(usdt.irma_in_circulation, 1_200_000_000);
+        assert_eq!(usdc.backing_reserves, 800_000_000 + 20_000);
+        assert_eq!(usdc.irma_in_circulation, 800_000_000);
+
+        // the USDC reserve cannot cover a swap larger than its circulation
+        assert!(swap_reserves(&mut state, &config(), "USDT", "USDC", 900_000_000, 0).is_err());
+        assert!(swap_reserves(&mut state, &config(), "USDT", "USDT", 200_000_000, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_inflation() -> Result<()> {
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        let mut index = InflationIndex::new(0);
+        state.set_usd_rate(&config(), index.index, "USDC", 2 * PRICE_ONE, 0)?;
+
+        // one day at 3.65% a year raises the index by 0.01%
+        let now: i64 = 1_750_000_000;
+        index.compound(365, now)?;
+        assert_eq!(index.index, PRICE_ONE + PRICE_ONE / 10_000);
+        assert_eq!(index.last_applied, now);
+        state.reprice(&config(), index.index)?;
+        assert_eq!(state.get_stablecoin("USDT")?.mint_price, index.index);
+        assert_eq!(state.get_stablecoin("USDC")?.mint_price, 2 * index.index);
+
+        // at most once per period
+        assert!(index.compound(365, now + DEFAULT_INFLATION_PERIOD - 1).is_err());
+        index.compound(0, now + DEFAULT_INFLATION_PERIOD)?;
+        assert_eq!(index.index, PRICE_ONE + PRICE_ONE / 10_000);
+
+        // a half-day period may be applied twice a day and compounds half a day of the rate
+        index.set_period(DEFAULT_INFLATION_PERIOD / 2)?;
+        let before = index.index;
+        index.compound(730, now + DEFAULT_INFLATION_PERIOD * 3 / 2)?;
+        assert_eq!(index.index, before + before / 10_000);
+        for period in [0, -1, MAX_INFLATION_PERIOD + 1] {
+            assert_eq!(index.set_period(period).err(), Some(error!(CustomError::InvalidInflationPeriod)));
+        }
+        assert_eq!(index.period, DEFAULT_INFLATION_PERIOD / 2);
+
+        // a mint price reaching the configured maximum fails the repricing and leaves every price as it was
+        let mut capped = config();
+        capped.set_max_mint_price(2 * PRICE_ONE)?;
+        let prices = (state.get_stablecoin("USDT")?.mint_price, state.get_stablecoin("USDC")?.mint_price);
+        assert_eq!(state.reprice(&capped, PRICE_ONE).err(), Some(error!(CustomError::MintPriceTooHigh)));
+        assert_eq!(state.reprice(&config(), MAX_MINT_PRICE / 2).err(), Some(error!(CustomError::MintPriceTooHigh)));
+        assert_eq!((state.get_stablecoin("USDT")?.mint_price, state.get_stablecoin("USDC")?.mint_price), prices);
+        // and so does a USD rate update
+        assert_eq!(
+            state.override_usd_rate(&capped, PRICE_ONE, "USDT", 2 * PRICE_ONE, now).err(),
+            Some(error!(CustomError::MintPriceTooHigh))
+        );
+        state.override_usd_rate(&capped, PRICE_ONE, "USDT", 2 * PRICE_ONE - 1, now)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tolerable_inflation_and_deflation() -> Result<()> {
+        let mut state = init_state();
+        mint_irma(&mut state, &config(), "USDT", 100_000_000, 0)?;
+        let mut index = InflationIndex::new(0);
+        let mut now: i64 = 1_750_000_000;
+
+        // inflation within the tolerable 1% leaves the index alone
+        index.compound(50, now)?;
+        assert_eq!(index.index, PRICE_ONE);
+        assert_eq!(index.last_rate_bps, 50);
+
+        // 3.65% a year of deflation lowers the index below 1.0 by 0.01% a day
+        now += DEFAULT_INFLATION_PERIOD;
+        index.compound(-365, now)?;
+        assert_eq!(index.index, PRICE_ONE - PRICE_ONE / 10_000);
+        state.reprice(&config(), index.index)?;
+        let usdt = state.get_stablecoin("USDT")?;
+        assert!(usdt.mint_price < usdt.redemption_price()?);
+
+        // redemptions are paid at the lower mint price and the IRMA is burned as usual
+        let backing = usdt.backing_reserves;
+        let circulation = usdt.irma_in_circulation;
+        let irma_amount: u64 = 10_000_000;
+        redeem_irma(&mut state, &config(), "USDT", irma_amount, 0)?;
+        let usdt = state.get_stablecoin("USDT")?;
+        let payout = fixed_point::mul_price(irma_amount as u128, index.index, Rounding::Down)?;
+        assert_eq!(usdt.backing_reserves, backing - payout);
+        assert_eq!(usdt.irma_in_circulation, circulation - irma_amount as u128);
+
+        // holding through deflation keeps the index, and the tolerable rate cannot be negative
+        index.set_params(100, DeflationPolicy::Hold)?;
+        now += DEFAULT_INFLATION_PERIOD;
+        index.compound(-365, now)?;
+        assert_eq!(index.index, PRICE_ONE - PRICE_ONE / 10_000);
+        assert!(index.set_params(-1, DeflationPolicy::Track).is_err());
+        now += DEFAULT_INFLATION_PERIOD;
+        assert!(index.compound(-20_000, now).is_err());
+        Ok(())
+    }
+
+    fn feed_account(owner: Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            false,
+            Box::leak(Box::new(1_000_000u64)),
+            Box::leak(data.into_boxed_slice()),
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_oracle_feeds() -> Result<()> {
+        // mock feed: 0.9998 USD per token +/- 0.0001, published at t = 1000
+        let mock = MockPriceFeed { price: 99_980_000, conf: 10_000, exponent: -8, publish_time: 1000 };
+        let mut data: Vec<u8> = Vec::new();
+        mock.try_serialize(&mut data)?;
+        let reading = oracle::read_feed(FeedKind::Mock, &feed_account(IRMA_ID, data.clone()))?;
+        reading.check_age(1000 + oracle::MAX_PRICE_AGE, oracle::MAX_PRICE_AGE)?;
+        assert!(reading.check_age(1001 + oracle::MAX_PRICE_AGE, oracle::MAX_PRICE_AGE).is_err());
+        let usd_rate = oracle::usd_rate_from_reading(&reading)?;
+        let mut state = init_state();
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", usd_rate, 1000)?;
+        let mint_price = state.get_stablecoin("USDT")?.mint_price;
+        assert_eq!(fixed_point::mul_price(99_980_000, mint_price, Rounding::Down)?, 100_000_000);
+
+        // feeds must be owned by the program their adapter expects
+        assert!(oracle::read_feed(FeedKind::Mock, &feed_account(Pubkey::new_unique(), data)).is_err());
+        assert!(oracle::read_feed(FeedKind::Pyth, &feed_account(IRMA_ID, vec![0u8; 200])).is_err());
+
+        // confidence wider than 1% of the price is rejected
+        let wide = oracle::OracleReading { confidence: 1_000_000, ..reading };
+        assert!(wide.to_price().is_err());
+
+        // Pyth PriceUpdateV2, fully verified: 1.0001 USD per token
+        let mut pyth: Vec<u8> = oracle::PYTH_PRICE_UPDATE_DISCRIMINATOR.to_vec();
+        pyth.extend_from_slice(&[0u8; 32]); // write authority
+        pyth.push(1); // VerificationLevel::Full
+        pyth.extend_from_slice(&[7u8; 32]); // feed id
+        pyth.extend_from_slice(&100_010_000i64.to_le_bytes());
+        pyth.extend_from_slice(&5_000u64.to_le_bytes());
+        pyth.extend_from_slice(&(-8i32).to_le_bytes());
+        pyth.extend_from_slice(&1000i64.to_le_bytes());
+        pyth.extend_from_slice(&[0u8; 40]); // prev publish time, ema price, ema conf, posted slot
+        let reading = PythFeed::read(&feed_account(oracle::PYTH_RECEIVER_ID, pyth.clone()))?;
+        assert_eq!(reading.to_price()?, fixed_point::from_decimal(100_010_000, 8, Rounding::Down)?);
+        let mut partial = pyth[..40].to_vec();
+        partial.extend_from_slice(&[0u8, 3u8]); // VerificationLevel::Partial { num_signatures: 3 }
+        partial.extend_from_slice(&pyth[41..]);
+        assert!(PythFeed::parse(&partial).is_err());
+
+        // Truflation on Switchboard: 2.85% +/- 0.01%, in percent with 18 decimals
+        let mut truflation = vec![0u8; oracle::SWITCHBOARD_RESULT_OFFSET + 64];
+        truflation[..8].copy_from_slice(&oracle::SWITCHBOARD_PULL_FEED_DISCRIMINATOR);
+        let at = oracle::SWITCHBOARD_LAST_UPDATE_OFFSET;
+        truflation[at..at + 8].copy_from_slice(&1000i64.to_le_bytes());
+        let at = oracle::SWITCHBOARD_RESULT_OFFSET;
+        truflation[at..at + 16].copy_from_slice(&2_850_000_000_000_000_000i128.to_le_bytes());
+        truflation[at + 16..at + 32].copy_from_slice(&10_000_000_000_000_000i128.to_le_bytes());
+        let reading = TruflationFeed::read(&feed_account(oracle::SWITCHBOARD_ON_DEMAND_ID, truflation))?;
+        assert_eq!(reading.publish_time, 1000);
+        assert_eq!(reading.to_rate_bps()?, 285);
+        let deflation = oracle::OracleReading { mantissa: -reading.mantissa, ..reading };
+        assert_eq!(deflation.to_rate_bps()?, -285);
+        Ok(())
+    }
+
+    fn ed25519_instruction(authority: &Pubkey, message: &[u8]) -> solana_program::instruction::Instruction {
+        // one signature, with key, signature and message inside this instruction (index u16::MAX)
+        let (pubkey_offset, signature_offset, message_offset) = (16u16, 48u16, 112u16);
+        let mut data: Vec<u8> = vec![1, 0];
+        for value in [signature_offset, u16::MAX, pubkey_offset, u16::MAX, message_offset, message.len() as u16, u16::MAX] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&authority.to_bytes());
+        data.extend_from_slice(&[9u8; 64]); // signature, checked by the runtime
+        data.extend_from_slice(message);
+        solana_program::instruction::Instruction {
+            program_id: solana_program::ed25519_program::ID,
+            accounts: vec![],
+            data,
+        }
+    }
+
+    #[test]
+    fn test_signed_price_attestation() -> Result<()> {
+        let authority = Pubkey::new_unique();
+        let attestation = PriceAttestation {
+            program_id: IRMA_ID,
+            quote_token: "USDT".to_string(),
+            usd_rate: PRICE_ONE + PRICE_ONE / 1000,
+            nonce: 1,
+            timestamp: 1000,
+        };
+        let message = attestation.message()?;
+        let ix = ed25519_instruction(&authority, &message);
+        attestation::verify_ed25519_instruction(&ix, &authority, &message)?;
+
+        // the verified key and message must be exactly the authority and the attestation
+        assert!(attestation::verify_ed25519_instruction(&ix, &Pubkey::new_unique(), &message).is_err());
+        let other = PriceAttestation { usd_rate: 2 * PRICE_ONE, ..attestation.clone() }.message()?;
+        assert!(attestation::verify_ed25519_instruction(&ix, &authority, &other).is_err());
+        // key taken from another instruction is not accepted
+        let mut elsewhere = ix.clone();
+        elsewhere.data[8..10].copy_from_slice(&0u16.to_le_bytes());
+        assert!(attestation::verify_ed25519_instruction(&elsewhere, &authority, &message).is_err());
+        let not_ed25519 = solana_program::instruction::Instruction { program_id: IRMA_ID, ..ix.clone() };
+        assert!(attestation::verify_ed25519_instruction(&not_ed25519, &authority, &message).is_err());
+
+        // registry: nonces strictly increase per authority
+        let mut registry = PriceAuthorities {
+            authorities: vec![], feeds: vec![], emergency_authority: Pubkey::default(), bump: 0
+        };
+        assert!(registry.use_nonce(&authority, 1).is_err());
+        registry.add(authority)?;
+        assert!(registry.add(authority).is_err());
+        registry.use_nonce(&authority, 1)?;
+        assert!(registry.use_nonce(&authority, 1).is_err());
+        registry.use_nonce(&authority, 5)?;
+        registry.remove(authority)?;
+        assert!(registry.use_nonce(&authority, 6).is_err());
+
+        // feeds are kept apart from the signing authorities and have their own slots
+        let feed = Pubkey::new_unique();
+        registry.add(authority)?;
+        assert!(!registry.has_feed(&authority));
+        registry.add_feed(feed)?;
+        assert!(!registry.contains(&feed));
+        assert_eq!(registry.add_feed(feed).err(), Some(error!(CustomError::PriceFeedExists)));
+        for _ in 1..attestation::MAX_PRICE_FEEDS {
+            registry.add_feed(Pubkey::new_unique())?;
+        }
+        assert_eq!(registry.add_feed(Pubkey::new_unique()).err(), Some(error!(CustomError::TooManyPriceFeeds)));
+        registry.add(Pubkey::new_unique())?;
+        registry.remove_feed(feed)?;
+        assert!(!registry.has_feed(&feed));
+        assert_eq!(registry.remove_feed(feed).err(), Some(error!(CustomError::UnknownPriceFeed)));
+
+        // timestamps: not stale, not too far ahead
+        attestation::check_timestamp(1000, 1000 + oracle::MAX_PRICE_AGE)?;
+        assert!(attestation::check_timestamp(1000, 1001 + oracle::MAX_PRICE_AGE).is_err());
+        assert!(attestation::check_timestamp(1000 + attestation::MAX_CLOCK_DRIFT + 1, 1000).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_price_aggregation() -> Result<()> {
+        let mut state = init_state();
+        let initial_rate = PRICE_ONE + PRICE_ONE / 100;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", initial_rate, 1)?;
+        let usdt_mint = state.get_stablecoin("USDT")?.mint_address;
+        let mut aggregator = PriceAggregator::new(usdt_mint, 0);
+        let mut history = PriceHistory::new(usdt_mint, 0);
+        let (a, b, c, d) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let now: i64 = 1000;
+        let mut submit = |aggregator: &mut PriceAggregator, state: &mut StateMap, source: Pubkey, usd_rate: u128| {
+            let submission = PriceSubmission { source, usd_rate, timestamp: now };
+            aggregation::submit_rate(aggregator, state, &mut history, &config(), PRICE_ONE, "USDT", submission, now)
+        };
+
+        // below quorum nothing is committed, and a source cannot count twice
+        submit(&mut aggregator, &mut state, a, PRICE_ONE)?;
+        submit(&mut aggregator, &mut state, a, PRICE_ONE)?;
+        submit(&mut aggregator, &mut state, b, PRICE_ONE + PRICE_ONE / 1000)?;
+        assert_eq!(aggregator.submissions.len(), 2);
+        assert_eq!(state.get_stablecoin("USDT")?.usd_rate, initial_rate);
+
+        // an outlier 10% off the median is rejected, leaving no quorum
+        submit(&mut aggregator, &mut state, c, 11 * PRICE_ONE / 10)?;
+        assert_eq!(aggregator.submissions.len(), 3);
+        assert_eq!(state.get_stablecoin("USDT")?.usd_rate, initial_rate);
+
+        // a third agreeing source commits the median of the agreeing rates
+        submit(&mut aggregator, &mut state, d, PRICE_ONE - PRICE_ONE / 1000)?;
+        assert_eq!(state.get_stablecoin("USDT")?.usd_rate, PRICE_ONE);
+        assert_eq!(state.get_stablecoin("USDT")?.mint_price, PRICE_ONE);
+        assert!(aggregator.submissions.is_empty());
+        // only the committed rate is recorded
+        assert_eq!(history.observations.len(), 1);
+        assert_eq!(history.latest().unwrap().mint_price, PRICE_ONE);
+
+        // stale submissions are refused, expired ones are dropped
+        let stale = PriceSubmission { source: a, usd_rate: PRICE_ONE, timestamp: now - aggregator.window - 1 };
+        assert!(aggregator.submit(stale, now).is_err());
+        aggregator.submit(PriceSubmission { source: a, usd_rate: PRICE_ONE, timestamp: now }, now)?;
+        aggregator.submit(PriceSubmission { source: b, usd_rate: PRICE_ONE, timestamp: now + 10 }, now + aggregator.window + 1)?;
+        assert_eq!(aggregator.submissions.len(), 1);
+
+        // the aggregator only accepts rates for its own reserve
+        let mut other = PriceAggregator::new(Pubkey::new_unique(), 0);
+        let mut other_history = PriceHistory::new(other.mint, 0);
+        assert!(aggregation::submit_rate(&mut other, &mut state, &mut other_history, &config(), PRICE_ONE, "USDT",
+            PriceSubmission { source: a, usd_rate: PRICE_ONE, timestamp: now }, now).is_err());
+
+        assert_eq!(aggregation::median(&[4, 1, 3, 2])?, 3);
+        assert!(aggregator.configure(0, 300, 50).is_err());
+        aggregator.configure(1, 60, 10)?;
+        assert!(aggregator.submissions.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_price_bounds() -> Result<()> {
+        let mut state = init_state();
+        let start: i64 = 1_750_000_000;
+        // the first rate after listing is not bounded
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", 2 * PRICE_ONE, start)?;
+        state.set_price_bounds("USDT", 100, 300)?;
+        assert!(state.set_price_bounds("USDT", 300, 100).is_err());
+
+        // 1% per update
+        let step = 2 * PRICE_ONE / 100;
+        assert!(state.set_usd_rate(&config(), PRICE_ONE, "USDT", 2 * PRICE_ONE + step + 1, start + 1).is_err());
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", 2 * PRICE_ONE + step, start + 1)?;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", 2 * PRICE_ONE + 2 * step, start + 2)?;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", 2 * PRICE_ONE + 3 * step, start + 3)?;
+
+        // 3% per day, measured from the rate at the start of the window
+        let rate = 2 * PRICE_ONE + 3 * step;
+        assert!(state.set_usd_rate(&config(), PRICE_ONE, "USDT", rate + step / 2, start + 4).is_err());
+        assert_eq!(state.get_stablecoin("USDT")?.usd_rate, rate);
+        assert_eq!(state.get_stablecoin("USDT")?.window_start, start);
+        let next_day = start + pricing::PRICE_BOUND_WINDOW;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", rate + step / 2, next_day)?;
+        assert_eq!(state.get_stablecoin("USDT")?.window_start, next_day);
+        assert_eq!(state.get_stablecoin("USDT")?.window_start_rate, rate);
+
+        // an emergency override ignores the bounds and starts a new window
+        state.override_usd_rate(&config(), PRICE_ONE, "USDT", PRICE_ONE, next_day + 1)?;
+        assert_eq!(state.get_stablecoin("USDT")?.mint_price, PRICE_ONE);
+        assert_eq!(state.get_stablecoin("USDT")?.window_start_rate, PRICE_ONE);
+        assert!(state.set_usd_rate(&config(), PRICE_ONE, "USDT", PRICE_ONE + PRICE_ONE / 50, next_day + 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_mint_price() -> Result<()> {
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        let updated_at: i64 = 1_750_000_000;
+        // a reserve that was never priced cannot be minted against
+        assert!(mint_irma(&mut state, &config(), "USDT", 100_000_000, updated_at).is_err());
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", PRICE_ONE, updated_at)?;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDC", PRICE_ONE, updated_at)?;
+        assert_eq!(state.get_stablecoin("USDT")?.price_updated_at, updated_at);
+
+        let max_age = pricing::DEFAULT_MAX_PRICE_AGE as i64;
+        mint_irma(&mut state, &config(), "USDT", 100_000_000, updated_at + max_age)?;
+        let stale = updated_at + max_age + 1;
+        assert!(mint_irma(&mut state, &config(), "USDT", 100_000_000, stale).is_err());
+        assert!(swap_reserves(&mut state, &config(), "USDT", "USDC", 100_000_000, stale).is_err());
+        // redemptions are still allowed
+        redeem_irma(&mut state, &config(), "USDT", 10_000_000, 0)?;
+
+        // a fresh rate resumes minting
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", PRICE_ONE, stale)?;
+        mint_irma(&mut state, &config(), "USDT", 100_000_000, stale)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_price_history() -> Result<()> {
+        let mut state = init_state();
+        let start: i64 = 1_750_000_000;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", PRICE_ONE, start)?;
+        let usdt = state.get_stablecoin("USDT")?;
+        let mut history = PriceHistory::new(usdt.mint_address, 0);
+        assert!(history.price_twap(100, start).is_err());
+        history.record(&usdt, start)?;
+        // observations are recorded with the reserve's state at the time
+        mint_irma(&mut state, &config(), "USDT", 100_000_000, start)?;
+        history.record(&state.get_stablecoin("USDT")?, start)?;
+        assert_eq!(history.observations.len(), 1);
+        assert_eq!(history.latest().unwrap().backing_reserves, usdt.backing_reserves + 100_000_000);
+        assert!(history.record(&state.get_stablecoin("USDT")?, start - 1).is_err());
+
+        // 1.0 for 75s, then 1.02 for 25s: the TWAP weights each price by how long it held
+        let raised = PRICE_ONE + PRICE_ONE / 50;
+        state.set_usd_rate(&config(), PRICE_ONE, "USDT", raised, start + 75)?;
+        history.record(&state.get_stablecoin("USDT")?, start + 75)?;
+        let (mint_twap, redemption_twap) = history.price_twap(100, start + 100)?;
+        assert_eq!(mint_twap, PRICE_ONE + PRICE_ONE / 200);
+        assert_eq!(redemption_twap, PRICE_ONE);
+        assert_eq!(history.price_twap(20, start + 100)?.0, raised);
+        // the history must cover the whole window
+        assert!(history.price_twap(101, start + 100).is_err());
+        assert!(history.price_twap(0, start + 100).is_err());
+
+        // once full, the oldest observations are overwritten
+        for i in 1..=PRICE_HISTORY_LEN as i64 {
+            history.record(&state.get_stablecoin("USDT")?, start + 75 + i)?;
+        }
+        assert_eq!(history.observations.len(), PRICE_HISTORY_LEN);
+        assert_eq!(history.chronological().next().unwrap().timestamp, start + 76);
+        assert_eq!(history.latest().unwrap().timestamp, start + 75 + PRICE_HISTORY_LEN as i64);
+        assert!(history.price_twap(100, start + 100).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_config() -> Result<()> {
+        let mut config = config();
+        // setters refuse out-of-range values and leave the old ones in place
+        assert!(config.set_mint_redeem_limits(0, 100).is_err());
+        assert!(config.set_max_mint_price(PRICE_ONE).is_err());
+        assert!(config.set_max_mint_price(pricing::MAX_MINT_PRICE + 1).is_err());
+        assert!(config.set_min_price_diff(MAX_MIN_PRICE_DIFF + 1).is_err());
+        assert!(config.set_slippage(MAX_SLIPPAGE_BPS + 1).is_err());
+        assert!(config.set_position_amounts(1, 0).is_err());
+        assert_eq!(config, ProtocolConfig::default());
+
+        // the mint and redemption limits apply as configured
+        let mut state = init_state();
+        state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE, 1)?;
+        config.set_mint_redeem_limits(10, 1)?;
+        mint_irma(&mut state, &config, "USDT", 10_000_000, 1)?;
+        assert!(mint_irma(&mut state, &config, "USDT", 9_999_999, 1).is_err());
+        assert!(redeem_irma(&mut state, &config, "USDT", 1_000_001, 0).is_err());
+        redeem_irma(&mut state, &config, "USDT", 1_000_000, 0)?;
+
+        // minting stops once the configured maximum is lowered to the mint price,
+        // and no USD rate update may take a mint price to it
+        state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE + PRICE_ONE / 100, 2)?;
+        config.set_max_mint_price(PRICE_ONE + PRICE_ONE / 100)?;
+        assert_eq!(mint_irma(&mut state, &config, "USDT", 10_000_000, 2).err(), Some(error!(CustomError::MintPriceTooHigh)));
+        assert_eq!(
+            state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE + PRICE_ONE / 100, 3).err(),
+            Some(error!(CustomError::MintPriceTooHigh))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve_mint_redeem_limits() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "DAI", pubkey!("EjmyN6qEC1Tf1JxiG1ae7UTJhUxSwk1TCWNWqxWV4J6o"), 18).unwrap());
+        state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE, 1)?;
+        state.set_usd_rate(&config, PRICE_ONE, "DAI", PRICE_ONE, 1)?;
+
+        // the protocol default of 100 tokens scales with each reserve's decimals
+        let usdt = state.get_stablecoin("USDT")?;
+        assert_eq!(usdt.min_mint_base_units(&config)?, 100_000_000);
+        assert_eq!(state.get_stablecoin("DAI")?.min_mint_base_units(&config)?, 100 * fixed_point::pow10(18)?);
+        assert!(mint_irma(&mut state, &config, "USDT", 99_999_999, 1).is_err());
+        mint_irma(&mut state, &config, "USDT", 100_000_000, 1)?;
+
+        // a reserve's own limits override the defaults
+        {
+            let dai = state.get_mut_stablecoin("DAI")?;
+            dai.min_mint_amount = 5;
+            dai.max_redeem_amount = 2;
+        }
+        let dai = state.get_stablecoin("DAI")?;
+        assert_eq!(dai.min_mint_base_units(&config)?, 5 * fixed_point::pow10(18)?);
+        assert_eq!(dai.max_redeem_irma(&config)?, 2_000_000);
+        assert_eq!(dai.max_redeem_payout(&config)?, 2 * fixed_point::pow10(18)?);
+        assert!(mint_irma(&mut state, &config, "DAI", 4_999_999_999_999_999_999, 1).is_err());
+        mint_irma(&mut state, &config, "DAI", 5_000_000_000_000_000_000, 1)?;
+        assert!(redeem_irma(&mut state, &config, "DAI", 2_000_001, 0).is_err());
+        redeem_irma(&mut state, &config, "DAI", 2_000_000, 0)?;
+
+        // other reserves keep the default redemption limit
+        assert_eq!(usdt.max_redeem_irma(&config)?, 100_000 * 1_000_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_redemption_window_caps() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        for symbol in ["USDT", "USDC"] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, 1_000_000_000, 1)?;
+        }
+        // caps are off by default
+        redeem_irma(&mut state, &config, "USDT", 5_000_000, 100)?;
+        assert_eq!(state.redeemed_in_window, 5_000_000);
+
+        // 7 IRMA per window from USDT, 5 of which are already redeemed, and 8 IRMA in total
+        state.get_mut_stablecoin("USDT")?.redeem_window_cap = 7;
+        state.redeem_window_cap = 8;
+        redeem_irma(&mut state, &config, "USDT", 2_000_000, 200)?;
+        assert!(redeem_irma(&mut state, &config, "USDT", 1, 200).is_err());
+        redeem_irma(&mut state, &config, "USDC", 1_000_000, 200)?;
+        assert!(redeem_irma(&mut state, &config, "USDC", 1, 200).is_err());
+        // failed redemptions are not counted
+        assert_eq!(state.get_stablecoin("USDT")?.redeemed_in_window, 7_000_000);
+        assert_eq!(state.redeemed_in_window, 8_000_000);
+
+        // every counter restarts with the next window
+        let next = state.redeem_window_start + pricing::DEFAULT_REDEEM_WINDOW as i64;
+        assert!(redeem_irma(&mut state, &config, "USDT", 1, next - 1).is_err());
+        redeem_irma(&mut state, &config, "USDT", 7_000_000, next)?;
+        assert_eq!(state.redeem_window_start, next);
+        assert_eq!(state.get_stablecoin("USDC")?.redeemed_in_window, 0);
+        assert_eq!(state.redeemed_in_window, 7_000_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_redemption_queue() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        let usdt_mint = state.get_stablecoin("USDT")?.mint_address;
+        state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE, 1)?;
+        mint_irma(&mut state, &config, "USDT", 1_000_000_000, 1)?;
+
+        let (alice, bob, carol) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut queue = RedemptionQueue::new(usdt_mint, 255);
+        assert_eq!(queue.enqueue(alice, 5_000_000, PRICE_ONE / 2, 10)?, 0);
+        assert_eq!(queue.enqueue(bob, 3_000_000, 2 * PRICE_ONE, 11)?, 1); // price never met
+        assert_eq!(queue.enqueue(carol, 4_000_000, 0, 12)?, 2);
+        assert!(queue.enqueue(carol, 0, 0, 12).is_err());
+
+        // a vault holding 7 USDT covers alice but not carol; bob's price is not met and is passed over
+        let before = state.get_stablecoin("USDT")?;
+        assert_eq!(queue::fill(&mut queue, &mut state, &config, "USDT", 7_000_000, 20)?, 5_000_000);
+        assert_eq!(queue.requests[0].payout, 4_999_500); // 5 USDT less the 1 bps fee
+        assert!(!queue.requests[1].is_filled() && !queue.requests[2].is_filled());
+        assert_eq!(queue.unclaimed()?, 4_999_500);
+        let after = state.get_stablecoin("USDT")?;
+        assert_eq!(after.irma_in_circulation, before.irma_in_circulation - 5_000_000);
+        assert_eq!(after.backing_reserves, before.backing_reserves - 4_999_500);
+
+        // only the owner can cancel, and only before the request is filled
+        assert!(queue.cancel(&carol, 1).is_err());
+        assert_eq!(queue.cancel(&bob, 1)?, 3_000_000);
+        assert!(queue.cancel(&alice, 0).is_err());
+        assert!(queue.claim(&carol, 2).is_err());
+
+        // the next mint fills carol; the payouts set aside are not counted as available
+        mint_irma(&mut state, &config, "USDT", 100_000_000, 30)?;
+        let unfilled = state.clone();
+        assert_eq!(queue::fill(&mut queue, &mut state, &config, "USDT", 8_000_000, 30)?, 0);
+        assert_eq!(state, unfilled);
+        assert_eq!(queue::fill(&mut queue, &mut state, &config, "USDT", 9_000_000, 30)?, 4_000_000);
+        assert_eq!(queue.claim(&alice, 0)?.payout, 4_999_500);
+        assert_eq!(queue.claim(&carol, 2)?.payout, 3_999_600);
+        assert!(queue.requests.is_empty());
+
+        // a request the redemption cap stops is not filled, and nothing of it is counted
+        queue.enqueue(alice, 2_000_000, 0, 40)?;
+        state.get_mut_stablecoin("USDT")?.redeem_window_cap = 1;
+        let unfilled = state.clone();
+        assert_eq!(queue::fill(&mut queue, &mut state, &config, "USDT", 100_000_000, 40)?, 0);
+        assert_eq!(state, unfilled);
+        assert!(!queue.requests[0].is_filled());
+        Ok(())
+    }
+
+    #[test]
+    fn test_redeem_basket() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        state.add_reserve(StableState::new(
+            "DAI", pubkey!("EjmyN6qEC1Tf1JxiG1ae7UTJhUxSwk1TCWNWqxWV4J6o"), 18).unwrap());
+        state.add_reserve(StableState::new(
+            "PYUSD", pubkey!("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo"), 6).unwrap());
+        state.get_mut_stablecoin("DAI")?.min_mint_amount = 1;
+        state.get_mut_stablecoin("PYUSD")?.status = ReserveStatus::Pending;
+        state.redemption_fee_bps = 0;
+        for (symbol, amount) in [("USDT", 300_000_000u64), ("USDC", 100_000_000), ("DAI", 10_000_000_000_000_000_000)] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, amount, 1)?;
+        }
+        let before = state.clone();
+
+        // backing worth 301, 101 and 11 USD: 41.3 IRMA splits into 30.1, 10.1 and 1.1
+        let payouts = pricing::redeem_basket(&mut state, &config, 41_300_000, 2)?;
+        assert_eq!(payouts, vec![
+            ("DAI".to_string(), 1_100_000_000_000_000_000, 0),
+            ("USDC".to_string(), 10_100_000, 0),
+            ("USDT".to_string(), 30_100_000, 0),
+        ]);
+        for reserve in before.reserves.iter() {
+            let after = state.get_stablecoin(&reserve.symbol)?;
+            assert_eq!(after.redemption_price()?, reserve.redemption_price()?);
+        }
+        assert_eq!(state.get_stablecoin("USDT")?.irma_in_circulation, 301_000_000 - 30_100_000);
+        assert_eq!(state.get_stablecoin("PYUSD")?, before.get_stablecoin("PYUSD")?);
+
+        // the rounding remainder goes to the most valuable reserve
+        let payouts = pricing::redeem_basket(&mut state, &config, 1, 3)?;
+        assert_eq!(payouts.iter().map(|(_, out, _)| *out).collect::<Vec<u64>>(), vec![0, 0, 1]);
+        assert!(pricing::redeem_basket(&mut state, &config, 0, 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mint_basket() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        state.add_reserve(StableState::new(
+            "PYUSD", pubkey!("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo"), 6).unwrap());
+        let now: i64 = 100_000;
+        state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE, now)?;
+        state.set_usd_rate(&config, PRICE_ONE, "USDC", PRICE_ONE, now)?;
+        let deposit = |symbol: &str, amount: u64| BasketDeposit { symbol: symbol.to_string(), amount };
+
+        let minted = pricing::mint_basket(
+            &mut state, &config, &[deposit("USDC", 200_000_000), deposit("USDT", 100_000_000)], now)?;
+        assert_eq!(minted, vec![200_000_000, 100_000_000]);
+        assert_eq!(state.get_stablecoin("USDC")?.backing_reserves, 201_000_000);
+
+        // a stale, paused, repeated or undersized deposit fails the basket and changes nothing
+        let before = state.clone();
+        assert!(pricing::mint_basket(
+            &mut state, &config, &[deposit("USDT", 100_000_000), deposit("PYUSD", 100_000_000)], now).is_err());
+        state.set_usd_rate(&config, PRICE_ONE, "PYUSD", PRICE_ONE, now)?;
+        state.get_mut_stablecoin("PYUSD")?.status = ReserveStatus::MintPaused;
+        let before_paused = state.clone();
+        assert!(pricing::mint_basket(
+            &mut state, &config, &[deposit("USDT", 100_000_000), deposit("PYUSD", 100_000_000)], now).is_err());
+        assert_eq!(state, before_paused);
+        assert!(pricing::mint_basket(
+            &mut state, &config, &[deposit("USDT", 100_000_000), deposit("USDT", 100_000_000)], now).is_err());
+        assert!(pricing::mint_basket(
+            &mut state, &config, &[deposit("USDT", 100_000_000), deposit("USDC", 1)], now).is_err());
+        assert!(pricing::mint_basket(&mut state, &config, &[], now).is_err());
+        assert_eq!(state.get_stablecoin("USDT")?, before.get_stablecoin("USDT")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocation_strategies() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        state.add_reserve(StableState::new(
+            "PYUSD", pubkey!("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo"), 6).unwrap());
+        for symbol in ["USDT", "USDC", "PYUSD"] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, 100_000_000, 1)?;
+        }
+        // spreads of 0 (USDT), 0.02 (USDC) and 0.01 (PYUSD)
+        state.get_mut_stablecoin("USDC")?.mint_price = PRICE_ONE * 102 / 100;
+        state.get_mut_stablecoin("PYUSD")?.mint_price = PRICE_ONE * 101 / 100;
+        assert_eq!(state.allocation_strategy, AllocationStrategy::MaxSpread);
+
+        // circulation reductions of USDT, USDC and PYUSD; the payout always comes out of USDT
+        let distribute = |strategy: AllocationStrategy, irma_amount: u64| -> Result<Vec<u128>> {
+            let mut after = state.clone();
+            after.allocation_strategy = strategy;
+            after.distribute(&config, "USDT", irma_amount)?;
+            assert_eq!(after.get_stablecoin("USDT")?.backing_reserves, 101_000_000 - irma_amount as u128);
+            let reductions: Vec<u128> = ["USDT", "USDC", "PYUSD"].iter()
+                .map(|symbol| Ok(state.get_stablecoin(symbol)?.irma_in_circulation
+                    - after.get_stablecoin(symbol)?.irma_in_circulation))
+                .collect::<Result<_>>()?;
+            assert_eq!(reductions.iter().sum::<u128>(), irma_amount as u128);
+            assert_eq!(after.get_stablecoin("USDC")?.backing_reserves, 101_000_000);
+            assert_eq!(after.get_stablecoin("PYUSD")?.backing_reserves, 101_000_000);
+            Ok(reductions)
+        };
+
+        assert_eq!(distribute(AllocationStrategy::SelfOnly, 10_000_000)?, vec![10_000_000, 0, 0]);
+        // the widest spread takes a large redemption, and a small one is split linearly with the subject
+        assert_eq!(distribute(AllocationStrategy::MaxSpread, 10_000_000)?, vec![0, 10_000_000, 0]);
+        let split = distribute(AllocationStrategy::MaxSpread, 1_000_000)?;
+        assert!(split[0] > 0 && split[1] > 0 && split[2] == 0);
+        // USDC takes about twice as much as PYUSD, USDT the rounding remainder
+        let shares = distribute(AllocationStrategy::ProportionalToSpread, 10_000_000)?;
+        assert!(shares[0] <= 2 && shares[1].abs_diff(2 * shares[2]) <= 2);
+
+        // allocations that break the invariants are rejected; reserves are PYUSD, USDC and USDT
+        state.get_mut_stablecoin("PYUSD")?.status = ReserveStatus::Retired;
+        assert_eq!(state.list_reserves(), vec!["PYUSD", "USDC", "USDT"]);
+        assert!(allocation::check_allocation(&state.reserves, 2, 1_000, &[0, 1_000, 0]).is_ok());
+        assert!(allocation::check_allocation(&state.reserves, 2, 1_000, &[0, 999, 0]).is_err());
+        assert!(allocation::check_allocation(&state.reserves, 2, 1_000, &[500, 0, 500]).is_err());
+        assert!(allocation::check_allocation(&state.reserves, 2, 1_000, &[0, 1_000]).is_err());
+        assert!(allocation::check_allocation(&state.reserves, 2, 200_000_000, &[0, 200_000_000, 0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_spread_linear_split() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        for symbol in ["USDT", "USDC"] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, 100_000_000, 1)?;
+        }
+        state.get_mut_stablecoin("USDC")?.mint_price = PRICE_ONE * 102 / 100;
+        let subject = state.reserves.iter().position(|r| r.symbol == "USDT").unwrap();
+        let other = 1 - subject;
+
+        // small redemptions are split between USDT and USDC, and the two reductions add up to the redemption
+        for irma_amount in [100_000u64, 500_000, 1_000_000] {
+            let reductions = AllocationStrategy::MaxSpread.allocator()
+                .allocate(&state.reserves, subject, irma_amount, &config, &[])?;
+            assert!(reductions[subject] > 0 && reductions[other] > 0);
+            assert_eq!(reductions[subject] + reductions[other], irma_amount as u128);
+            allocation::check_allocation(&state.reserves, subject, irma_amount, &reductions)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_water_fill_allocation() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        for (symbol, decimals) in [("USDC", 6), ("PYUSD", 6), ("USDS", 6), ("FDUSD", 6), ("DAI", 18)] {
+            state.add_reserve(StableState::new(symbol, Pubkey::new_unique(), decimals).unwrap());
+        }
+        state.get_mut_stablecoin("DAI")?.min_mint_amount = 1;
+        for symbol in ["USDT", "USDC", "PYUSD", "USDS", "FDUSD"] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, 100_000_000, 1)?;
+        }
+        state.set_usd_rate(&config, PRICE_ONE, "DAI", PRICE_ONE, 1)?;
+        mint_irma(&mut state, &config, "DAI", 10_000_000_000_000_000_000, 1)?;
+        // sudden inflation: mint prices jump by 0% to 6%, redemption prices are all 1.0
+        for (symbol, percent) in [("USDT", 100), ("USDC", 106), ("PYUSD", 105), ("USDS", 102), ("FDUSD", 101), ("DAI", 104)] {
+            state.get_mut_stablecoin(symbol)?.mint_price = PRICE_ONE * percent / 100;
+        }
+        state.allocation_strategy = AllocationStrategy::WaterFill;
+        let spread = |state: &StateMap, symbol: &str| -> Result<i128> {
+            let reserve = state.get_stablecoin(symbol)?;
+            fixed_point::signed_diff(reserve.mint_price, reserve.redemption_price()?)
+        };
+        let tolerance: i128 = (PRICE_ONE / 1_000_000) as i128;
+        let circulation = |state: &StateMap| state.reserves.iter().map(|r| r.irma_in_circulation).sum::<u128>();
+
+        // 3 IRMA from USDT: USDC, PYUSD and DAI end at one spread in one step, and USDT takes the rounding
+        let mut after = state.clone();
+        after.distribute(&config, "USDT", 3_000_000)?;
+        assert_eq!(circulation(&state) - circulation(&after), 3_000_000);
+        let level = spread(&after, "USDC")?;
+        assert!(level < spread(&state, "PYUSD")? && level > spread(&state, "USDS")?);
+        for symbol in ["PYUSD", "DAI"] {
+            assert!((spread(&after, symbol)? - level).abs() < tolerance, "{} off the level", symbol);
+        }
+        assert!(101_000_000 - after.get_stablecoin("USDT")?.irma_in_circulation <= 3);
+        for symbol in ["USDS", "FDUSD"] {
+            assert_eq!(after.get_stablecoin(symbol)?, state.get_stablecoin(symbol)?);
+        }
+
+        // 5 IRMA fills every reserve above the average, USDT included, down to it; the rest comes off USDT
+        let mut after = state.clone();
+        after.distribute(&config, "USDT", 5_000_000)?;
+        assert_eq!(circulation(&state) - circulation(&after), 5_000_000);
+        let level = spread(&after, "USDC")?;
+        for symbol in ["PYUSD", "DAI"] {
+            assert!((spread(&after, symbol)? - level).abs() < tolerance, "{} off the level", symbol);
+        }
+        assert!(spread(&after, "USDT")? < level);
+
+        // negligible spreads leave the whole reduction on the subject
+        for reserve in state.reserves.iter_mut() {
+            reserve.mint_price = PRICE_ONE;
+        }
+        let mut after = state.clone();
+        after.distribute(&config, "USDT", 5_000_000)?;
+        assert_eq!(after.get_stablecoin("USDT")?.irma_in_circulation, 96_000_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_market_price_allocation() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        state.add_reserve(StableState::new(
+            "DAI", pubkey!("EjmyN6qEC1Tf1JxiG1ae7UTJhUxSwk1TCWNWqxWV4J6o"), 18).unwrap());
+        state.get_mut_stablecoin("DAI")?.min_mint_amount = 1;
+        for (symbol, amount) in [("USDT", 100_000_000u64), ("USDC", 100_000_000), ("DAI", 10_000_000_000_000_000_000)] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, amount, 1)?;
+        }
+
+        // DLMM prices are Y per X in base units: 1.0 at bin 0, 1.01^2 two bins up at a 100 bps step
+        let usdc = state.get_stablecoin("USDC")?.clone();
+        let irma_mint = Pubkey::new_unique();
+        let mut lb_pair: LbPair = bytemuck::Zeroable::zeroed();
+        lb_pair.bin_step = 100;
+        lb_pair.parameters.max_bin_id = 2;
+        lb_pair.token_x_mint = irma_mint;
+        lb_pair.token_y_mint = usdc.mint_address;
+        assert_eq!(allocation::pair_price(&usdc, &lb_pair)?, PRICE_ONE);
+        lb_pair.active_id = 2;
+        let price = allocation::pair_price(&usdc, &lb_pair)?;
+        assert!(price.abs_diff(PRICE_ONE * 10201 / 10000) < PRICE_ONE / 1_000_000_000);
+        // with the reserve as token X the price is inverted
+        lb_pair.token_x_mint = usdc.mint_address;
+        lb_pair.token_y_mint = irma_mint;
+        let inverse = allocation::pair_price(&usdc, &lb_pair)?;
+        assert!(fixed_point::mul_price(price, inverse, Rounding::Down)?.abs_diff(PRICE_ONE) < PRICE_ONE / 1_000_000_000);
+        // DAI base units are 10^12 times smaller than IRMA base units
+        let dai = state.get_stablecoin("DAI")?.clone();
+        lb_pair.active_id = 0;
+        lb_pair.token_x_mint = irma_mint;
+        lb_pair.token_y_mint = dai.mint_address;
+        assert_eq!(allocation::pair_price(&dai, &lb_pair)?, PRICE_ONE / 1_000_000_000_000);
+        lb_pair.token_y_mint = Pubkey::new_unique();
+        assert!(allocation::pair_price(&dai, &lb_pair).is_err());
+        // an active bin outside the pair's range, or one whose price overflows, is an error, not a panic
+        lb_pair.token_y_mint = dai.mint_address;
+        lb_pair.active_id = 3;
+        assert_eq!(allocation::pair_price(&dai, &lb_pair).err(), Some(error!(CustomError::InvalidLbPairState)));
+        lb_pair.parameters.max_bin_id = MAX_BIN_ID;
+        lb_pair.active_id = MAX_BIN_ID + 1;
+        assert_eq!(allocation::pair_price(&dai, &lb_pair).err(), Some(error!(CustomError::InvalidLbPairState)));
+        lb_pair.active_id = MAX_BIN_ID;
+        assert_eq!(allocation::pair_price(&dai, &lb_pair).err(), Some(error!(CustomError::MathError)));
+
+        // market prices need the LbPair of every redeemable reserve with a pool, or none
+        let (usdc_pool, dai_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        state.get_mut_stablecoin("USDC")?.pool_id = usdc_pool;
+        state.get_mut_stablecoin("DAI")?.pool_id = dai_pool;
+        let mut usdc_pair: LbPair = bytemuck::Zeroable::zeroed();
+        usdc_pair.bin_step = 100;
+        usdc_pair.parameters.max_bin_id = 10;
+        usdc_pair.token_y_mint = usdc.mint_address;
+        let mut dai_pair = usdc_pair;
+        dai_pair.token_y_mint = dai.mint_address;
+        assert_eq!(allocation::market_prices(&state.reserves, &[None, None, None])?, vec![None, None, None]);
+        assert_eq!(allocation::market_prices(&state.reserves, &[Some(&dai_pair), Some(&usdc_pair), None])?,
+            vec![Some(PRICE_ONE / 1_000_000_000_000), Some(PRICE_ONE), None]);
+        assert_eq!(allocation::market_prices(&state.reserves, &[None, Some(&usdc_pair), None]).err(),
+            Some(error!(CustomError::IncompleteLbPairs)));
+        // a reserve closed for redemption is not needed
+        state.get_mut_stablecoin("DAI")?.status = ReserveStatus::Retired;
+        assert!(allocation::market_prices(&state.reserves, &[None, Some(&usdc_pair), None]).is_ok());
+        state.get_mut_stablecoin("DAI")?.status = ReserveStatus::Active;
+
+        // USDC has the widest spread to its mint price, but its IRMA trades at the redemption price,
+        // so a market-aware redemption reduces DAI instead, whose IRMA trades at its mint price
+        state.get_mut_stablecoin("USDC")?.mint_price = PRICE_ONE * 102 / 100;
+        state.get_mut_stablecoin("DAI")?.mint_price = PRICE_ONE * 101 / 100;
+        assert_eq!(state.list_reserves(), vec!["DAI", "USDC", "USDT"]);
+        let market_prices = vec![Some(PRICE_ONE * 101 / 100), Some(PRICE_ONE), None];
+        let mut blind = state.clone();
+        redeem_irma(&mut blind, &config, "USDT", 10_000_000, 2)?;
+        assert_eq!(blind.get_stablecoin("USDC")?.irma_in_circulation, 91_000_000);
+        let mut aware = state.clone();
+        pricing::redeem_irma_at_market(&mut aware, &config, "USDT", 10_000_000, 2, &market_prices)?;
+        assert_eq!(aware.get_stablecoin("DAI")?.irma_in_circulation, 1_000_000);
+        assert_eq!(aware.get_stablecoin("USDC")?, state.get_stablecoin("USDC")?);
+        assert_eq!(aware.get_stablecoin("USDT")?.backing_reserves, blind.get_stablecoin("USDT")?.backing_reserves);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crossed_redemption_bins() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.add_reserve(StableState::new(
+            "USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6).unwrap());
+        for symbol in ["USDT", "USDC"] {
+            state.set_usd_rate(&config, PRICE_ONE, symbol, PRICE_ONE, 1)?;
+            mint_irma(&mut state, &config, symbol, 100_000_000, 1)?;
+        }
+        state.get_mut_stablecoin("USDC")?.mint_price = PRICE_ONE * 102 / 100;
+
+        // bins of a pair with a 1% step: bin 0 is 1.0, and a price belongs to the highest bin not above it
+        let mut lb_pair: LbPair = bytemuck::Zeroable::zeroed();
+        lb_pair.bin_step = 100;
+        lb_pair.parameters.min_bin_id = -100;
+        lb_pair.parameters.max_bin_id = 100;
+        assert_eq!(allocation::price_bin(&lb_pair, PRICE_ONE)?, 0);
+        assert_eq!(allocation::price_bin(&lb_pair, PRICE_ONE - 1)?, -1);
+        assert_eq!(allocation::price_bin(&lb_pair, allocation::bin_price(2, 100)?)?, 2);
+        assert_eq!(allocation::price_bin(&lb_pair, PRICE_ONE * 3)?, 100);
+        assert_eq!(allocation::price_bin(&lb_pair, 0)?, -100);
+        let pools: Vec<Option<&LbPair>> = vec![Some(&lb_pair); state.reserves.len()];
+        let before = pricing::redemption_prices(&state.reserves)?;
+
+        // taking the redeemed IRMA off USDC's circulation raises its redemption price by several bins
+        let mut after = state.clone();
+        after.distribute(&config, "USDT", 10_000_000)?;
+        let crossed = pricing::crossed_redemption_bins(&before, &after.reserves, &pools)?;
+        assert_eq!(crossed, vec![("USDC".to_string(), after.get_stablecoin("USDC")?.redemption_price()?)]);
+        // without its LbPair the move is left to the check_shift_price_ranges crank
+        let without: Vec<Option<&LbPair>> = vec![None; state.reserves.len()];
+        assert!(pricing::crossed_redemption_bins(&before, &after.reserves, &without)?.is_empty());
+
+        // a redemption off the subject's own circulation leaves every price where it was
+        let mut after = state.clone();
+        after.allocation_strategy = AllocationStrategy::SelfOnly;
+        after.distribute(&config, "USDT", 10_000_000)?;
+        assert!(pricing::crossed_redemption_bins(&before, &after.reserves, &pools)?.is_empty());
+
+        // a rise within the bin does not count, one past the next bin does
+        let backing = state.get_stablecoin("USDT")?.backing_reserves;
+        let mut after = state.clone();
+        after.get_mut_stablecoin("USDT")?.backing_reserves = backing + 10;
+        assert!(after.get_stablecoin("USDT")?.redemption_price()? > state.get_stablecoin("USDT")?.redemption_price()?);
+        assert!(pricing::crossed_redemption_bins(&before, &after.reserves, &pools)?.is_empty());
+        after.get_mut_stablecoin("USDT")?.backing_reserves = backing * 102 / 100;
+        assert_eq!(pricing::crossed_redemption_bins(&before, &after.reserves, &pools)?.len(), 1);
+        // with a 5% step the same rise stays within the bin
+        let mut wide_pair = lb_pair;
+        wide_pair.bin_step = 500;
+        let wide: Vec<Option<&LbPair>> = vec![Some(&wide_pair); state.reserves.len()];
+        assert!(pricing::crossed_redemption_bins(&before, &after.reserves, &wide)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve_lifecycle() -> Result<()> {
+        let config = config();
+        let mut state = init_state();
+        state.set_usd_rate(&config, PRICE_ONE, "USDT", PRICE_ONE, 1)?;
+        mint_irma(&mut state, &config, "USDT", 100_000_000, 1)?;
+        let mut usdc = StableState::new("USDC", pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), 6)?;
+        usdc.status = ReserveStatus::Pending;
+        state.add_reserve(usdc);
+        state.set_usd_rate(&config, PRICE_ONE, "USDC", PRICE_ONE, 1)?;
+
+        // a Pending reserve neither mints nor redeems, and becomes Active only once it has an LbPair
+        assert!(mint_irma(&mut state, &config, "USDC", 100_000_000, 1).is_err());
+        assert!(redeem_irma(&mut state, &config, "USDC", 100_000, 1).is_err());
+        assert!(state.set_reserve_status("USDC", ReserveStatus::Active).is_err());
+        state.get_mut_stablecoin("USDC")?.pool_id = Pubkey::new_unique();
+        assert!(state.set_reserve_status("USDC", ReserveStatus::Retired).is_err());
+        state.set_reserve_status("USDC", ReserveStatus::Active)?;
+        mint_irma(&mut state, &config, "USDC", 100_000_000, 1)?;
+
+        // paused minting still redeems and can resume
+        state.set_reserve_status("USDC", ReserveStatus::MintPaused)?;
+        assert!(mint_irma(&mut state, &config, "USDC", 100_000_000, 2).is_err());
+        redeem_irma(&mut state, &config, "USDC", 100_000, 2)?;
+        state.set_reserve_status("USDC", ReserveStatus::Active)?;
+        mint_irma(&mut state, &config, "USDC", 100_000_000, 2)?;
+
+        // winding down is one way; a Retired reserve is closed but keeps its backing and circulation
+        state.set_reserve_status("USDC", ReserveStatus::RedeemOnly)?;
+        assert!(state.set_reserve_status("USDC", ReserveStatus::Active).is_err());
+        assert!(state.set_reserve_status("USDC", ReserveStatus::MintPaused).is_err());
+        assert!(mint_irma(&mut state, &config, "USDC", 100_000_000, 3).is_err());
+        redeem_irma(&mut state, &config, "USDC", 100_000, 3)?;
+        assert!(state.remove_reserve("USDC").is_err());
+        state.set_reserve_status("USDC", ReserveStatus::Retired)?;
+        let retired = state.get_stablecoin("USDC")?;
+        assert!(redeem_irma(&mut state, &config, "USDC", 100_000, 4).is_err());
+        assert!(state.set_usd_rate(&config, PRICE_ONE, "USDC", PRICE_ONE, 4).is_err());
+        assert!(state.set_reserve_status("USDC", ReserveStatus::RedeemOnly).is_err());
+        assert!(state.remove_reserve("USDC").is_err());
+        assert_eq!(state.get_stablecoin("USDC")?, retired);
+        // a redemption elsewhere takes no circulation off it
+        state.allocation_strategy = AllocationStrategy::MaxSpread;
+        redeem_irma(&mut state, &config, "USDT", 1_000_000, 4)?;
+        assert_eq!(state.get_stablecoin("USDC")?, retired);
+
+        // only a Pending reserve can be removed
+        let mut pyusd = StableState::new("PYUSD", pubkey!("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo"), 6)?;
+        pyusd.status = ReserveStatus::Pending;
+        state.add_reserve(pyusd);
+        state.remove_reserve("PYUSD")?;
+        assert_eq!(state.list_reserves(), vec!["USDC", "USDT"]);
+
+        // legacy accounts keep active reserves Active and pause minting on inactive ones
+        let legacy_reserve = |active: bool| LegacyStableState {
+            symbol: "USDT".to_string(),
+            mint_address: pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"),
+            backing_decimals: 6,
+            mint_price: 1.0,
+            backing_reserves: 1_000,
+            irma_in_circulation: 900,
+            pool_id: Pubkey::default(),
+            active,
+            extra: [0; 15],
+        };
+        assert_eq!(legacy_reserve(true).migrate()?.status, ReserveStatus::Active);
+        assert_eq!(legacy_reserve(false).migrate()?.status, ReserveStatus::MintPaused);
+        Ok(())
+    }
+
+    #[test]
+    fn test_manage_state_owner_only() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let state = init_state();
+        assert_eq!(manage_state(&state, owner, Pubkey::new_unique()).err(), Some(error!(CustomError::Unauthorized)));
+        assert!(manage_state(&state, owner, owner).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_fee_policy() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        pricing::set_fee_policy(manage_ctx(&mut accounts), 30, FeePolicy::Treasury)?;
+        assert_eq!(accounts.state.redemption_fee_bps, 30);
+        assert_eq!(accounts.state.fee_policy, FeePolicy::Treasury);
+
+        // fees above the maximum are refused and leave the policy as it was
+        assert_eq!(
+            pricing::set_fee_policy(manage_ctx(&mut accounts), MAX_REDEMPTION_FEE_BPS + 1, FeePolicy::Backing).err(),
+            Some(error!(CustomError::InvalidAmount)));
+        assert_eq!((accounts.state.redemption_fee_bps, accounts.state.fee_policy), (30, FeePolicy::Treasury));
+        pricing::set_fee_policy(manage_ctx(&mut accounts), MAX_REDEMPTION_FEE_BPS, FeePolicy::Backing)?;
+        assert_eq!(accounts.state.redemption_fee_bps, MAX_REDEMPTION_FEE_BPS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_price_bounds() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        pricing::set_price_bounds(manage_ctx(&mut accounts), "USDT", 100, 300)?;
+        let usdt = accounts.state.get_stablecoin("USDT")?;
+        assert_eq!((usdt.max_update_change_bps, usdt.max_daily_change_bps), (100, 300));
+
+        // a zero per-update bound, a per-update bound above the daily one and a daily bound above 100% are refused
+        for (max_update, max_daily) in [(0, 300), (400, 300), (100, 10_001)] {
+            assert_eq!(
+                pricing::set_price_bounds(manage_ctx(&mut accounts), "USDT", max_update, max_daily).err(),
+                Some(error!(CustomError::InvalidAmount)));
+        }
+        assert_eq!(
+            pricing::set_price_bounds(manage_ctx(&mut accounts), "USDC", 100, 300).err(),
+            Some(error!(CustomError::InvalidQuoteToken)));
+        let usdt = accounts.state.get_stablecoin("USDT")?;
+        assert_eq!((usdt.max_update_change_bps, usdt.max_daily_change_bps), (100, 300));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_max_price_age() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        pricing::set_max_price_age(manage_ctx(&mut accounts), "USDT", 600)?;
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.max_price_age, 600);
+
+        assert_eq!(
+            pricing::set_max_price_age(manage_ctx(&mut accounts), "USDT", 0).err(),
+            Some(error!(CustomError::InvalidAmount)));
+        assert_eq!(
+            pricing::set_max_price_age(manage_ctx(&mut accounts), "USDC", 600).err(),
+            Some(error!(CustomError::InvalidQuoteToken)));
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.max_price_age, 600);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_reserve_limits() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        pricing::set_reserve_limits(manage_ctx(&mut accounts), "USDT", 5, 1_000)?;
+        let usdt = accounts.state.get_stablecoin("USDT")?;
+        assert_eq!((usdt.min_mint_amount, usdt.max_redeem_amount), (5, 1_000));
+
+        // zero falls back to the protocol-wide limits
+        pricing::set_reserve_limits(manage_ctx(&mut accounts), "USDT", 0, 0)?;
+        let usdt = accounts.state.get_stablecoin("USDT")?;
+        assert_eq!((usdt.min_mint_amount, usdt.max_redeem_amount), (0, 0));
+        assert_eq!(
+            pricing::set_reserve_limits(manage_ctx(&mut accounts), "USDC", 5, 1_000).err(),
+            Some(error!(CustomError::InvalidQuoteToken)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_redemption_caps() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        pricing::set_reserve_redemption_cap(manage_ctx(&mut accounts), "USDT", 50_000)?;
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.redeem_window_cap, 50_000);
+        assert_eq!(
+            pricing::set_reserve_redemption_cap(manage_ctx(&mut accounts), "USDC", 50_000).err(),
+            Some(error!(CustomError::InvalidQuoteToken)));
+
+        pricing::set_redemption_window(manage_ctx(&mut accounts), 3_600, 100_000)?;
+        assert_eq!((accounts.state.redeem_window, accounts.state.redeem_window_cap), (3_600, 100_000));
+        // a window must have a length
+        assert_eq!(
+            pricing::set_redemption_window(manage_ctx(&mut accounts), 0, 100_000).err(),
+            Some(error!(CustomError::InvalidAmount)));
+        assert_eq!(accounts.state.redeem_window, 3_600);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_allocation_strategy() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        assert_eq!(accounts.state.allocation_strategy, AllocationStrategy::MaxSpread);
+        allocation::set_allocation_strategy(manage_ctx(&mut accounts), AllocationStrategy::WaterFill)?;
+        assert_eq!(accounts.state.allocation_strategy, AllocationStrategy::WaterFill);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_reserve_status() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut accounts = manage_state(&init_state(), owner, owner)?;
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.status, ReserveStatus::Active);
+        pricing::disable_reserve(manage_ctx(&mut accounts), "USDT")?;
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.status, ReserveStatus::MintPaused);
+        pricing::set_reserve_status(manage_ctx(&mut accounts), "USDT", ReserveStatus::RedeemOnly)?;
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.status, ReserveStatus::RedeemOnly);
+
+        // winding down is for good, and unknown reserves are refused
+        assert_eq!(
+            pricing::set_reserve_status(manage_ctx(&mut accounts), "USDT", ReserveStatus::Active).err(),
+            Some(error!(CustomError::InvalidStatusTransition)));
+        assert_eq!(
+            pricing::disable_reserve(manage_ctx(&mut accounts), "USDC").err(),
+            Some(error!(CustomError::InvalidBacking)));
+        assert_eq!(accounts.state.get_stablecoin("USDT")?.status, ReserveStatus::RedeemOnly);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_legacy_state() -> Result<()> {
+        let legacy = LegacyStableState {
+            symbol: "USDC".to_string(),
+            mint_address: pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"),
+            backing_decimals: 8,
+            mint_price: 1.25,
+            backing_reserves: 1_000,
+            irma_in_circulation: 900,
+            pool_id: Pubkey::default(),
+            active: true,
+            extra: [0; 15],
+        };
+        let migrated = legacy.migrate()?;
+        assert_eq!(migrated.mint_price, 5 * PRICE_ONE / 4);
+        assert_eq!(migrated.backing_reserves, 1_000 * 100_000_000);
+        assert_eq!(migrated.irma_in_circulation, 900 * 1_000_000);
+        // the redemption price is unchanged by the conversion
+        assert_eq!(migrated.redemption_price()?, fixed_point::from_ratio(1_000, 900, Rounding::Down)?);
+
+        let bad = LegacyStableState { mint_price: f64::NAN, ..legacy };
+        assert!(bad.migrate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_legacy_state() -> Result<()> {
+        let legacy = LegacyStateMap {
+            reserves: vec![LegacyStableState {
+                symbol: "USDT".to_string(),
+                mint_address: pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"),
+                backing_decimals: 6,
+                mint_price: 1.0,
+                backing_reserves: 5,
+                irma_in_circulation: 4,
+                pool_id: Pubkey::default(),
+                active: true,
+                extra: [0; 15],
+            }],
+            bump: 13,
+            padding: [0; 7],
+        };
+        let mut data: Vec<u8> = StateMap::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut data)?;
+        data.resize(data.len() + 64, 0); // accounts carry trailing space
+
+        let state = migration::read_state(&data)?;
+        assert_eq!(state.bump, 13);
+        assert_eq!(state.version, irma::pricing::STATE_VERSION);
+        assert_eq!(state.redeem_window, irma::pricing::DEFAULT_REDEEM_WINDOW);
+        let usdt = state.get_stablecoin("USDT")?;
+        assert_eq!(usdt.backing_reserves, 5_000_000);
+        assert_eq!(usdt.irma_in_circulation, 4_000_000);
+        assert_eq!(usdt.usd_rate, PRICE_ONE);
+        assert_eq!(usdt.fees_collected, 0);
+        assert_eq!(usdt.price_updated_at, 0);
+
+        // a migrated account is not migrated twice
+        let mut current: Vec<u8> = Vec::new();
+        state.try_serialize(&mut current)?;
+        assert!(migration::read_state(&current).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_redemption_fee_policy() -> Result<()> {
+        let mut state = init_state();
+        {
+            let mut_reserve = state.get_mut_stablecoin("USDT").unwrap();
+            mut_reserve.backing_reserves = 1_000_000_000;
+            mut_reserve.irma_in_circulation = 1_000_000_000;
+        }
+        // default: 1 bps, kept in the backing
+        assert_eq!(state.redemption_fee_bps, 1);
+        assert_eq!(state.charge_redemption_fee("USDT", 100_000_000)?, 10_000);
+        let usdt = state.get_stablecoin("USDT")?;
+        assert_eq!(usdt.backing_reserves, 1_000_010_000);
+        assert_eq!(usdt.fees_collected, 10_000);
+        assert_eq!(usdt.treasury_fees, 0);
+
+        // treasury: the backing is untouched; the fee rounds up
+        state.redemption_fee_bps = 30;
+        state.fee_policy = FeePolicy::Treasury;
+        assert_eq!(state.charge_redemption_fee("USDT", 1_001)?, 4);
+        let usdt = state.get_stablecoin("USDT")?;
+        assert_eq!(usdt.backing_reserves, 1_000_010_000);
+        assert_eq!(usdt.fees_collected, 10_004);
+        assert_eq!(usdt.treasury_fees, 4);
+        Ok(())
+    }
+
     fn prep_accounts(owner: &'static Pubkey, state_account: Pubkey) -> 
-        (AccountInfo, AccountInfo, AccountInfo, AccountInfo)
+        (AccountInfo<'static>, AccountInfo<'static>, AccountInfo<'static>, AccountInfo<'static>)
     {
         // Create a buffer for StateMap and wrap it in AccountInfo
         let lamports: &mut u64 = Box::leak(Box::new(100000u64));
-        let mut state: StateMap = allocate_state();
+        let state: StateMap = allocate_state();
         // let _ = state.init_reserves(); // Add initial stablecoins to the state
 
         // Prepare the account data with the correct discriminator
@@ -136,9 +1503,7 @@ mod tests {
             0,
         );
         let lamportsc: &mut u64 = Box::leak(Box::new(1000000u64));
-        let mut vec = Vec::new();
-        vec.push(Pubkey::new_unique());
-        let mut core_state: Core = Core::create_core(*owner, vec).unwrap();
+        let core_state: Core = Core::create_core(*owner, vec![]).unwrap();
         let mut core_data_vec: Vec<u8> = Vec::with_capacity(std::mem::size_of::<Core>());
         core_state.try_serialize(&mut core_data_vec).unwrap();
         let core_data: &'static mut Vec<u8> = Box::leak(Box::new(core_data_vec));
@@ -189,9 +1554,9 @@ mod tests {
 
     fn initialize_anchor(program_id: &'static Pubkey) -> (
             Account<'static, StateMap>,
-            Signer,
-            Program<anchor_lang::system_program::System>,
-            Account<Core> // <'static, Core<'static>>
+            Signer<'static>,
+            Program<'static, anchor_lang::system_program::System>,
+            Account<'static, Core> // <'static, Core<'static>>
         ) {
         //                 state_account_info: &'info AccountInfo<'info>) {
         //                 sys_account_info: &AccountInfo<'info>) {
@@ -210,28 +1575,27 @@ mod tests {
             system_program: Program::try_from(sys_account_static).unwrap(),
             core: Account::try_from(core_account_static).unwrap(),
         };
-        let mut accounts_copy = Init {
+        let accounts_copy = Init {
             state: Account::try_from(state_account_static).unwrap(),
             irma_admin: Signer::try_from(irma_admin_account_static).unwrap(),
             system_program: Program::try_from(sys_account_static).unwrap(),
             core: Account::try_from(core_account_static).unwrap(),
         };
-        let mut accounts_static: &'static mut Init = Box::leak(Box::new(accounts_copy));
+        let accounts_static: &'static mut Init = Box::leak(Box::new(accounts_copy));
         let irma_admin = accounts.irma_admin.key().to_string();
-        let mut ctx: Context<Init> = Context::new(
+        let ctx: Context<Init> = Context::new(
             program_id,
             accounts_static,
             &[],
             InitBumps::default(), // Use default bumps if not needed
         );
-        let mut vec = Vec::new();
-        vec.push(Pubkey::new_unique().to_string());
-        assert_eq!(vec.len(), 1);
+        // Core starts without pair configs; they are added with add_reserve's LbPair
+        let vec: Vec<String> = Vec::new();
         let result: std::result::Result<(), Error> = irma::irma::initialize(
             ctx, irma_admin, vec);
         assert!(result.is_ok());
         // msg!("StateMap account: {:?}", accounts.state);
-        return (accounts.state, accounts.irma_admin, accounts.system_program, accounts.core);
+        (accounts.state, accounts.irma_admin, accounts.system_program, accounts.core)
     }
 
     #[test]
@@ -245,14 +1609,14 @@ mod tests {
                 = initialize_anchor(program_id);
 
         // Bind to variables to extend their lifetime
-        let mut accounts_mut = Init {
+        let accounts_mut = Init {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             system_program: sys_account.clone(),
             core: core_account.clone(),
         };
-        let mut accounts_static: &'static mut Init = Box::leak(Box::new(accounts_mut));
-        let mut ctx: Context<Init> = Context::new(
+        let accounts_static: &'static mut Init = Box::leak(Box::new(accounts_mut));
+        let ctx: Context<Init> = Context::new(
             program_id,
             accounts_static,
             &[],
@@ -260,9 +1624,8 @@ mod tests {
         );
 
         // Call the initialize function again. This should fail because the account is already initialized.
-        let mut vec = Vec::new();
-        vec.push(Pubkey::new_unique().to_string());
-        assert_eq!(vec.len(), 1);
+        // Core starts without pair configs; they are added with add_reserve's LbPair
+        let vec: Vec<String> = Vec::new();
         let result: std::result::Result<(), Error> = irma::irma::initialize(
             ctx, irma_admin_account.key().to_string(), vec);
         assert!(result.is_ok()); // not running on-chain, so it's OK?
@@ -270,119 +1633,36 @@ mod tests {
    }
 
     #[test]
-    fn test_set_mint_price_anchor() {
+    fn test_set_usd_rate_anchor() {
         msg!("\n-------------------------------------------------------------------------");
         msg!("Testing set IRMA mint price with normal conditions");  
         msg!("-------------------------------------------------------------------------");
         let program_id: &Pubkey = &IRMA_ID;
         let (state_account, irma_admin_account, sys_account, core_account)
                 = initialize_anchor(program_id);
-        // Bind to variables to extend their lifetime
-        let accounts = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_copy1 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static1: &'static mut Maint = Box::leak(Box::new(accounts_copy1));
-        let mut accounts_copy2 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static2: &'static mut Maint = Box::leak(Box::new(accounts_copy2));
-        let mut accounts_copy3 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static3: &'static mut Maint = Box::leak(Box::new(accounts_copy3));
-        let mut accounts_copy4 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static4: &'static mut Maint = Box::leak(Box::new(accounts_copy4));
-        let mut accounts_copy5 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static5: &'static mut Maint = Box::leak(Box::new(accounts_copy5));
-        let mut accounts_copy6 = Maint {
+        // every instruction works on the same accounts, so the reserves added by one are seen by the next
+        let mut accounts = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static6: &'static mut Maint = Box::leak(Box::new(accounts_copy6));
-
-        let mut ctx: Context<Maint> = Context::new(
-            program_id,
-            accounts_static1,
-            &[],
-            MaintBumps::default(),
-        );
-        let xresult = irma::irma::add_reserve(
-            ctx, "USDT".to_string(), pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        
-        let mut ctx: Context<Maint> = Context::new(
-            program_id,
-            accounts_static2,
-            &[],
-            MaintBumps::default(),
-        );
-        let xresult = irma::irma::add_reserve(
-            ctx, "USDC".to_string(), pubkey!("Es8vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        
-        let mut ctx: Context<Maint> = Context::new(
-            program_id,
-            accounts_static3,
-            &[],
-            MaintBumps::default(),
-        );
-        let xresult = irma::irma::add_reserve(
-            ctx, "FDUSD".to_string(), pubkey!("Es7vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
+        for (symbol, mint_address) in [
+            ("USDT", pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("USDC", pubkey!("Es8vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("FDUSD", pubkey!("Es7vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+        ] {
+            let ctx: Context<Maint> = Context::new(program_id, &mut accounts, &[], MaintBumps::default());
+            assert!(irma::irma::add_reserve(ctx, symbol.to_string(), mint_address, 6u8).is_ok());
+        }
 
-        let mut ctx: Context<Maint> = Context::new(
-            program_id,
-            accounts_static4,
-            &[],
-            MaintBumps::default(),
-        );
-        let mut result: std::result::Result<(), Error> = irma::irma::set_mint_price(
-            ctx, "USDT".to_string(), 1.5);
-        assert!(result.is_ok());
-        // Re-create ctx for the next call if needed
-        ctx = Context::<Maint>::new(
-            program_id,
-            accounts_static5,
-            &[],
-            MaintBumps::default(),
-        );
-        result = irma::irma::set_mint_price(ctx, "USDC".to_string(), 1.8);
-        assert!(result.is_ok());
-        ctx = Context::<Maint>::new(
-            program_id,
-            accounts_static6,
-            &[],
-            MaintBumps::default(),
-        );
-        result = irma::irma::set_mint_price(ctx, "FDUSD".to_string(), 1.3);
-        assert!(result.is_ok());
-        // msg!("Mint price for USDT set successfully: {:?}", accounts.state.mint_price["USDT" as usize]);
-        // msg!("Mint price for USDC set successfully: {:?}", accounts.state.mint_price[Stablecoins::USDC as usize]);
-        // msg!("Mint price for USDE set successfully: {:?}", accounts.state.mint_price[Stablecoins::FDUSD as usize]);
+        // mint prices derive from the inflation index, so set the USD rates
+        let index = InflationIndex::new(0);
+        assert!(accounts.state.set_usd_rate(&config(), index.index, "USDT", 3 * PRICE_ONE / 2, 0).is_ok());
+        assert!(accounts.state.set_usd_rate(&config(), index.index, "USDC", 9 * PRICE_ONE / 5, 0).is_ok());
+        assert!(accounts.state.set_usd_rate(&config(), index.index, "FDUSD", 13 * PRICE_ONE / 10, 0).is_ok());
+        assert_eq!(accounts.state.get_stablecoin("USDC").unwrap().mint_price, 9 * PRICE_ONE / 5);
     }
 
     #[test]
@@ -399,70 +1679,21 @@ mod tests {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_copy1 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static1: &'static mut Maint = Box::leak(Box::new(accounts_copy1));
-        let mut accounts_copy2 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static2: &'static mut Maint = Box::leak(Box::new(accounts_copy2));
-        let mut accounts_copy3 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static3: &'static mut Maint = Box::leak(Box::new(accounts_copy3));
-        let mut accounts_copy4 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static4: &'static mut Maint = Box::leak(Box::new(accounts_copy4));
-        let mut accounts_copy5 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static5: &'static mut Maint = Box::leak(Box::new(accounts_copy5));
-        let mut accounts_copy6 = Maint {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            core: core_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut accounts_static6: &'static mut Maint = Box::leak(Box::new(accounts_copy6));
-
         // Add all six stablecoins for testing
-        let _ = irma::irma::add_reserve(
-            Context::new(program_id, accounts_static1, &[], MaintBumps::default()),
-            "USDT".to_string(), pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        let _ = irma::irma::add_reserve(
-            Context::new(program_id, accounts_static2, &[], MaintBumps::default()),
-            "USDC".to_string(), pubkey!("Es8vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        let _ = irma::irma::add_reserve(
-            Context::new(program_id, accounts_static3, &[], MaintBumps::default()),
-            "FDUSD".to_string(), pubkey!("Es7vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        let _ = irma::irma::add_reserve(
-            Context::new(program_id, accounts_static4, &[], MaintBumps::default()),
-            "PYUSD".to_string(), pubkey!("Es6vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        let _ = irma::irma::add_reserve(
-            Context::new(program_id, accounts_static5, &[], MaintBumps::default()),
-            "USDG".to_string(), pubkey!("Es5vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
-        let _ = irma::irma::add_reserve(
-            Context::new(program_id, accounts_static6, &[], MaintBumps::default()),
-            "USDE".to_string(), pubkey!("Es4vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p"), 6u8);
+        for (symbol, mint_address) in [
+            ("USDT", pubkey!("Es9vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("USDC", pubkey!("Es8vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("FDUSD", pubkey!("Es7vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("PYUSD", pubkey!("Es6vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("USDG", pubkey!("Es5vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+            ("USDE", pubkey!("Es4vMFrzaTmVRL3P15S3BtQDvVwWZEzPDk1e45sA2v6p")),
+        ] {
+            let ctx: Context<Maint> = Context::new(program_id, &mut accounts, &[], MaintBumps::default());
+            irma::irma::add_reserve(ctx, symbol.to_string(), mint_address, 6u8)?;
+        }
 
         // make sure they're all in there
         msg!("Pre-mint IRMA state:");
@@ -485,7 +1716,7 @@ mod tests {
             &[],
             MaintBumps::default(),
         );
-        let mut result = mint_irma(&mut ctx.accounts.state, "USDT", 100);
+        let mut result = mint_irma(&mut ctx.accounts.state, &config(), "USDT", 100, 0);
         match result {
             Err(e) => {
                 msg!("Error minting IRMA for USDT: {:?}", e);
@@ -500,7 +1731,7 @@ mod tests {
             &[],
             MaintBumps::default(),
         );
-        result = mint_irma(&mut ctx.accounts.state, "PYUSD", 1000);
+        result = mint_irma(&mut ctx.accounts.state, &config(), "PYUSD", 1000, 0);
         match result {
             Err(e) => {
                 msg!("Error minting IRMA for PYUSD: {:?}", e);
@@ -515,7 +1746,7 @@ mod tests {
             &[],
             MaintBumps::default(),
         );
-        result = mint_irma(&mut ctx.accounts.state, "USDG", 10000);
+        result = mint_irma(&mut ctx.accounts.state, &config(), "USDG", 10000, 0);
         match result {
             Err(e) => {
                 msg!("Error minting IRMA for USDG: {:?}", e);
@@ -550,48 +1781,54 @@ mod tests {
         let program_id: &Pubkey = &IRMA_ID;
         let (mut state_account, irma_admin_account, sys_account, core_account) 
             = initialize_anchor(program_id);
-        let mut accounts_copy1 = Maint {
+        let accounts_copy1 = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static1: &'static mut Maint = Box::leak(Box::new(accounts_copy1));
-        let mut accounts_copy2 = Maint {
+        let accounts_static1: &'static mut Maint = Box::leak(Box::new(accounts_copy1));
+        let accounts_copy2 = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static2: &'static mut Maint = Box::leak(Box::new(accounts_copy2));
-        let mut accounts_copy3 = Maint {
+        let accounts_static2: &'static mut Maint = Box::leak(Box::new(accounts_copy2));
+        let accounts_copy3 = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static3: &'static mut Maint = Box::leak(Box::new(accounts_copy3));
-        let mut accounts_copy4 = Maint {
+        let accounts_static3: &'static mut Maint = Box::leak(Box::new(accounts_copy3));
+        let accounts_copy4 = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static4: &'static mut Maint = Box::leak(Box::new(accounts_copy4));
-        let mut accounts_copy5 = Maint {
+        let accounts_static4: &'static mut Maint = Box::leak(Box::new(accounts_copy4));
+        let accounts_copy5 = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static5: &'static mut Maint = Box::leak(Box::new(accounts_copy5));
-        let mut accounts_copy6 = Maint {
+        let accounts_static5: &'static mut Maint = Box::leak(Box::new(accounts_copy5));
+        let accounts_copy6 = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
-        let mut accounts_static6: &'static mut Maint = Box::leak(Box::new(accounts_copy6));
+        let accounts_static6: &'static mut Maint = Box::leak(Box::new(accounts_copy6));
 
         // Add all six stablecoins for testing
         let _ = irma::irma::add_reserve(
@@ -616,6 +1853,7 @@ mod tests {
         let mut accounts_mut = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
             core: core_account.clone(),
         };
@@ -645,7 +1883,7 @@ mod tests {
         // msg!("Current prices: {:?}", accounts.state.mint_price);
         // msg!("Backing reserves: {:?}", accounts.state.backing_reserves);
         // msg!("IRMA in circulation: {:?}", accounts.state.irma_in_circulation);
-        let mut result: std::result::Result<(), Error> = redeem_irma(&mut state_account.clone(), "USDC", 10);
+        let mut result: std::result::Result<(), Error> = redeem_irma(&mut state_account.clone(), &config(), "USDC", 10, 0);
         match result {
             Err(e) => {
                 msg!("Error redeeming IRMA for USDC: {:?}", e);
@@ -655,7 +1893,7 @@ mod tests {
             }
         }
         // assert!(result.is_ok(), "Redeem IRMA failed for USDC");
-        result = redeem_irma(&mut state_account.clone(), "USDT", 20);
+        result = redeem_irma(&mut state_account.clone(), &config(), "USDT", 20, 0);
         match result {
             Err(e) => {
                 msg!("Error redeeming IRMA for USDT: {:?}", e);
@@ -664,7 +1902,7 @@ mod tests {
                 msg!("Redeem IRMA successful for USDT");
             }
         }
-        result = redeem_irma(&mut state_account.clone(), "PYUSD", 30);
+        result = redeem_irma(&mut state_account.clone(), &config(), "PYUSD", 30, 0);
         match result {
             Err(e) => {
                 msg!("Error redeeming IRMA for PYUSD: {:?}", e);
@@ -673,7 +1911,7 @@ mod tests {
                 msg!("Redeem IRMA successful for PYUSD");
             }
         }
-        result = redeem_irma(&mut state_account.clone(), "USDG", 40);
+        result = redeem_irma(&mut state_account.clone(), &config(), "USDG", 40, 0);
         match result {
             Err(e) => {
                 msg!("Error redeeming IRMA for USDG: {:?}", e);
@@ -682,7 +1920,7 @@ mod tests {
                 msg!("Redeem IRMA successful for USDG");
             }
         }
-        result = redeem_irma(&mut state_account.clone(), "FDUSD", 50);
+        result = redeem_irma(&mut state_account.clone(), &config(), "FDUSD", 50, 0);
         match result {
             Err(e) => {
                 msg!("Error redeeming IRMA for FDUSD: {:?}", e);
@@ -692,13 +1930,14 @@ mod tests {
             }
         }
 
-        let mut accounts_mut = Maint {
+        let accounts_mut = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
             core: core_account.clone(),
         };
-        let mut accounts_static: &mut Maint = Box::leak(Box::new(accounts_mut));
+        let accounts_static: &mut Maint = Box::leak(Box::new(accounts_mut));
         let ctx = Context::<Maint>::new(
             program_id,
             accounts_static,
@@ -711,13 +1950,14 @@ mod tests {
         
         // mint IRMA for USDT
         
-        let mut accounts_mut = Maint {
+        let accounts_mut = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
             core: core_account.clone(),
         };
-        let mut accounts_static: &mut Maint = Box::leak(Box::new(accounts_mut));
+        let accounts_static: &mut Maint = Box::leak(Box::new(accounts_mut));
         let ctx = Context::<Maint>::new(
             program_id,
             accounts_static,
@@ -726,16 +1966,25 @@ mod tests {
         );
         // pub fn sale_trade_event(ctx: Context<Maint>, bought_token: String, bought_amount: u64) -> Result<()> {
         result = irma::irma::sale_trade_event(ctx, "USDT".to_string(), 50_000_000);
+        match result {
+            Err(e) => {
+                msg!("Error minting IRMA for USDT: {:?}", e);
+            },
+            Ok(_) => {
+                msg!("Mint IRMA successful for USDT");
+            }
+        }
 
         // Test for near maximum redemption
         
-        let mut accounts_mut = Maint {
+        let accounts_mut = Maint {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
             core: core_account.clone(),
         };
-        let mut accounts_static: &mut Maint = Box::leak(Box::new(accounts_mut));
+        let accounts_static: &mut Maint = Box::leak(Box::new(accounts_mut));
         let ctx = Context::<Maint>::new(
             program_id,
             accounts_static,
@@ -751,7 +2000,7 @@ mod tests {
                 msg!("Redeem IRMA successful for USDT");
             }
         }
-        result = redeem_irma(&mut state_account.clone(), "USDS", 10);
+        result = redeem_irma(&mut state_account.clone(), &config(), "USDS", 10, 0);
         match result {
             Err(e) => {
                 msg!("Error redeeming IRMA for USDS: {:?}", e);
@@ -779,6 +2028,7 @@ mod tests {
             state: state_account.clone(),
             irma_admin: irma_admin_account.clone(),
             core: core_account.clone(),
+            protocol_config: protocol_config_account(),
             system_program: sys_account.clone(),
         };
         {
@@ -802,10 +2052,10 @@ mod tests {
                 let mut_backing = state.get_mut_stablecoin(&sc.symbol).unwrap();
                 let reserve: &mut u128 = &mut mut_backing.backing_reserves;
                 let circulation: &mut u128 = &mut mut_backing.irma_in_circulation;
-                let price: &mut f64 = &mut mut_backing.mint_price;
+                let price: &mut u128 = &mut mut_backing.mint_price;
                 *reserve = 9_900_000_000; // Set a large reserve for testing
                 *circulation = 10_000_000_000; // Set a large IRMA in circulation for testing
-                *price = (i as f64 + 1.0) * (i as f64 + 1.0); // Set a price for testing
+                *price = (i as u128 + 1) * (i as u128 + 1) * PRICE_ONE; // Set a price for testing
                 i += 1;
             }
         }
@@ -822,7 +2072,7 @@ mod tests {
         // Test for near maximum redemption, multiple times, until it fails.
         // What we expect is that these repeated redemptions will equalize the differences between
         // mint prices and redemptions prices for all stablecoins.
-        let mut reslt = redeem_irma(&mut ctx.accounts.state, "FDUSD", 100_000_000_000);
+        let mut reslt = redeem_irma(&mut ctx.accounts.state, &config(), "FDUSD", 100_000_000_000, 0);
         while reslt.is_ok() {
             ctx = Context::<Maint>::new(
                 program_id,
@@ -830,7 +2080,7 @@ mod tests {
                 &[],
                 MaintBumps::default(),
             );
-            reslt = redeem_irma(&mut ctx.accounts.state, "FDUSD", 100_000_000_000);
+            reslt = redeem_irma(&mut ctx.accounts.state, &config(), "FDUSD", 100_000_000_000, 0);
             match reslt {
                 Err(e) => {
                     msg!("Error redeeming IRMA for USDT: {:?}", e);
@@ -842,7 +2092,7 @@ mod tests {
             }
 
             // Print the current state after every ten redemptions
-            if count % 10 == 0 {
+            if count.is_multiple_of(10) {
                 let reserves = &accounts.state.reserves;
                 for sc in reserves {
                     let backing: u128 = sc.backing_reserves;
@@ -850,7 +2100,7 @@ mod tests {
                     let redemption_price: f64 = backing as f64 / circulation as f64;
                     msg!("{}, {:.3}, {}, {}, {:.3}", 
                         sc.symbol, 
-                        sc.mint_price, 
+                        sc.mint_price as f64 / PRICE_ONE as f64, 
                         backing,
                         circulation,
                         redemption_price);